@@ -1,10 +1,20 @@
-use crate::types::{Exchange, Pair};
+use crate::types::{Exchange, Pair, Price};
 
 use web3::ethabi::Address;
-use web3::types::U256;
+use web3::types::{H256, U256};
 
-#[derive(Debug)]
+/// Position of an event log in the chain.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct LogMetadata {
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub log_index: U256,
+}
+
+#[derive(Clone, Debug)]
 pub struct PositionWasOpened {
+    pub log: LogMetadata,
+    pub strategy: Address,
     pub id: U256,
     pub owner: Address,
     pub owed_token: Address,
@@ -17,40 +27,103 @@ pub struct PositionWasOpened {
     pub created_at: U256,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PositionWasClosed {
+    pub log: LogMetadata,
+    pub strategy: Address,
     pub id: U256,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PositionWasLiquidated {
+    pub log: LogMetadata,
+    pub strategy: Address,
     pub id: U256,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct RiskFactorWasUpdated {
+    pub log: LogMetadata,
+    pub strategy: Address,
     pub token: Address,
     pub new_risk_factor: U256,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Ticker {
     pub exchange: Exchange,
     pub pair: Pair,
-    pub price: f64,
+    pub price: Price,
+    /// Unix time at which the exchange published the price.
+    pub timestamp: u64,
 }
 
 #[derive(Clone, Debug)]
 pub struct BlockHeader {
+    pub number: u64,
+    pub hash: H256,
+    pub parent_hash: H256,
     pub timestamp: U256,
+    /// Base fee of the block, on networks which have one.
+    pub base_fee_per_gas: Option<U256>,
+}
+
+/// Blocks which are no longer part of the canonical chain after a reorganisation.
+#[derive(Clone, Debug)]
+pub struct ChainReorganized {
+    pub removed_blocks: Vec<H256>,
+}
+
+/// A liquidation transaction which was mined successfully.
+#[derive(Clone, Debug)]
+pub struct LiquidationSucceeded {
+    pub strategy: Address,
+    pub position_id: U256,
+    pub transaction_hash: H256,
+}
+
+/// A liquidation which was not sent, e.g. because it would revert, or whose transaction reverted.
+#[derive(Clone, Debug)]
+pub struct LiquidationFailed {
+    pub strategy: Address,
+    pub position_id: U256,
+    pub reason: String,
 }
 
-#[derive(Debug)]
+/// A liquidation transaction which was not mined in time and was cancelled or dropped.
+#[derive(Clone, Debug)]
+pub struct LiquidationTimedOut {
+    pub strategy: Address,
+    pub position_id: U256,
+}
+
+#[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Event {
     BlockHeader(BlockHeader),
+    ChainReorganized(ChainReorganized),
+    LogWasRemoved(LogMetadata),
     PositionWasOpened(PositionWasOpened),
     PositionWasClosed(PositionWasClosed),
     PositionWasLiquidated(PositionWasLiquidated),
     RiskFactorWasUpdated(RiskFactorWasUpdated),
+    /// Every log of the Ithil contracts up to this block included has been sent.
+    LogsSynced(u64),
     Ticker(Ticker),
+    LiquidationSucceeded(LiquidationSucceeded),
+    LiquidationFailed(LiquidationFailed),
+    LiquidationTimedOut(LiquidationTimedOut),
+}
+
+impl Event {
+    /// Returns the log the event was decoded from, for events emitted by contracts.
+    pub fn log(&self) -> Option<&LogMetadata> {
+        match self {
+            Event::PositionWasOpened(event) => Some(&event.log),
+            Event::PositionWasClosed(event) => Some(&event.log),
+            Event::PositionWasLiquidated(event) => Some(&event.log),
+            Event::RiskFactorWasUpdated(event) => Some(&event.log),
+            _ => None,
+        }
+    }
 }
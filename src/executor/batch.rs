@@ -0,0 +1,122 @@
+//! Liquidation of several positions of a strategy in a single transaction.
+//!
+//! When prices crash, many positions become liquidable at once: they are grouped per strategy
+//! for a short window and liquidated together through a batch function of the `Liquidator`
+//! contract, each liquidation being allowed to fail on its own.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use web3::ethabi::{self, Token};
+use web3::types::{Address, Bytes, TransactionReceipt, H256, U256};
+
+use crate::types::Liquidation;
+
+#[derive(Clone, Debug)]
+pub struct Configuration {
+    /// Time during which the liquidations of a strategy are grouped.
+    pub window: Duration,
+    pub max_size: usize,
+    /// Function of the `Liquidator` taking a strategy and an array of position ids, if any.
+    pub liquidator_function: Option<String>,
+}
+
+/// Batch function of the `Liquidator`.
+#[derive(Clone, Debug)]
+pub struct BatchCall(ethabi::Function);
+
+impl BatchCall {
+    /// Picks the batch function of `liquidator_abi` if it has the configured one, or `None` to
+    /// liquidate positions one by one.
+    pub fn new(configuration: &Configuration, liquidator_abi: &ethabi::Contract) -> Option<Self> {
+        let name = configuration.liquidator_function.as_ref()?;
+        match liquidator_abi.function(name) {
+            Ok(function) => Some(BatchCall(function.clone())),
+            Err(_) => {
+                println!("Liquidator has no {} function", name);
+                None
+            }
+        }
+    }
+
+    /// Returns the data of the call to the `Liquidator` liquidating `position_ids` of `strategy`.
+    pub fn encode(&self, strategy: Address, position_ids: &[U256]) -> Bytes {
+        let ids = position_ids.iter().map(|id| Token::Uint(*id)).collect();
+        let data = self
+            .0
+            .encode_input(&[Token::Address(strategy), Token::Array(ids)])
+            .unwrap();
+        Bytes(data)
+    }
+}
+
+/// Liquidations waiting for their batch to be sent, per strategy.
+pub struct Batcher {
+    window: Duration,
+    max_size: usize,
+    batches: HashMap<Address, (Instant, Vec<Liquidation>)>,
+}
+
+impl Batcher {
+    pub fn new(window: Duration, max_size: usize) -> Self {
+        Self {
+            window,
+            max_size,
+            batches: HashMap::new(),
+        }
+    }
+
+    /// Adds a liquidation, returning its batch right away if it is full.
+    pub fn push(&mut self, liquidation: Liquidation, now: Instant) -> Option<Vec<Liquidation>> {
+        let strategy = liquidation.strategy;
+        let (_, batch) = self
+            .batches
+            .entry(strategy)
+            .or_insert_with(|| (now + self.window, vec![]));
+        batch.push(liquidation);
+
+        if batch.len() >= self.max_size {
+            self.batches.remove(&strategy).map(|(_, batch)| batch)
+        } else {
+            None
+        }
+    }
+
+    /// Time when the next batch is due.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.batches.values().map(|(deadline, _)| *deadline).min()
+    }
+
+    /// Removes and returns the batches which are due at `now`.
+    pub fn take_due(&mut self, now: Instant) -> Vec<Vec<Liquidation>> {
+        let due: Vec<Address> = self
+            .batches
+            .iter()
+            .filter(|(_, (deadline, _))| *deadline <= now)
+            .map(|(strategy, _)| *strategy)
+            .collect();
+
+        due.iter()
+            .filter_map(|strategy| self.batches.remove(strategy))
+            .map(|(_, batch)| batch)
+            .collect()
+    }
+}
+
+/// Positions liquidated by the transaction of `receipt`, according to the `PositionWasLiquidated`
+/// logs of the strategies, whose signature is `position_was_liquidated`.
+pub fn liquidated_positions(
+    receipt: &TransactionReceipt,
+    position_was_liquidated: H256,
+) -> Vec<(Address, U256)> {
+    receipt
+        .logs
+        .iter()
+        .filter(|log| log.topics.first() == Some(&position_was_liquidated))
+        .filter_map(|log| {
+            let id = log.topics.get(1)?;
+            Some((log.address, U256::from_big_endian(id.as_bytes())))
+        })
+        .collect()
+}
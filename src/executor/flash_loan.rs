@@ -0,0 +1,144 @@
+//! Liquidations paid with a flash loan, for when the liquidation account does not hold the debt of
+//! the position.
+//!
+//! The debt is borrowed from an Aave pool or a Balancer vault, which hands it to a receiver
+//! contract. The receiver runs the liquidation encoded in the callback data of the loan, sells
+//! what it gets and pays the loan back with its fee, all in the same transaction.
+
+use serde::Deserialize;
+use web3::api::Eth;
+use web3::contract::{Contract, Options};
+use web3::ethabi::{self, Param, ParamType, StateMutability, Token};
+use web3::transports::WebSocket;
+use web3::types::{Address, Bytes, U256};
+
+use crate::types::RequiredFunds;
+
+const BALANCE_OF_ABI: &str = r#"[
+    {"type": "function", "name": "balanceOf", "inputs": [{"name": "account", "type": "address"}], "outputs": [{"name": "", "type": "uint256"}], "stateMutability": "view"}
+]"#;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Lender {
+    Aave,
+    Balancer,
+}
+
+#[derive(Clone, Debug)]
+pub struct Configuration {
+    pub lender: Lender,
+    /// Address of the Aave pool or of the Balancer vault.
+    pub lender_address: Address,
+    /// Contract receiving the loan, which makes the call encoded in its callback data as
+    /// `(address target, bytes data)` and pays the loan back.
+    pub receiver_address: Address,
+    /// Minimum net profit of a liquidation in wei once the fee of the loan is paid.
+    pub min_profit: U256,
+}
+
+pub struct FlashLoan {
+    lender: Lender,
+    lender_address: Address,
+    receiver_address: Address,
+    function: ethabi::Function,
+    min_profit: U256,
+}
+
+impl FlashLoan {
+    pub fn new(configuration: &Configuration) -> Self {
+        Self {
+            lender: configuration.lender,
+            lender_address: configuration.lender_address,
+            receiver_address: configuration.receiver_address,
+            function: lender_function(configuration.lender),
+            min_profit: configuration.min_profit,
+        }
+    }
+
+    pub fn min_profit(&self) -> U256 {
+        self.min_profit
+    }
+
+    /// Returns the address to call and the data to borrow `funds` and call `target` with `data`
+    /// once they are received.
+    pub fn encode(&self, funds: &RequiredFunds, target: Address, data: &Bytes) -> (Address, Bytes) {
+        let callback_data = ethabi::encode(&[Token::Address(target), Token::Bytes(data.0.clone())]);
+        let receiver = Token::Address(self.receiver_address);
+
+        let arguments = match self.lender {
+            Lender::Aave => vec![
+                receiver,
+                Token::Address(funds.token),
+                Token::Uint(funds.amount),
+                Token::Bytes(callback_data),
+                Token::Uint(U256::zero()),
+            ],
+            Lender::Balancer => vec![
+                receiver,
+                Token::Array(vec![Token::Address(funds.token)]),
+                Token::Array(vec![Token::Uint(funds.amount)]),
+                Token::Bytes(callback_data),
+            ],
+        };
+
+        (
+            self.lender_address,
+            Bytes(self.function.encode_input(&arguments).unwrap()),
+        )
+    }
+}
+
+/// Balance of `account` in `token`.
+/// `flashLoanSimple` of the Aave V3 pool or `flashLoan` of the Balancer vault.
+fn lender_function(lender: Lender) -> ethabi::Function {
+    let (name, inputs) = match lender {
+        Lender::Aave => (
+            "flashLoanSimple",
+            vec![
+                ("receiverAddress", ParamType::Address),
+                ("asset", ParamType::Address),
+                ("amount", ParamType::Uint(256)),
+                ("params", ParamType::Bytes),
+                ("referralCode", ParamType::Uint(16)),
+            ],
+        ),
+        Lender::Balancer => (
+            "flashLoan",
+            vec![
+                ("recipient", ParamType::Address),
+                ("tokens", ParamType::Array(Box::new(ParamType::Address))),
+                ("amounts", ParamType::Array(Box::new(ParamType::Uint(256)))),
+                ("userData", ParamType::Bytes),
+            ],
+        ),
+    };
+
+    #[allow(deprecated)]
+    ethabi::Function {
+        name: String::from(name),
+        inputs: inputs
+            .into_iter()
+            .map(|(name, kind)| Param {
+                name: String::from(name),
+                kind,
+                internal_type: None,
+            })
+            .collect(),
+        outputs: vec![],
+        constant: false,
+        state_mutability: StateMutability::NonPayable,
+    }
+}
+
+pub async fn balance(
+    eth: Eth<WebSocket>,
+    token: Address,
+    account: Address,
+) -> web3::contract::Result<U256> {
+    let contract = Contract::from_json(eth, token, BALANCE_OF_ABI.as_bytes()).unwrap();
+
+    contract
+        .query("balanceOf", account, None, Options::default(), None)
+        .await
+}
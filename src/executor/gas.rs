@@ -0,0 +1,347 @@
+//! Gas limits and fees of liquidation transactions.
+//!
+//! Fees follow the recent base fees and priority fees of the network, with more headroom the more
+//! urgent liquidations are, and are capped so that gas never eats more than a share of the reward
+//! of the liquidation.
+
+use std::fmt;
+
+use serde::Deserialize;
+use web3::api::Eth;
+use web3::transports::WebSocket;
+use web3::types::{BlockNumber, CallRequest, FeeHistory, TransactionParameters, U256, U64};
+
+use crate::vault_math::RESOLUTION;
+
+const EIP1559_TRANSACTION_TYPE: u64 = 2;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionType {
+    /// A single gas price, for networks without EIP-1559.
+    Legacy,
+    Eip1559,
+}
+
+/// How quickly liquidations should be included, at the expense of higher fees.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Medium,
+    High,
+}
+
+impl Urgency {
+    /// Percentile of the priority fees paid in recent blocks to match.
+    pub fn priority_fee_percentile(&self) -> f64 {
+        match self {
+            Urgency::Low => 10.0,
+            Urgency::Medium => 50.0,
+            Urgency::High => 90.0,
+        }
+    }
+
+    /// Maximum base fee relative to the next one, in basis points. The base fee rises by at most
+    /// 12.5% per block, so 15000 covers three full blocks in a row.
+    pub fn base_fee_multiplier(&self) -> u32 {
+        match self {
+            Urgency::Low => 11250,
+            Urgency::Medium => 15000,
+            Urgency::High => 20000,
+        }
+    }
+
+    /// Premium over the gas price of the node for legacy transactions, in basis points.
+    pub fn gas_price_premium(&self) -> u32 {
+        match self {
+            Urgency::Low => 0,
+            Urgency::Medium => 1000,
+            Urgency::High => 2500,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Configuration {
+    pub transaction_type: TransactionType,
+    pub urgency: Urgency,
+    /// Number of recent blocks whose fees are considered.
+    pub fee_history_blocks: u64,
+    /// Margin added to gas estimates, in basis points.
+    pub gas_limit_margin: u32,
+    /// Maximum share of the expected reward of a liquidation spent on gas, in basis points.
+    pub max_reward_share: u32,
+    /// Fee increase of replacement transactions, in basis points.
+    pub replacement_fee_bump: u32,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            transaction_type: TransactionType::Eip1559,
+            urgency: Urgency::Medium,
+            fee_history_blocks: 10,
+            gas_limit_margin: 2000,
+            max_reward_share: 5000,
+            replacement_fee_bump: 1250,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fees {
+    Legacy {
+        gas_price: U256,
+    },
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+}
+
+impl Fees {
+    /// Highest price paid for a unit of gas.
+    pub fn max_fee_per_gas(&self) -> U256 {
+        match self {
+            Fees::Legacy { gas_price } => *gas_price,
+            Fees::Eip1559 {
+                max_fee_per_gas, ..
+            } => *max_fee_per_gas,
+        }
+    }
+
+    /// The same fees raised by `bump` basis points, to replace a pending transaction. Nodes only
+    /// accept replacements paying at least 10% more.
+    pub fn bumped(&self, bump: u32) -> Fees {
+        let bumped = |fee: &U256| fee.saturating_add(apply_bps(*fee, bump));
+        match self {
+            Fees::Legacy { gas_price } => Fees::Legacy {
+                gas_price: bumped(gas_price),
+            },
+            Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => Fees::Eip1559 {
+                max_fee_per_gas: bumped(max_fee_per_gas),
+                max_priority_fee_per_gas: bumped(max_priority_fee_per_gas),
+            },
+        }
+    }
+
+    /// The same fees, lowered to at most `max_fee_per_gas`.
+    fn capped(&self, max_fee_per_gas: U256) -> Fees {
+        match self {
+            Fees::Legacy { gas_price } => Fees::Legacy {
+                gas_price: (*gas_price).min(max_fee_per_gas),
+            },
+            Fees::Eip1559 {
+                max_fee_per_gas: fee,
+                max_priority_fee_per_gas: priority_fee,
+            } => Fees::Eip1559 {
+                max_fee_per_gas: (*fee).min(max_fee_per_gas),
+                max_priority_fee_per_gas: (*priority_fee).min(max_fee_per_gas),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GasPlan {
+    pub gas_limit: U256,
+    pub fees: Fees,
+}
+
+impl GasPlan {
+    /// Cost of the transaction if it used its whole gas limit at the highest price.
+    pub fn max_cost(&self) -> U256 {
+        self.gas_limit.saturating_mul(self.fees.max_fee_per_gas())
+    }
+
+    /// Sets the gas limit, fees and type of `transaction`.
+    pub fn apply(&self, transaction: &mut TransactionParameters) {
+        transaction.gas = self.gas_limit;
+        match self.fees {
+            Fees::Legacy { gas_price } => {
+                transaction.transaction_type = None;
+                transaction.gas_price = Some(gas_price);
+            }
+            Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                transaction.transaction_type = Some(U64::from(EIP1559_TRANSACTION_TYPE));
+                transaction.max_fee_per_gas = Some(max_fee_per_gas);
+                transaction.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GasError {
+    Web3(web3::Error),
+    /// Even the lowest fees the network accepts would cost more than the budget.
+    Unprofitable {
+        gas_limit: U256,
+        min_fee_per_gas: U256,
+        budget: U256,
+    },
+}
+
+impl fmt::Display for GasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GasError::Web3(error) => write!(f, "{}", error),
+            GasError::Unprofitable {
+                gas_limit,
+                min_fee_per_gas,
+                budget,
+            } => write!(
+                f,
+                "{} gas at {} wei would cost more than {} wei",
+                gas_limit, min_fee_per_gas, budget
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GasError {}
+
+impl From<web3::Error> for GasError {
+    fn from(error: web3::Error) -> Self {
+        GasError::Web3(error)
+    }
+}
+
+/// `value * bps / 10000`.
+pub fn apply_bps(value: U256, bps: u32) -> U256 {
+    value.saturating_mul(U256::from(bps)) / U256::from(RESOLUTION)
+}
+
+/// Adds the safety margin to a gas estimate.
+pub fn gas_limit(estimate: U256, margin: u32) -> U256 {
+    estimate.saturating_add(apply_bps(estimate, margin))
+}
+
+/// Fees of a legacy transaction, from the gas price suggested by the node.
+pub fn legacy_fees(gas_price: U256, urgency: Urgency) -> Fees {
+    Fees::Legacy {
+        gas_price: gas_price.saturating_add(apply_bps(gas_price, urgency.gas_price_premium())),
+    }
+}
+
+/// Fees of a type-2 transaction, from an `eth_feeHistory` queried with the priority fee
+/// percentile of `urgency`: the median of the recent priority fees at that percentile on top of
+/// the next base fee with the headroom of `urgency`.
+pub fn eip1559_fees(fee_history: &FeeHistory, urgency: Urgency) -> Fees {
+    let max_priority_fee_per_gas = median_priority_fee(fee_history);
+
+    Fees::Eip1559 {
+        max_fee_per_gas: apply_bps(next_base_fee(fee_history), urgency.base_fee_multiplier())
+            .saturating_add(max_priority_fee_per_gas),
+        max_priority_fee_per_gas,
+    }
+}
+
+/// Base fee of the block after the newest one of the history.
+pub fn next_base_fee(fee_history: &FeeHistory) -> U256 {
+    fee_history
+        .base_fee_per_gas
+        .last()
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn median_priority_fee(fee_history: &FeeHistory) -> U256 {
+    // Empty blocks report zero priority fees, which say nothing about the competition.
+    let mut priority_fees: Vec<U256> = fee_history
+        .reward
+        .iter()
+        .flatten()
+        .filter_map(|rewards| rewards.first().cloned())
+        .filter(|reward| !reward.is_zero())
+        .collect();
+    priority_fees.sort();
+
+    priority_fees
+        .get(priority_fees.len() / 2)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Lowers the fees of `plan` so that it costs at most `budget`, failing if this would take them
+/// below `min_fee_per_gas`, the lowest price at which the transaction can be included.
+pub fn cap_fees(plan: GasPlan, budget: U256, min_fee_per_gas: U256) -> Result<GasPlan, GasError> {
+    if plan.gas_limit.is_zero() || plan.max_cost() <= budget {
+        return Ok(plan);
+    }
+
+    let max_fee_per_gas = budget / plan.gas_limit;
+    if max_fee_per_gas < min_fee_per_gas {
+        return Err(GasError::Unprofitable {
+            gas_limit: plan.gas_limit,
+            min_fee_per_gas,
+            budget,
+        });
+    }
+
+    Ok(GasPlan {
+        fees: plan.fees.capped(max_fee_per_gas),
+        ..plan
+    })
+}
+
+pub struct GasStrategy {
+    configuration: Configuration,
+    eth: Eth<WebSocket>,
+}
+
+impl GasStrategy {
+    pub fn new(configuration: Configuration, eth: Eth<WebSocket>) -> Self {
+        Self { configuration, eth }
+    }
+
+    /// Estimates the gas of `call` and chooses its fees, spending at most a share of
+    /// `expected_reward` (in wei) on gas when it is known.
+    pub async fn plan(
+        &self,
+        call: CallRequest,
+        expected_reward: Option<U256>,
+    ) -> Result<GasPlan, GasError> {
+        let estimate = self.eth.estimate_gas(call, None).await?;
+        let gas_limit = gas_limit(estimate, self.configuration.gas_limit_margin);
+        let urgency = self.configuration.urgency;
+
+        let (fees, min_fee_per_gas) = match self.configuration.transaction_type {
+            TransactionType::Legacy => {
+                let gas_price = self.eth.gas_price().await?;
+                (legacy_fees(gas_price, urgency), gas_price)
+            }
+            TransactionType::Eip1559 => {
+                let fee_history = self
+                    .eth
+                    .fee_history(
+                        U256::from(self.configuration.fee_history_blocks),
+                        BlockNumber::Latest,
+                        Some(vec![urgency.priority_fee_percentile()]),
+                    )
+                    .await?;
+                (
+                    eip1559_fees(&fee_history, urgency),
+                    next_base_fee(&fee_history),
+                )
+            }
+        };
+
+        let plan = GasPlan { gas_limit, fees };
+        match expected_reward {
+            Some(reward) => cap_fees(
+                plan,
+                apply_bps(reward, self.configuration.max_reward_share),
+                min_fee_per_gas,
+            ),
+            None => Ok(plan),
+        }
+    }
+}
@@ -0,0 +1,905 @@
+//! Sends the liquidation transactions decided by the liquidator.
+
+pub mod batch;
+pub mod flash_loan;
+pub mod gas;
+pub mod nonce;
+pub mod relay;
+pub mod signer;
+pub mod simulation;
+pub mod wallets;
+
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
+
+use num_bigint::BigInt;
+
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::{self, Instant};
+
+use web3::contract::tokens::Tokenize;
+use web3::ethabi;
+use web3::transports::WebSocket;
+use web3::types::{
+    Address, BlockNumber, Bytes, CallRequest, TransactionParameters, TransactionReceipt, H256,
+    U256, U64,
+};
+use web3::Web3;
+
+use crate::events::{Event, LiquidationFailed, LiquidationSucceeded, LiquidationTimedOut};
+use crate::feeds::ithil;
+use crate::feeds::supervisor::HealthRegistry;
+use crate::metrics::Metrics;
+use crate::profitability;
+use crate::readiness;
+use crate::types::{Liquidation, RequiredFunds};
+use crate::vault_math;
+use batch::{BatchCall, Batcher};
+use flash_loan::FlashLoan;
+use gas::{GasPlan, GasStrategy};
+use nonce::{PendingTransaction, PrivateTransaction, Replacement};
+use relay::Relay;
+use signer::SignedTransaction;
+use wallets::{Wallet, WalletPool};
+
+pub struct Configuration {
+    pub ethereum_provider_wss_url: String,
+    pub liquidator_address: String,
+    pub liquidator_abi_path: String,
+    /// Accounts sending the liquidations.
+    pub wallets: wallets::Configuration,
+    pub gas: gas::Configuration,
+    /// Delay between two checks of the pending transactions.
+    pub poll_interval: Duration,
+    /// Number of blocks after which a pending transaction is replaced.
+    pub stuck_blocks: u64,
+    /// Number of times a liquidation is sped up before being cancelled, and its cancellation
+    /// before being reported stuck.
+    pub max_speed_ups: u32,
+    pub batch: batch::Configuration,
+    /// Relay receiving liquidations privately, if any, instead of the public mempool.
+    pub relay: Option<relay::Configuration>,
+    /// Lender of the debt of positions when the account does not hold it, if any.
+    pub flash_loan: Option<flash_loan::Configuration>,
+}
+
+// Gas of a transfer, i.e. of a cancellation.
+const TRANSFER_GAS: u64 = 21000;
+
+impl Tokenize for Liquidation {
+    fn into_tokens(self) -> Vec<ethabi::Token> {
+        vec![
+            ethabi::Token::Address(self.strategy),
+            ethabi::Token::Uint(self.position_id),
+        ]
+    }
+}
+
+/// Sends the liquidations received on `liquidation_rx`, reporting those which cannot be sent on
+/// `event_tx`, and whether the node and the accounts can be relied upon to `health`.
+pub async fn liquidate_positions(
+    mut liquidation_rx: Receiver<Liquidation>,
+    event_tx: Sender<Event>,
+    metrics: Metrics,
+    health: HealthRegistry,
+    configuration: &Configuration,
+) -> web3::Result {
+    let ws = WebSocket::new(&configuration.ethereum_provider_wss_url).await?;
+    let web3 = Web3::new(ws);
+    let mut executor = Executor::new(web3, event_tx, metrics, configuration).await?;
+    executor.report_balances(&health);
+
+    // Liquidations are sent as soon as their batch is due, pending transactions are checked in
+    // between.
+    let mut poll_interval = time::interval(configuration.poll_interval);
+    loop {
+        let deadline = executor.batcher.deadline();
+        tokio::select! {
+            liquidation = liquidation_rx.recv() => match liquidation {
+                Some(liquidation) => executor.queue(liquidation).await,
+                None => break,
+            },
+            _ = sleep_until(deadline) => {
+                for batch in executor.batcher.take_due(Instant::now()) {
+                    executor.liquidate(batch).await;
+                }
+            }
+            _ = poll_interval.tick() => {
+                match executor.check_pending_transactions().await {
+                    Ok(()) => health.set_component(
+                        readiness::EXECUTOR,
+                        true,
+                        String::from("pending transactions checked"),
+                    ),
+                    Err(error) => {
+                        println!("Cannot check pending transactions: {}", error);
+                        health.set_component(readiness::EXECUTOR, false, error.to_string());
+                    }
+                }
+                executor.report_balances(&health);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// Outcomes of the liquidations whose nonce was used by the transaction of `receipt`, if any.
+///
+/// Batches may succeed with some of their liquidations failing, so each position of a batch is
+/// looked up in the `PositionWasLiquidated` logs, whose signature is `position_was_liquidated`.
+pub fn outcomes(
+    pending: &PendingTransaction,
+    receipt: Option<&TransactionReceipt>,
+    position_was_liquidated: H256,
+) -> Vec<Event> {
+    let receipt = match receipt {
+        Some(receipt) if pending.hashes.contains(&receipt.transaction_hash) => receipt,
+        // The liquidation was cancelled, or its nonce used by another transaction of the account.
+        _ => {
+            return pending
+                .positions
+                .iter()
+                .map(|(strategy, position_id)| {
+                    Event::LiquidationTimedOut(LiquidationTimedOut {
+                        strategy: *strategy,
+                        position_id: *position_id,
+                    })
+                })
+                .collect()
+        }
+    };
+
+    let succeeded = receipt.status == Some(U64::one());
+    let liquidated = match pending.positions.len() {
+        1 => pending.positions.clone(),
+        _ => batch::liquidated_positions(receipt, position_was_liquidated),
+    };
+    pending
+        .positions
+        .iter()
+        .map(|(strategy, position_id)| {
+            if succeeded && liquidated.contains(&(*strategy, *position_id)) {
+                Event::LiquidationSucceeded(LiquidationSucceeded {
+                    strategy: *strategy,
+                    position_id: *position_id,
+                    transaction_hash: receipt.transaction_hash,
+                })
+            } else {
+                Event::LiquidationFailed(LiquidationFailed {
+                    strategy: *strategy,
+                    position_id: *position_id,
+                    reason: match succeeded {
+                        true => format!("not liquidated by {:?}", receipt.transaction_hash),
+                        false => format!("transaction {:?} reverted", receipt.transaction_hash),
+                    },
+                })
+            }
+        })
+        .collect()
+}
+
+struct Executor<'a> {
+    configuration: &'a Configuration,
+    web3: Web3<WebSocket>,
+    event_tx: Sender<Event>,
+    metrics: Metrics,
+    liquidator_contract_address: Address,
+    liquidator_abi: ethabi::Contract,
+    chain_id: u64,
+    gas_strategy: GasStrategy,
+    wallets: WalletPool,
+    batch_call: Option<BatchCall>,
+    batcher: Batcher,
+    position_was_liquidated: H256,
+    relay: Option<Relay>,
+    flash_loan: Option<FlashLoan>,
+}
+
+impl<'a> Executor<'a> {
+    async fn new(
+        web3: Web3<WebSocket>,
+        event_tx: Sender<Event>,
+        metrics: Metrics,
+        configuration: &'a Configuration,
+    ) -> web3::Result<Self> {
+        let liquidator_contract_address =
+            Address::from_str(&configuration.liquidator_address).unwrap();
+        let liquidator_abi = ethabi::Contract::load(
+            fs::read(&configuration.liquidator_abi_path)
+                .unwrap()
+                .as_slice(),
+        )
+        .unwrap();
+
+        let chain_id = web3.eth().chain_id().await?.as_u64();
+        let mut wallets = vec![];
+        for signer in &configuration.wallets.signers {
+            let address = signer.address();
+            // Transactions sent before a restart are still pending and keep their nonces.
+            let next_nonce = web3
+                .eth()
+                .transaction_count(address, Some(BlockNumber::Pending))
+                .await?;
+            let balance = web3.eth().balance(address, None).await?;
+            wallets.push(Wallet::new(signer.clone(), next_nonce, balance));
+        }
+        let mut wallets = WalletPool::new(wallets, configuration.wallets.min_balance);
+        println!("Liquidating with {} accounts", wallets.len());
+        for wallet in 0..wallets.len() {
+            let balance = wallets.get(wallet).balance;
+            if wallets.update_balance(wallet, balance) {
+                low_balance(wallets.get(wallet), wallets.min_balance());
+            }
+        }
+
+        let batch_call = BatchCall::new(&configuration.batch, &liquidator_abi);
+        if batch_call.is_some() {
+            println!("Batching the liquidations of each strategy");
+        }
+        let relay = configuration.relay.as_ref().map(|relay| {
+            println!("Sending liquidations privately through {}", relay.url);
+            Relay::new(relay)
+        });
+
+        Ok(Self {
+            configuration,
+            batch_call,
+            batcher: Batcher::new(configuration.batch.window, configuration.batch.max_size),
+            position_was_liquidated: ithil::make_position_was_liquidated_event().signature(),
+            gas_strategy: GasStrategy::new(configuration.gas.clone(), web3.eth()),
+            web3,
+            event_tx,
+            metrics,
+            liquidator_contract_address,
+            liquidator_abi,
+            chain_id,
+            wallets,
+            relay,
+            flash_loan: configuration.flash_loan.as_ref().map(FlashLoan::new),
+        })
+    }
+
+    /// Sends `liquidation` with the next batch of its strategy, or right away if liquidations
+    /// cannot be batched.
+    async fn queue(&mut self, liquidation: Liquidation) {
+        println!("LIQUIDATION => {:?}", liquidation);
+        if self.batch_call.is_none() {
+            self.liquidate(vec![liquidation]).await;
+            return;
+        }
+        if let Some(batch) = self.batcher.push(liquidation, Instant::now()) {
+            self.liquidate(batch).await;
+        }
+    }
+
+    /// Liquidates positions of the same strategy, in a single transaction if possible. They are
+    /// prepared from the least busy account, another one sending them if it cannot pay for gas.
+    async fn liquidate(&mut self, liquidations: Vec<Liquidation>) {
+        let wallet = match self.wallets.pick(U256::zero()) {
+            Some(wallet) => wallet,
+            None => {
+                for liquidation in liquidations {
+                    let position = (liquidation.strategy, liquidation.position_id);
+                    self.fail(position, String::from("no account can pay for gas"))
+                        .await;
+                }
+                return;
+            }
+        };
+        let liquidate_single = self
+            .liquidator_abi
+            .function("liquidateSingle")
+            .unwrap()
+            .clone();
+
+        // Nothing is paid for liquidations which would revert, which also keeps one of them from
+        // reverting a whole batch.
+        let mut viable = vec![];
+        let mut balances = HashMap::new();
+        for liquidation in liquidations {
+            let position = (liquidation.strategy, liquidation.position_id);
+            let expected_reward = liquidation.expected_reward;
+            let expected_profit = liquidation.expected_profit.clone();
+            let required_funds = liquidation.required_funds.clone();
+            let data = Bytes(
+                liquidate_single
+                    .encode_input(&liquidation.into_tokens())
+                    .unwrap(),
+            );
+            if self.flash_loan.is_some()
+                && !self.set_aside(wallet, &mut balances, &required_funds).await
+            {
+                self.liquidate_with_flash_loan(
+                    wallet,
+                    position,
+                    expected_reward,
+                    expected_profit,
+                    &required_funds,
+                    data,
+                )
+                .await;
+                continue;
+            }
+
+            let call = self.call(wallet, self.liquidator_contract_address, data.clone());
+            match simulation::simulate(&self.web3.eth(), &self.liquidator_abi, call).await {
+                Ok(()) => viable.push((position, expected_reward, data)),
+                Err(reason) => self.fail(position, format!("reverted: {}", reason)).await,
+            }
+        }
+
+        if viable.len() > 1 {
+            if let Some(batch_call) = &self.batch_call {
+                let strategy = viable[0].0 .0;
+                let position_ids: Vec<U256> = viable
+                    .iter()
+                    .map(|((_, position_id), _, _)| *position_id)
+                    .collect();
+                let to = self.liquidator_contract_address;
+                let data = batch_call.encode(strategy, &position_ids);
+                let call = self.call(wallet, to, data.clone());
+                match simulation::simulate(&self.web3.eth(), &self.liquidator_abi, call).await {
+                    Ok(()) => {
+                        let positions = viable.iter().map(|(position, _, _)| *position).collect();
+                        // The reward of the batch is only known if the reward of each liquidation is.
+                        let expected_reward = viable.iter().try_fold(
+                            U256::zero(),
+                            |total, (_, expected_reward, _)| {
+                                Some(total.saturating_add((*expected_reward)?))
+                            },
+                        );
+                        self.submit(wallet, positions, to, data, expected_reward)
+                            .await;
+                        return;
+                    }
+                    Err(reason) => {
+                        println!("Batch reverted: {}, liquidating one by one", reason)
+                    }
+                }
+            }
+        }
+
+        for (position, expected_reward, data) in viable {
+            self.submit(
+                wallet,
+                vec![position],
+                self.liquidator_contract_address,
+                data,
+                expected_reward,
+            )
+            .await;
+        }
+    }
+
+    /// Sets `funds` aside for a liquidation if `wallet` holds them, besides the funds already set
+    /// aside in `balances`. Returns `false` if the debt must be borrowed instead.
+    async fn set_aside(
+        &self,
+        wallet: usize,
+        balances: &mut HashMap<Address, U256>,
+        funds: &RequiredFunds,
+    ) -> bool {
+        let balance = match balances.get(&funds.token) {
+            Some(balance) => *balance,
+            None => match flash_loan::balance(
+                self.web3.eth(),
+                funds.token,
+                self.wallets.get(wallet).address,
+            )
+            .await
+            {
+                Ok(balance) => balance,
+                Err(error) => {
+                    // The simulation of the liquidation tells whether the account can pay.
+                    println!("Cannot read balance of {:?}: {}", funds.token, error);
+                    return true;
+                }
+            },
+        };
+
+        let has_funds = balance >= funds.amount;
+        let balance = match has_funds {
+            true => balance - funds.amount,
+            false => balance,
+        };
+        balances.insert(funds.token, balance);
+
+        has_funds
+    }
+
+    /// Liquidates `position` by calling the liquidator with `data` once its debt is borrowed, if
+    /// the fee of the loan leaves the minimum profit.
+    async fn liquidate_with_flash_loan(
+        &mut self,
+        wallet: usize,
+        position: (Address, U256),
+        expected_reward: Option<U256>,
+        expected_profit: Option<BigInt>,
+        funds: &RequiredFunds,
+        data: Bytes,
+    ) {
+        if let (Some(profit), Some(fee)) = (&expected_profit, funds.flash_loan_fee) {
+            let profit = profitability::with_flash_loan(profit, fee);
+            let min_profit = self.flash_loan.as_ref().unwrap().min_profit();
+            if profit < vault_math::to_big_int(min_profit) {
+                let reason = format!(
+                    "flash loan fee of {} wei leaves a profit of {} wei, below {} wei",
+                    fee, profit, min_profit
+                );
+                self.fail(position, reason).await;
+                return;
+            }
+        }
+
+        let flash_loan = self.flash_loan.as_ref().unwrap();
+        let (to, data) = flash_loan.encode(funds, self.liquidator_contract_address, &data);
+        let call = self.call(wallet, to, data.clone());
+        match simulation::simulate(&self.web3.eth(), &self.liquidator_abi, call).await {
+            Ok(()) => {
+                println!(
+                    "FLASH LOAN => {} of {:?} for position {} of strategy {:?}",
+                    funds.amount, funds.token, position.1, position.0
+                );
+                // Gas is paid out of what is left of the reward once the loan is paid back.
+                let expected_reward = match funds.flash_loan_fee {
+                    Some(fee) => expected_reward.map(|reward| reward.saturating_sub(fee)),
+                    None => expected_reward,
+                };
+                self.submit(wallet, vec![position], to, data, expected_reward)
+                    .await;
+            }
+            Err(reason) => {
+                self.fail(position, format!("reverted with a flash loan: {}", reason))
+                    .await
+            }
+        }
+    }
+
+    fn call(&self, wallet: usize, to: Address, data: Bytes) -> CallRequest {
+        CallRequest {
+            from: Some(self.wallets.get(wallet).address),
+            to: Some(to),
+            data: Some(data),
+            ..Default::default()
+        }
+    }
+
+    /// Sends a transaction liquidating `positions` from `wallet`, spending at most a share of
+    /// `expected_reward` on gas.
+    async fn submit(
+        &mut self,
+        wallet: usize,
+        positions: Vec<(Address, U256)>,
+        to: Address,
+        data: Bytes,
+        expected_reward: Option<U256>,
+    ) {
+        let gas_plan = match self
+            .gas_strategy
+            .plan(self.call(wallet, to, data.clone()), expected_reward)
+            .await
+        {
+            Ok(gas_plan) => gas_plan,
+            Err(error) => {
+                for position in positions {
+                    self.fail(position, error.to_string()).await;
+                }
+                return;
+            }
+        };
+        let wallet = match self.payer(wallet, to, &data, gas_plan.max_cost()).await {
+            Some(wallet) => wallet,
+            None => {
+                let reason = format!("no account can pay {} wei of gas", gas_plan.max_cost());
+                for position in positions {
+                    self.fail(position, reason.clone()).await;
+                }
+                return;
+            }
+        };
+
+        let nonces = &mut self.wallets.get_mut(wallet).nonces;
+        let nonce = nonces.reserve();
+        let transaction = TransactionParameters {
+            nonce: Some(nonce),
+            to: Some(to),
+            data,
+            chain_id: Some(self.chain_id),
+            ..Default::default()
+        };
+        match self
+            .send_privately(wallet, transaction.clone(), &gas_plan)
+            .await
+        {
+            Ok((hash, block, private)) => {
+                println!(
+                    "LIQUIDATION SENT => {:?} from {:?} with nonce {} for {} positions",
+                    hash,
+                    self.wallets.get(wallet).address,
+                    nonce,
+                    positions.len()
+                );
+                self.metrics.liquidations_submitted(positions.len());
+                let budget = expected_reward
+                    .map(|reward| gas::apply_bps(reward, self.configuration.gas.max_reward_share));
+                self.wallets.get_mut(wallet).nonces.sent(
+                    nonce,
+                    PendingTransaction {
+                        positions,
+                        cancelled: false,
+                        transaction,
+                        gas_plan,
+                        budget,
+                        hashes: vec![hash],
+                        cancellation_hashes: vec![],
+                        sent_at: block,
+                        private,
+                        replacements: 0,
+                    },
+                );
+            }
+            Err(error) => {
+                self.wallets.get_mut(wallet).nonces.release(nonce);
+                for position in positions {
+                    self.fail(position, error.to_string()).await;
+                }
+            }
+        }
+    }
+
+    /// Returns the receipt of whichever version of `pending` was mined.
+    async fn find_receipt(
+        &self,
+        pending: &PendingTransaction,
+    ) -> web3::Result<Option<TransactionReceipt>> {
+        for hash in pending
+            .hashes
+            .iter()
+            .chain(pending.cancellation_hashes.iter())
+        {
+            if let Some(receipt) = self.web3.eth().transaction_receipt(*hash).await? {
+                return Ok(Some(receipt));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reports to the liquidator that the liquidation of `position` was not sent.
+    async fn fail(&self, (strategy, position_id): (Address, U256), reason: String) {
+        self.report(Event::LiquidationFailed(LiquidationFailed {
+            strategy,
+            position_id,
+            reason,
+        }))
+        .await;
+    }
+
+    async fn report(&self, event: Event) {
+        if self.event_tx.send(event).await.is_err() {
+            println!("Cannot report liquidation outcome: the liquidator is gone");
+        }
+    }
+
+    /// Returns `wallet` if it can pay `cost` on gas, or else the least busy account which can and
+    /// from which the call to `to` with `data` succeeds, as accounts may not hold the same funds.
+    async fn payer(&self, wallet: usize, to: Address, data: &Bytes, cost: U256) -> Option<usize> {
+        if self.wallets.get(wallet).spendable() >= cost {
+            return Some(wallet);
+        }
+
+        for payer in self.wallets.eligible(cost) {
+            let call = self.call(payer, to, data.clone());
+            match simulation::simulate(&self.web3.eth(), &self.liquidator_abi, call).await {
+                Ok(()) => {
+                    println!(
+                        "Account {:?} cannot pay {} wei of gas, sending from {:?}",
+                        self.wallets.get(wallet).address,
+                        cost,
+                        self.wallets.get(payer).address
+                    );
+                    return Some(payer);
+                }
+                Err(reason) => println!(
+                    "Cannot send from {:?}, reverted: {}",
+                    self.wallets.get(payer).address,
+                    reason
+                ),
+            }
+        }
+
+        None
+    }
+
+    /// Signs `transaction` from `wallet` with the gas of `gas_plan`.
+    async fn sign(
+        &self,
+        wallet: usize,
+        mut transaction: TransactionParameters,
+        gas_plan: &GasPlan,
+    ) -> web3::Result<SignedTransaction> {
+        gas_plan.apply(&mut transaction);
+        Ok(self
+            .wallets
+            .get(wallet)
+            .signer
+            .sign_transaction(transaction)
+            .await?)
+    }
+
+    /// Signs `transaction` from `wallet` with the gas of `gas_plan` and sends it, returning its
+    /// hash and the current block.
+    async fn send(
+        &self,
+        wallet: usize,
+        transaction: TransactionParameters,
+        gas_plan: &GasPlan,
+    ) -> web3::Result<(H256, u64)> {
+        let signed = self.sign(wallet, transaction, gas_plan).await?;
+        self.broadcast(signed.raw_transaction).await
+    }
+
+    /// Sends a signed transaction, returning its hash and the current block.
+    async fn broadcast(&self, raw_transaction: Bytes) -> web3::Result<(H256, u64)> {
+        // Read beforehand, a transaction once broadcast is pending whatever fails next.
+        let block = self.web3.eth().block_number().await?.as_u64();
+        let hash = self
+            .web3
+            .eth()
+            .send_raw_transaction(raw_transaction)
+            .await?;
+
+        Ok((hash, block))
+    }
+
+    /// Sends `transaction` to the relay as bundles targeting the next blocks, or publicly if there
+    /// is no relay or it accepts none of them. Also returns the bundles sent, if any.
+    async fn send_privately(
+        &self,
+        wallet: usize,
+        transaction: TransactionParameters,
+        gas_plan: &GasPlan,
+    ) -> web3::Result<(H256, u64, Option<PrivateTransaction>)> {
+        let (relay, configuration) = match (&self.relay, &self.configuration.relay) {
+            (Some(relay), Some(configuration)) => (relay, configuration),
+            _ => {
+                let (hash, block) = self.send(wallet, transaction, gas_plan).await?;
+                return Ok((hash, block, None));
+            }
+        };
+
+        let signed = self.sign(wallet, transaction.clone(), gas_plan).await?;
+        let block = self.web3.eth().block_number().await?.as_u64();
+        let raw_transactions = [signed.raw_transaction.clone()];
+        let mut private_until = None;
+        for target in block + 1..=block + configuration.max_missed_blocks {
+            match relay.send_bundle(&raw_transactions, target).await {
+                Ok(bundle_hash) => {
+                    println!("Sent bundle {:?} for block {}", bundle_hash, target);
+                    private_until = Some(target);
+                }
+                Err(error) => println!("Cannot send bundle for block {}: {}", target, error),
+            }
+        }
+
+        match private_until {
+            Some(until) => Ok((
+                signed.transaction_hash,
+                block,
+                Some(PrivateTransaction {
+                    until,
+                    raw_transaction: signed.raw_transaction,
+                }),
+            )),
+            None => {
+                let (hash, block) = self.send(wallet, transaction, gas_plan).await?;
+                Ok((hash, block, None))
+            }
+        }
+    }
+
+    /// Reports whether every account holds the minimum balance to `health`.
+    fn report_balances(&self, health: &HealthRegistry) {
+        let low: Vec<String> = (0..self.wallets.len())
+            .map(|wallet| self.wallets.get(wallet))
+            .filter(|wallet| wallet.is_low())
+            .map(|wallet| format!("{:?} holds {} wei", wallet.address, wallet.balance))
+            .collect();
+        let detail = match low.is_empty() {
+            true => format!("{} accounts above the minimum balance", self.wallets.len()),
+            false => low.join(", "),
+        };
+        health.set_component(readiness::BALANCES, low.is_empty(), detail);
+    }
+
+    /// Forgets the mined transactions of every account, replaces those stuck for too long and
+    /// reports the accounts to top up. An account which cannot be checked does not hold back the
+    /// others, the last error being returned.
+    async fn check_pending_transactions(&mut self) -> web3::Result {
+        let mut result = Ok(());
+        for wallet in 0..self.wallets.len() {
+            if let Err(error) = self.check_wallet(wallet).await {
+                println!(
+                    "Cannot check account {:?}: {}",
+                    self.wallets.get(wallet).address,
+                    error
+                );
+                result = Err(error);
+            }
+        }
+
+        result
+    }
+
+    async fn check_wallet(&mut self, wallet: usize) -> web3::Result {
+        let eth = self.web3.eth();
+        let address = self.wallets.get(wallet).address;
+        let mined_nonce = eth
+            .transaction_count(address, Some(BlockNumber::Latest))
+            .await?;
+        let mined = self.wallets.get_mut(wallet).nonces.confirm(mined_nonce);
+        for (nonce, mined) in mined {
+            let receipt = match self.find_receipt(&mined).await {
+                Ok(receipt) => receipt,
+                Err(error) => {
+                    // The liquidator tries again, after simulating the liquidation.
+                    println!("Cannot read receipt of nonce {}: {}", nonce, error);
+                    None
+                }
+            };
+            println!(
+                "LIQUIDATION RECEIPT => {:?} nonce {}: {:?}",
+                address, nonce, receipt
+            );
+            if let Some(receipt) = &receipt {
+                self.metrics.gas_spent(receipt);
+            }
+            for event in outcomes(&mined, receipt.as_ref(), self.position_was_liquidated) {
+                self.report(event).await;
+            }
+        }
+
+        let balance = eth.balance(address, None).await?;
+        if self.wallets.update_balance(wallet, balance) {
+            low_balance(self.wallets.get(wallet), self.wallets.min_balance());
+        }
+
+        let block = eth.block_number().await?.as_u64();
+        // Liquidations the relay could not get included are broadcast publicly as signed, so that
+        // their hash stays the one recorded. One which cannot be broadcast is tried again at the
+        // next check.
+        for nonce in self.wallets.get(wallet).nonces.missed(block) {
+            let raw_transaction = match self.wallets.get(wallet).nonces.get(&nonce) {
+                Some(PendingTransaction {
+                    private: Some(private),
+                    ..
+                }) => private.raw_transaction.clone(),
+                _ => continue,
+            };
+            match self.broadcast(raw_transaction).await {
+                Ok((hash, block)) => {
+                    println!("Bundle missed, broadcast nonce {} as {:?}", nonce, hash);
+                    self.wallets.get_mut(wallet).nonces.published(nonce, block);
+                }
+                Err(error) => println!(
+                    "Cannot broadcast nonce {} of account {:?}: {}",
+                    nonce, address, error
+                ),
+            }
+        }
+
+        let replacements = self.wallets.get(wallet).nonces.stuck(
+            block,
+            self.configuration.stuck_blocks,
+            self.configuration.max_speed_ups,
+        );
+        for replacement in replacements {
+            if let Replacement::Stuck(nonce) = replacement {
+                stuck(self.wallets.get(wallet), nonce);
+                self.wallets.get_mut(wallet).nonces.reported(nonce, block);
+                continue;
+            }
+            // A replacement which cannot be sent is tried again at the next check, without holding
+            // back the others.
+            let nonce = replacement.nonce();
+            if let Err(error) = self.replace(wallet, replacement).await {
+                println!(
+                    "Cannot replace nonce {} of account {:?}: {}",
+                    nonce, address, error
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn replace(&mut self, wallet: usize, replacement: Replacement) -> web3::Result {
+        let bump = self.configuration.gas.replacement_fee_bump;
+        let (nonce, cancel) = match replacement {
+            Replacement::SpeedUp(nonce) => (nonce, false),
+            Replacement::Cancel(nonce) => (nonce, true),
+            Replacement::Stuck(_) => return Ok(()),
+        };
+        let pending = match self.wallets.get(wallet).nonces.get(&nonce) {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        let speed_up = GasPlan {
+            gas_limit: pending.gas_plan.gas_limit,
+            fees: pending.gas_plan.fees.bumped(bump),
+        };
+        // A liquidation which cannot pay higher fees is cancelled, a cancellation costs little.
+        let cancel = cancel
+            || pending
+                .budget
+                .is_some_and(|budget| !pending.cancelled && speed_up.max_cost() > budget);
+        let (transaction, gas_plan) = if cancel {
+            (
+                TransactionParameters {
+                    nonce: Some(nonce),
+                    to: Some(self.wallets.get(wallet).address),
+                    chain_id: Some(self.chain_id),
+                    ..Default::default()
+                },
+                GasPlan {
+                    gas_limit: U256::from(TRANSFER_GAS),
+                    fees: pending.gas_plan.fees.bumped(bump),
+                },
+            )
+        } else {
+            (pending.transaction.clone(), speed_up)
+        };
+
+        let (hash, block) = self.send(wallet, transaction.clone(), &gas_plan).await?;
+        println!(
+            "{} transaction with nonce {} => {:?} {:?}",
+            if cancel { "CANCELLED" } else { "SPED UP" },
+            nonce,
+            hash,
+            gas_plan.fees
+        );
+        self.wallets.get_mut(wallet).nonces.replaced(
+            nonce,
+            transaction,
+            gas_plan,
+            hash,
+            block,
+            cancel,
+        );
+
+        Ok(())
+    }
+}
+
+/// Alerts that the account of `wallet` holds less than `min_balance`.
+fn low_balance(wallet: &Wallet, min_balance: U256) {
+    println!(
+        "LOW BALANCE => account {:?} holds {} wei, top it up above {} wei",
+        wallet.address, wallet.balance, min_balance
+    );
+}
+
+/// Alerts that the cancellation of the transaction with `nonce` of `wallet` is still pending after
+/// every speed up, blocking the account.
+fn stuck(wallet: &Wallet, nonce: U256) {
+    let cancellations = wallet
+        .nonces
+        .get(&nonce)
+        .map(|pending| pending.cancellation_hashes.clone())
+        .unwrap_or_default();
+    println!(
+        "STUCK => nonce {} of account {:?} still pending after cancellations {:?}, replace it by hand",
+        nonce, wallet.address, cancellations
+    );
+}
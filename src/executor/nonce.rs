@@ -0,0 +1,205 @@
+//! Nonces of the liquidation account.
+//!
+//! Nonces are handed out locally, so that liquidations can be sent without waiting for the
+//! previous ones to be mined. A transaction still pending after a few blocks blocks all those with
+//! higher nonces, so it is replaced with the same nonce and higher fees, first by itself, then by
+//! an empty transaction cancelling it. A cancellation which stays stuck is reported rather than
+//! sped up forever. Transactions sent privately to a relay are not replaced until they are
+//! broadcast publicly.
+
+use std::collections::BTreeMap;
+
+use web3::types::{Address, Bytes, TransactionParameters, H256, U256};
+
+use super::gas::GasPlan;
+
+#[derive(Clone, Debug)]
+pub struct PendingTransaction {
+    /// Strategy and id of the liquidated positions.
+    pub positions: Vec<(Address, U256)>,
+    /// Whether the liquidation was replaced by a cancellation.
+    pub cancelled: bool,
+    pub transaction: TransactionParameters,
+    pub gas_plan: GasPlan,
+    /// Maximum cost of the liquidation in wei, if known.
+    pub budget: Option<U256>,
+    /// Hashes of every version of the liquidation sent, the latest last.
+    pub hashes: Vec<H256>,
+    /// Hashes of the cancellations sent.
+    pub cancellation_hashes: Vec<H256>,
+    /// Block when the latest version was sent, or when it was last reported stuck.
+    pub sent_at: u64,
+    /// Bundles of the transaction, while it is only known to the relay.
+    pub private: Option<PrivateTransaction>,
+    pub replacements: u32,
+}
+
+/// A transaction sent to the relay only.
+#[derive(Clone, Debug)]
+pub struct PrivateTransaction {
+    /// Last block targeted by the bundles of the transaction.
+    pub until: u64,
+    /// The signed transaction, broadcast as is if no bundle is included so that its hash stays the
+    /// same.
+    pub raw_transaction: Bytes,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Replacement {
+    /// Same transaction, higher fees.
+    SpeedUp(U256),
+    /// Empty transaction to the account itself with higher fees, to free the nonce.
+    Cancel(U256),
+    /// Cancellation already sped up too many times, to be looked into by hand.
+    Stuck(U256),
+}
+
+impl Replacement {
+    pub fn nonce(&self) -> U256 {
+        match self {
+            Replacement::SpeedUp(nonce)
+            | Replacement::Cancel(nonce)
+            | Replacement::Stuck(nonce) => *nonce,
+        }
+    }
+}
+
+pub struct NonceManager {
+    next_nonce: U256,
+    pending: BTreeMap<U256, PendingTransaction>,
+}
+
+impl NonceManager {
+    /// Starts from the transaction count of the account, including its pending transactions.
+    pub fn new(next_nonce: U256) -> Self {
+        Self {
+            next_nonce,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn next_nonce(&self) -> U256 {
+        self.next_nonce
+    }
+
+    pub fn reserve(&mut self) -> U256 {
+        let nonce = self.next_nonce;
+        self.next_nonce += U256::one();
+        nonce
+    }
+
+    /// Gives back a nonce whose transaction could not be sent, if no later nonce was reserved.
+    pub fn release(&mut self, nonce: U256) {
+        if nonce + 1 == self.next_nonce && !self.pending.contains_key(&nonce) {
+            self.next_nonce = nonce;
+        }
+    }
+
+    pub fn sent(&mut self, nonce: U256, transaction: PendingTransaction) {
+        self.pending.insert(nonce, transaction);
+    }
+
+    pub fn get(&self, nonce: &U256) -> Option<&PendingTransaction> {
+        self.pending.get(nonce)
+    }
+
+    /// Number of transactions sent and not mined yet.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Wei the pending transactions may still spend on gas, if they use all of it.
+    pub fn pending_cost(&self) -> U256 {
+        self.pending.values().fold(U256::zero(), |total, pending| {
+            total.saturating_add(pending.gas_plan.max_cost())
+        })
+    }
+
+    /// Records that the transaction with `nonce` was replaced by `transaction`.
+    pub fn replaced(
+        &mut self,
+        nonce: U256,
+        transaction: TransactionParameters,
+        gas_plan: GasPlan,
+        hash: H256,
+        block: u64,
+        cancelled: bool,
+    ) {
+        if let Some(pending) = self.pending.get_mut(&nonce) {
+            pending.transaction = transaction;
+            pending.gas_plan = gas_plan;
+            pending.cancelled |= cancelled;
+            if pending.cancelled {
+                pending.cancellation_hashes.push(hash);
+            } else {
+                pending.hashes.push(hash);
+            }
+            pending.sent_at = block;
+            pending.replacements += 1;
+        }
+    }
+
+    /// Forgets the transactions mined before `mined_nonce`, the transaction count of the account
+    /// in the latest block, and returns them.
+    pub fn confirm(&mut self, mined_nonce: U256) -> Vec<(U256, PendingTransaction)> {
+        let still_pending = self.pending.split_off(&mined_nonce);
+        let mined = std::mem::replace(&mut self.pending, still_pending);
+        // Transactions may have been sent from the same account by someone else.
+        self.next_nonce = self.next_nonce.max(mined_nonce);
+
+        mined.into_iter().collect()
+    }
+
+    /// Returns the nonces of the private transactions still pending at `block`, the last block
+    /// targeted by their bundle or later.
+    pub fn missed(&self, block: u64) -> Vec<U256> {
+        self.pending
+            .iter()
+            .filter(|(_, pending)| {
+                pending
+                    .private
+                    .as_ref()
+                    .is_some_and(|private| private.until <= block)
+            })
+            .map(|(nonce, _)| *nonce)
+            .collect()
+    }
+
+    /// Records that the stuck cancellation with `nonce` was reported at `block`, so that it is
+    /// reported again `stuck_blocks` later.
+    pub fn reported(&mut self, nonce: U256, block: u64) {
+        if let Some(pending) = self.pending.get_mut(&nonce) {
+            pending.sent_at = block;
+        }
+    }
+
+    /// Records that the private transaction with `nonce` was broadcast publicly at `block`.
+    pub fn published(&mut self, nonce: U256, block: u64) {
+        if let Some(pending) = self.pending.get_mut(&nonce) {
+            pending.private = None;
+            pending.sent_at = block;
+        }
+    }
+
+    /// Returns how to replace the public transactions sent `stuck_blocks` blocks or more before
+    /// `block`: liquidations are sped up `max_speed_ups` times, then cancelled, and cancellations
+    /// are sped up `max_speed_ups` times, then reported stuck.
+    pub fn stuck(&self, block: u64, stuck_blocks: u64, max_speed_ups: u32) -> Vec<Replacement> {
+        self.pending
+            .iter()
+            .filter(|(_, pending)| pending.private.is_none())
+            .filter(|(_, pending)| pending.sent_at + stuck_blocks <= block)
+            .map(|(nonce, pending)| {
+                if !pending.cancelled && pending.replacements < max_speed_ups {
+                    Replacement::SpeedUp(*nonce)
+                } else if !pending.cancelled {
+                    Replacement::Cancel(*nonce)
+                } else if pending.cancellation_hashes.len() <= max_speed_ups as usize {
+                    Replacement::SpeedUp(*nonce)
+                } else {
+                    Replacement::Stuck(*nonce)
+                }
+            })
+            .collect()
+    }
+}
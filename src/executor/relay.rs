@@ -0,0 +1,136 @@
+//! Private submission of liquidations through a bundle relay, e.g. Flashbots.
+//!
+//! Transactions broadcast to the public mempool are easily front-run. They are instead sent to the
+//! relay as bundles targeting the next blocks with `eth_sendBundle`, and only broadcast publicly if
+//! none of these blocks includes them.
+
+use std::fmt;
+use std::str::FromStr;
+
+use secp256k1::SecretKey;
+use serde_json::{json, Value};
+use web3::signing::{self, Key, SecretKeyRef};
+use web3::types::{Bytes, H256};
+
+/// Header authenticating the searcher sending a bundle.
+const SIGNATURE_HEADER: &str = "X-Flashbots-Signature";
+
+#[derive(Clone, Debug)]
+pub struct Configuration {
+    pub url: String,
+    /// Key signing the bundles, which identifies the bot to the relay. It does not need to hold
+    /// any funds.
+    pub signing_key: SecretKey,
+    /// Number of blocks targeted by a bundle before its transaction is broadcast publicly.
+    pub max_missed_blocks: u64,
+}
+
+#[derive(Debug)]
+pub enum RelayError {
+    Http(reqwest::Error),
+    /// The relay rejected the bundle.
+    Rejected(String),
+    InvalidResponse(String),
+}
+
+impl fmt::Display for RelayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RelayError::Http(error) => write!(f, "{}", error),
+            RelayError::Rejected(message) => write!(f, "bundle rejected: {}", message),
+            RelayError::InvalidResponse(response) => write!(f, "invalid response: {}", response),
+        }
+    }
+}
+
+impl std::error::Error for RelayError {}
+
+impl From<reqwest::Error> for RelayError {
+    fn from(error: reqwest::Error) -> Self {
+        RelayError::Http(error)
+    }
+}
+
+pub struct Relay {
+    client: reqwest::Client,
+    url: String,
+    signing_key: SecretKey,
+}
+
+impl Relay {
+    pub fn new(configuration: &Configuration) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: configuration.url.clone(),
+            signing_key: configuration.signing_key,
+        }
+    }
+
+    /// Sends a bundle of signed `transactions` to be included in `block`, returning its hash.
+    pub async fn send_bundle(
+        &self,
+        transactions: &[Bytes],
+        block: u64,
+    ) -> Result<H256, RelayError> {
+        let body = bundle_request(transactions, block).to_string();
+        let response: Value = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header(
+                SIGNATURE_HEADER,
+                flashbots_signature(body.as_bytes(), &self.signing_key),
+            )
+            .body(body)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(error) = response.get("error") {
+            let message = error.get("message").and_then(|message| message.as_str());
+            return Err(RelayError::Rejected(
+                message.map_or_else(|| error.to_string(), String::from),
+            ));
+        }
+        response
+            .pointer("/result/bundleHash")
+            .and_then(|hash| hash.as_str())
+            .and_then(|hash| H256::from_str(hash).ok())
+            .ok_or_else(|| RelayError::InvalidResponse(response.to_string()))
+    }
+}
+
+/// JSON-RPC request of `eth_sendBundle`.
+pub fn bundle_request(transactions: &[Bytes], block: u64) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_sendBundle",
+        "params": [{
+            "txs": transactions,
+            "blockNumber": format!("{:#x}", block),
+        }],
+    })
+}
+
+/// Value of the signature header for a request with `body`: the address of `key` and its
+/// signature of the hex-encoded keccak hash of the body, as an Ethereum signed message.
+pub fn flashbots_signature(body: &[u8], key: &SecretKey) -> String {
+    let key = SecretKeyRef::new(key);
+    let body_hash = format!("{:?}", H256::from(signing::keccak256(body)));
+    let message_hash = signing::hash_message(body_hash.as_bytes());
+    let signature = key.sign_message(message_hash.as_bytes()).unwrap();
+
+    let mut bytes = Vec::with_capacity(65);
+    bytes.extend_from_slice(signature.r.as_bytes());
+    bytes.extend_from_slice(signature.s.as_bytes());
+    bytes.push(signature.v as u8 + 27);
+
+    format!("{:?}:{}", key.address(), hex_bytes(&bytes))
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("0x{}", hex)
+}
@@ -0,0 +1,314 @@
+//! Signers of the liquidation transactions.
+//!
+//! The key of the liquidation account is either held in memory, decrypted from a JSON keystore
+//! at startup, or never seen by the bot at all when transactions are signed by a remote signer
+//! such as Web3Signer.
+
+use std::fmt;
+use std::fs;
+
+use async_trait::async_trait;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5;
+use openssl::symm::{self, Cipher};
+use secp256k1::SecretKey;
+use serde_json::{json, Value};
+use web3::api::{Accounts, Namespace};
+use web3::helpers;
+use web3::signing::{self, Key, SecretKeyRef};
+use web3::types::{Address, Bytes, TransactionParameters, TransactionRequest, H256};
+use web3::{RequestId, Transport};
+
+#[derive(Debug)]
+pub enum SignerError {
+    Io(String, std::io::Error),
+    InvalidKeystore(String),
+    /// The password does not decrypt the keystore.
+    WrongPassword,
+    /// The nonce, fees or chain id of the transaction to sign are missing.
+    IncompleteTransaction,
+    Remote(String),
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignerError::Io(path, error) => write!(f, "cannot read {}: {}", path, error),
+            SignerError::InvalidKeystore(reason) => write!(f, "invalid keystore: {}", reason),
+            SignerError::WrongPassword => write!(f, "wrong keystore password"),
+            SignerError::IncompleteTransaction => write!(f, "incomplete transaction"),
+            SignerError::Remote(reason) => write!(f, "remote signer: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+impl From<SignerError> for web3::Error {
+    fn from(error: SignerError) -> Self {
+        web3::Error::Transport(web3::error::TransportError::Message(error.to_string()))
+    }
+}
+
+/// A transaction ready to be sent.
+#[derive(Clone, Debug)]
+pub struct SignedTransaction {
+    pub raw_transaction: Bytes,
+    pub transaction_hash: H256,
+}
+
+#[async_trait]
+pub trait Signer: Send + Sync {
+    /// Address of the account signing transactions.
+    fn address(&self) -> Address;
+
+    /// Signs `transaction`, whose nonce, gas, fees and chain id are set.
+    async fn sign_transaction(
+        &self,
+        transaction: TransactionParameters,
+    ) -> Result<SignedTransaction, SignerError>;
+}
+
+/// Signer holding the key in memory.
+pub struct LocalSigner {
+    secret: SecretKey,
+}
+
+impl LocalSigner {
+    pub fn new(secret: SecretKey) -> Self {
+        Self { secret }
+    }
+}
+
+#[async_trait]
+impl Signer for LocalSigner {
+    fn address(&self) -> Address {
+        SecretKeyRef::new(&self.secret).address()
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: TransactionParameters,
+    ) -> Result<SignedTransaction, SignerError> {
+        let signed = Accounts::new(Offline)
+            .sign_transaction(transaction, SecretKeyRef::new(&self.secret))
+            .await
+            .map_err(|_| SignerError::IncompleteTransaction)?;
+
+        Ok(SignedTransaction {
+            raw_transaction: signed.raw_transaction,
+            transaction_hash: signed.transaction_hash,
+        })
+    }
+}
+
+/// Transport of a `LocalSigner`, which only signs complete transactions and so never needs to
+/// ask a node for their missing fields.
+#[derive(Clone, Debug)]
+struct Offline;
+
+impl Transport for Offline {
+    type Out = futures::future::Ready<web3::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, jsonrpc_core::Call) {
+        (0, helpers::build_request(0, method, params))
+    }
+
+    fn send(&self, _: RequestId, _: jsonrpc_core::Call) -> Self::Out {
+        futures::future::ready(Err(web3::Error::Unreachable))
+    }
+}
+
+/// Signer asking a remote service, e.g. Web3Signer, to sign transactions with
+/// `eth_signTransaction`.
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: String,
+    address: Address,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, address: Address) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: String::from(url),
+            address,
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: TransactionParameters,
+    ) -> Result<SignedTransaction, SignerError> {
+        let request = TransactionRequest {
+            from: self.address,
+            to: transaction.to,
+            gas: Some(transaction.gas),
+            gas_price: transaction.gas_price,
+            value: Some(transaction.value),
+            data: Some(transaction.data),
+            nonce: transaction.nonce,
+            condition: None,
+            transaction_type: transaction.transaction_type,
+            access_list: transaction.access_list,
+            max_fee_per_gas: transaction.max_fee_per_gas,
+            max_priority_fee_per_gas: transaction.max_priority_fee_per_gas,
+        };
+        let response: Value = self
+            .client
+            .post(&self.url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "eth_signTransaction",
+                "params": [request],
+            }))
+            .send()
+            .await
+            .map_err(|error| SignerError::Remote(error.to_string()))?
+            .json()
+            .await
+            .map_err(|error| SignerError::Remote(error.to_string()))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(SignerError::Remote(error.to_string()));
+        }
+        // Web3Signer returns the raw transaction, Geth and Clef an object holding it.
+        let raw_transaction = match response.get("result") {
+            Some(Value::Object(result)) => result.get("raw"),
+            result => result,
+        }
+        .and_then(|raw| serde_json::from_value::<Bytes>(raw.clone()).ok())
+        .ok_or_else(|| SignerError::Remote(format!("invalid response {}", response)))?;
+
+        Ok(SignedTransaction {
+            transaction_hash: H256::from(signing::keccak256(&raw_transaction.0)),
+            raw_transaction,
+        })
+    }
+}
+
+/// Decrypts the keystore at `path` with the password held in `password_path`.
+pub fn read_keystore(path: &str, password_path: &str) -> Result<SecretKey, SignerError> {
+    let read = |path: &str| {
+        fs::read_to_string(path).map_err(|error| SignerError::Io(String::from(path), error))
+    };
+    let keystore: Value = serde_json::from_str(&read(path)?)
+        .map_err(|error| SignerError::InvalidKeystore(error.to_string()))?;
+    // Password files usually end with a newline, which is not part of the password.
+    let password = read(password_path)?;
+
+    decrypt_keystore(&keystore, password.trim_end_matches(['\r', '\n']))
+}
+
+/// Decrypts a version 3 JSON keystore, whose key is derived from the password with scrypt or
+/// PBKDF2 and encrypted with AES-128-CTR.
+pub fn decrypt_keystore(keystore: &Value, password: &str) -> Result<SecretKey, SignerError> {
+    let invalid = |reason: &str| SignerError::InvalidKeystore(String::from(reason));
+    // Older versions of Geth capitalize the crypto section.
+    let crypto = keystore
+        .get("crypto")
+        .or_else(|| keystore.get("Crypto"))
+        .ok_or_else(|| invalid("missing crypto"))?;
+    let string = |value: &Value, field: &str| {
+        value
+            .get(field)
+            .and_then(|value| value.as_str())
+            .map(String::from)
+            .ok_or_else(|| SignerError::InvalidKeystore(format!("missing {}", field)))
+    };
+    let hex = |value: &Value, field: &str| {
+        decode_hex(&string(value, field)?)
+            .ok_or_else(|| SignerError::InvalidKeystore(format!("invalid {}", field)))
+    };
+    let number = |value: &Value, field: &str| {
+        value
+            .get(field)
+            .and_then(|value| value.as_u64())
+            .ok_or_else(|| SignerError::InvalidKeystore(format!("missing {}", field)))
+    };
+
+    if string(crypto, "cipher")? != "aes-128-ctr" {
+        return Err(invalid("unsupported cipher"));
+    }
+    let kdf_params = crypto
+        .get("kdfparams")
+        .ok_or_else(|| invalid("missing kdfparams"))?;
+    let salt = hex(kdf_params, "salt")?;
+    let mut derived_key = vec![0u8; number(kdf_params, "dklen")? as usize];
+    if derived_key.len() < 32 {
+        return Err(invalid("derived key too short"));
+    }
+    match string(crypto, "kdf")?.as_str() {
+        "scrypt" => {
+            let (n, r, p) = (
+                number(kdf_params, "n")?,
+                number(kdf_params, "r")?,
+                number(kdf_params, "p")?,
+            );
+            // Scrypt needs 128 * r * n bytes, with some room for its other buffers.
+            let max_memory = 128 * r * (n + p + 2) + 1024 * 1024;
+            pkcs5::scrypt(
+                password.as_bytes(),
+                &salt,
+                n,
+                r,
+                p,
+                max_memory,
+                &mut derived_key,
+            )
+            .map_err(|error| SignerError::InvalidKeystore(error.to_string()))?;
+        }
+        "pbkdf2" => {
+            if string(kdf_params, "prf")? != "hmac-sha256" {
+                return Err(invalid("unsupported prf"));
+            }
+            pkcs5::pbkdf2_hmac(
+                password.as_bytes(),
+                &salt,
+                number(kdf_params, "c")? as usize,
+                MessageDigest::sha256(),
+                &mut derived_key,
+            )
+            .map_err(|error| SignerError::InvalidKeystore(error.to_string()))?;
+        }
+        _ => return Err(invalid("unsupported kdf")),
+    }
+
+    let ciphertext = hex(crypto, "ciphertext")?;
+    let mac = signing::keccak256(&[&derived_key[16..32], &ciphertext[..]].concat());
+    if mac[..] != hex(crypto, "mac")?[..] {
+        return Err(SignerError::WrongPassword);
+    }
+
+    let iv = hex(
+        crypto
+            .get("cipherparams")
+            .ok_or_else(|| invalid("missing cipherparams"))?,
+        "iv",
+    )?;
+    let secret = symm::decrypt(
+        Cipher::aes_128_ctr(),
+        &derived_key[..16],
+        Some(&iv),
+        &ciphertext,
+    )
+    .map_err(|error| SignerError::InvalidKeystore(error.to_string()))?;
+
+    SecretKey::from_slice(&secret).map_err(|error| SignerError::InvalidKeystore(error.to_string()))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
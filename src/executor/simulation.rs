@@ -0,0 +1,87 @@
+//! Dry runs of liquidations with `eth_call`, so that no gas is paid for transactions which would
+//! revert, e.g. because the position was already liquidated or is no longer underwater.
+
+use serde_json::Value;
+use web3::api::Eth;
+use web3::ethabi::{self, ParamType, Token};
+use web3::transports::WebSocket;
+use web3::types::{BlockId, BlockNumber, CallRequest};
+
+/// Selector of `Error(string)`, the error of `require` and `revert` with a message.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`, the error of failed assertions and arithmetic errors.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Runs `call` on top of the pending block, returning the reason it reverts, if any.
+pub async fn simulate(
+    eth: &Eth<WebSocket>,
+    abi: &ethabi::Contract,
+    call: CallRequest,
+) -> Result<(), String> {
+    match eth
+        .call(call, Some(BlockId::Number(BlockNumber::Pending)))
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(web3::Error::Rpc(error)) => Err(match error.data.as_ref().and_then(revert_data) {
+            Some(data) => decode_revert_reason(abi, &data),
+            None => error.message,
+        }),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Extracts the revert data of an `eth_call` error: a hex string in `data`, or in `data.data` for
+/// some nodes.
+pub fn revert_data(data: &Value) -> Option<Vec<u8>> {
+    let hex = match data {
+        Value::String(hex) => hex,
+        Value::Object(object) => object.get("data")?.as_str()?,
+        _ => return None,
+    };
+
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Decodes revert data into a readable reason: the message of `Error(string)`, the code of
+/// `Panic(uint256)`, or a custom error declared in `abi`.
+pub fn decode_revert_reason(abi: &ethabi::Contract, data: &[u8]) -> String {
+    if data.len() < 4 {
+        return String::from("reverted without reason");
+    }
+    let (selector, arguments) = data.split_at(4);
+
+    if selector == ERROR_SELECTOR {
+        if let Ok(tokens) = ethabi::decode(&[ParamType::String], arguments) {
+            if let Some(Token::String(message)) = tokens.first() {
+                return message.clone();
+            }
+        }
+    }
+    if selector == PANIC_SELECTOR {
+        if let Ok(tokens) = ethabi::decode(&[ParamType::Uint(256)], arguments) {
+            if let Some(Token::Uint(code)) = tokens.first() {
+                return format!("panic {:#x}", code);
+            }
+        }
+    }
+
+    for error in abi.errors() {
+        if error.signature()[..4] == *selector {
+            if let Ok(tokens) = error.decode(arguments) {
+                let arguments: Vec<String> = tokens.iter().map(|token| token.to_string()).collect();
+                return format!("{}({})", error.name, arguments.join(", "));
+            }
+        }
+    }
+
+    format!("unknown error 0x{}", hex(data))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
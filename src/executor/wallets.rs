@@ -0,0 +1,123 @@
+//! Accounts sending the liquidations.
+//!
+//! Each account has its own nonces, so spreading liquidations across several of them keeps one
+//! stuck transaction from holding back all the others. A liquidation goes to the account with the
+//! fewest pending transactions among those which can pay for its gas, and accounts running low on
+//! ETH are reported so that they can be topped up.
+
+use std::sync::Arc;
+
+use web3::types::{Address, U256};
+
+use super::nonce::NonceManager;
+use super::signer::Signer;
+
+#[derive(Clone)]
+pub struct Configuration {
+    /// Signers of the liquidation accounts.
+    pub signers: Vec<Arc<dyn Signer>>,
+    /// Balance in wei below which an account should be topped up.
+    pub min_balance: U256,
+}
+
+pub struct Wallet {
+    pub signer: Arc<dyn Signer>,
+    pub address: Address,
+    pub nonces: NonceManager,
+    /// Balance of the account in the latest block.
+    pub balance: U256,
+    /// Whether the account was reported to be below the minimum balance.
+    low: bool,
+}
+
+impl Wallet {
+    /// `next_nonce` is the transaction count of the account, including its pending transactions.
+    pub fn new(signer: Arc<dyn Signer>, next_nonce: U256, balance: U256) -> Self {
+        Self {
+            address: signer.address(),
+            signer,
+            nonces: NonceManager::new(next_nonce),
+            balance,
+            low: false,
+        }
+    }
+
+    /// Whether the balance of the account is below the minimum balance.
+    pub fn is_low(&self) -> bool {
+        self.low
+    }
+
+    /// Balance left once the pending transactions are paid for.
+    pub fn spendable(&self) -> U256 {
+        self.balance.saturating_sub(self.nonces.pending_cost())
+    }
+}
+
+pub struct WalletPool {
+    wallets: Vec<Wallet>,
+    min_balance: U256,
+}
+
+impl WalletPool {
+    pub fn new(wallets: Vec<Wallet>, min_balance: U256) -> Self {
+        Self {
+            wallets,
+            min_balance,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.wallets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wallets.is_empty()
+    }
+
+    pub fn get(&self, wallet: usize) -> &Wallet {
+        &self.wallets[wallet]
+    }
+
+    pub fn get_mut(&mut self, wallet: usize) -> &mut Wallet {
+        &mut self.wallets[wallet]
+    }
+
+    /// Returns the account with the fewest pending transactions among those which can spend
+    /// `cost` on gas, the one with the most ETH left on a tie.
+    pub fn pick(&self, cost: U256) -> Option<usize> {
+        self.eligible(cost).first().copied()
+    }
+
+    /// Returns the accounts which can spend `cost` on gas, from the one with the fewest pending
+    /// transactions to the one with the most, those with the most ETH left first on a tie.
+    pub fn eligible(&self, cost: U256) -> Vec<usize> {
+        let mut eligible: Vec<usize> = (0..self.wallets.len())
+            .filter(|wallet| self.wallets[*wallet].spendable() >= cost)
+            .collect();
+        eligible.sort_by(|a, b| {
+            let (a, b) = (&self.wallets[*a], &self.wallets[*b]);
+            a.nonces
+                .pending_count()
+                .cmp(&b.nonces.pending_count())
+                .then(b.spendable().cmp(&a.spendable()))
+        });
+
+        eligible
+    }
+
+    /// Records the balance of an account, returning `true` if it just fell below the minimum
+    /// balance. Accounts are reported again once topped up and drained anew.
+    pub fn update_balance(&mut self, wallet: usize, balance: U256) -> bool {
+        let min_balance = self.min_balance;
+        let wallet = &mut self.wallets[wallet];
+        wallet.balance = balance;
+
+        let was_low = wallet.low;
+        wallet.low = balance < min_balance;
+        wallet.low && !was_low
+    }
+
+    pub fn min_balance(&self) -> U256 {
+        self.min_balance
+    }
+}
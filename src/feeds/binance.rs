@@ -0,0 +1,125 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol;
+
+use crate::events;
+use crate::feeds::supervisor::{Feed, FeedError, FeedHealth};
+use crate::types::{CurrencyCode, Exchange, Pair, Price};
+use events::Event;
+
+const URL: &str = "wss://stream.binance.com:9443/stream";
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Message of a combined stream, e.g. `{"stream": "ethusdt@ticker", "data": {...}}`.
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    data: Ticker,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ticker {
+    /// Event time, in milliseconds.
+    #[serde(rename = "E")]
+    event_time: u64,
+    #[serde(rename = "s")]
+    symbol: String,
+    /// Last price.
+    #[serde(rename = "c")]
+    price: String,
+}
+
+pub struct Binance {
+    symbols: Vec<String>,
+}
+
+impl Binance {
+    pub fn new() -> Self {
+        Self {
+            symbols: vec![String::from("ETHUSDT"), String::from("BTCUSDT")],
+        }
+    }
+
+    fn url(&self) -> url::Url {
+        let streams: Vec<String> = self
+            .symbols
+            .iter()
+            .map(|symbol| format!("{}@ticker", symbol.to_lowercase()))
+            .collect();
+        let mut url = url::Url::parse(URL).unwrap();
+        url.query_pairs_mut()
+            .append_pair("streams", &streams.join("/"));
+        url
+    }
+}
+
+impl Default for Binance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Feed for Binance {
+    fn name(&self) -> &str {
+        "binance"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        // Streams are subscribed to through the URL, there is no subscription request.
+        let (mut ws_stream, _) = connect_async(self.url()).await?;
+        health.connected();
+
+        // Tickers are pushed every second, silence means the connection is dead.
+        while let Some(message) = tokio::time::timeout(IDLE_TIMEOUT, ws_stream.next())
+            .await
+            .map_err(|_| "no message received in time")?
+        {
+            match message? {
+                protocol::Message::Text(payload) => {
+                    let binance_ticker = match serde_json::from_str::<StreamMessage>(&payload) {
+                        Ok(message) => message.data,
+                        Err(_) => continue,
+                    };
+                    let pair = match parse_symbol(&binance_ticker.symbol) {
+                        Some(pair) => pair,
+                        None => continue,
+                    };
+                    let price = match Price::from_str(&binance_ticker.price) {
+                        Ok(price) => price,
+                        Err(()) => continue,
+                    };
+                    let ticker = events::Ticker {
+                        exchange: Exchange::Binance,
+                        pair,
+                        price,
+                        timestamp: binance_ticker.event_time / 1000,
+                    };
+                    events_queue.send(Event::Ticker(ticker)).await?;
+                }
+                protocol::Message::Close(_) => break,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_symbol(symbol: &str) -> Option<Pair> {
+    // XXX Binance has no USD markets, USDT is used as a proxy for USD, and BTC for WBTC.
+    match symbol {
+        "ETHUSDT" => Some(Pair(CurrencyCode::WETH, CurrencyCode::USD)),
+        "BTCUSDT" => Some(Pair(CurrencyCode::WBTC, CurrencyCode::USD)),
+        _ => None,
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::Sender;
+use web3::contract::{Contract, Options};
+use web3::futures::StreamExt;
+use web3::types::{Address, FilterBuilder, Log, H256, U256};
+
+use crate::events;
+use crate::feeds::supervisor::{Feed, FeedError, FeedHealth};
+use crate::types::{Exchange, Pair, Price};
+use events::Event;
+
+/// The parts of `AggregatorV3Interface` and of the underlying aggregator used by the feed.
+const AGGREGATOR_ABI: &str = r#"[
+    {"type": "function", "name": "decimals", "inputs": [], "outputs": [{"name": "", "type": "uint8"}], "stateMutability": "view"},
+    {"type": "function", "name": "aggregator", "inputs": [], "outputs": [{"name": "", "type": "address"}], "stateMutability": "view"},
+    {"type": "function", "name": "latestRoundData", "inputs": [], "outputs": [
+        {"name": "roundId", "type": "uint80"},
+        {"name": "answer", "type": "int256"},
+        {"name": "startedAt", "type": "uint256"},
+        {"name": "updatedAt", "type": "uint256"},
+        {"name": "answeredInRound", "type": "uint80"}
+    ], "stateMutability": "view"},
+    {"type": "event", "name": "AnswerUpdated", "anonymous": false, "inputs": [
+        {"name": "current", "type": "int256", "indexed": true},
+        {"name": "roundId", "type": "uint256", "indexed": true},
+        {"name": "updatedAt", "type": "uint256", "indexed": false}
+    ]}
+]"#;
+
+#[derive(Clone)]
+pub struct AggregatorConfiguration {
+    pub pair: Pair,
+    /// Address of the `AggregatorV3Interface` proxy, e.g. the ETH / USD feed.
+    pub address: Address,
+    /// Maximum time in seconds between two rounds, after which the answer is stale.
+    pub heartbeat: u64,
+}
+
+pub struct Configuration {
+    pub ethereum_provider_wss_url: String,
+    pub aggregators: Vec<AggregatorConfiguration>,
+    /// Interval between two reads of the latest rounds, in case no `AnswerUpdated` log arrives.
+    pub poll_interval: Duration,
+}
+
+/// Latest round of an aggregator, as returned by `latestRoundData`.
+#[derive(Clone, Debug)]
+pub struct Round {
+    pub round_id: U256,
+    /// Signed answer, in two's complement.
+    pub answer: U256,
+    pub updated_at: U256,
+    pub answered_in_round: U256,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StaleRound {
+    Incomplete,
+    CarriedOver {
+        round_id: U256,
+        answered_in_round: U256,
+    },
+    InvalidAnswer(U256),
+    Expired {
+        age: u64,
+        heartbeat: u64,
+    },
+}
+
+impl fmt::Display for StaleRound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StaleRound::Incomplete => write!(f, "round is not complete"),
+            StaleRound::CarriedOver {
+                round_id,
+                answered_in_round,
+            } => write!(
+                f,
+                "round {} carries the answer of round {}",
+                round_id, answered_in_round
+            ),
+            StaleRound::InvalidAnswer(answer) => write!(f, "invalid answer {}", answer),
+            StaleRound::Expired { age, heartbeat } => {
+                write!(f, "round is {}s old (heartbeat {}s)", age, heartbeat)
+            }
+        }
+    }
+}
+
+/// Converts the latest round of an aggregator into a ticker, if the round is still current at
+/// `now`.
+///
+/// Tickers are stamped with the time the round was answered, so that an aggregator which stops
+/// answering is not taken for a fresh source. Chainlink pairs are given their heartbeat as
+/// maximum price age in the configuration.
+pub fn ticker_from_round(
+    aggregator: &AggregatorConfiguration,
+    decimals: u32,
+    round: &Round,
+    now: u64,
+) -> Result<events::Ticker, StaleRound> {
+    if round.updated_at.is_zero() {
+        return Err(StaleRound::Incomplete);
+    }
+    if round.answered_in_round < round.round_id {
+        return Err(StaleRound::CarriedOver {
+            round_id: round.round_id,
+            answered_in_round: round.answered_in_round,
+        });
+    }
+    // Prices are positive, zero and negative answers (high bit set) are rejected.
+    if round.answer.is_zero() || round.answer.bit(255) {
+        return Err(StaleRound::InvalidAnswer(round.answer));
+    }
+    let age = now.saturating_sub(round.updated_at.low_u64());
+    if age > aggregator.heartbeat {
+        return Err(StaleRound::Expired {
+            age,
+            heartbeat: aggregator.heartbeat,
+        });
+    }
+
+    Ok(events::Ticker {
+        exchange: Exchange::Chainlink,
+        pair: aggregator.pair.clone(),
+        price: Price::new(round.answer, decimals),
+        timestamp: round.updated_at.low_u64(),
+    })
+}
+
+struct Aggregator {
+    configuration: AggregatorConfiguration,
+    proxy: Contract<web3::transports::WebSocket>,
+    decimals: u32,
+    // Whether the latest round was stale, so that it is reported once rather than at every poll.
+    stale: bool,
+}
+
+pub struct Chainlink {
+    ethereum_provider_wss_url: String,
+    aggregators: Vec<AggregatorConfiguration>,
+    poll_interval: Duration,
+    answer_updated: H256,
+}
+
+impl Chainlink {
+    pub fn new(configuration: Configuration) -> Self {
+        let abi = web3::ethabi::Contract::load(AGGREGATOR_ABI.as_bytes()).unwrap();
+
+        Self {
+            ethereum_provider_wss_url: configuration.ethereum_provider_wss_url,
+            aggregators: configuration.aggregators,
+            poll_interval: configuration.poll_interval,
+            answer_updated: abi.event("AnswerUpdated").unwrap().signature(),
+        }
+    }
+
+    async fn read_latest_round(aggregator: &Aggregator) -> web3::contract::Result<Round> {
+        let (round_id, answer, _started_at, updated_at, answered_in_round): (
+            U256,
+            U256,
+            U256,
+            U256,
+            U256,
+        ) = aggregator
+            .proxy
+            .query("latestRoundData", (), None, Options::default(), None)
+            .await?;
+
+        Ok(Round {
+            round_id,
+            answer,
+            updated_at,
+            answered_in_round,
+        })
+    }
+
+    async fn send_round(
+        aggregator: &mut Aggregator,
+        round: &Round,
+        events_queue: &Sender<Event>,
+    ) -> Result<(), FeedError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        match ticker_from_round(&aggregator.configuration, aggregator.decimals, round, now) {
+            Ok(ticker) => {
+                aggregator.stale = false;
+                events_queue.send(Event::Ticker(ticker)).await?
+            }
+            Err(stale_round) => {
+                if !aggregator.stale {
+                    println!(
+                        "Cannot use Chainlink {:?} price: {}",
+                        aggregator.configuration.pair, stale_round
+                    );
+                }
+                aggregator.stale = true;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Feed for Chainlink {
+    fn name(&self) -> &str {
+        "chainlink"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        let ws = web3::transports::WebSocket::new(&self.ethereum_provider_wss_url).await?;
+        let web3 = web3::Web3::new(ws);
+
+        // Rounds are answered by the aggregator behind each proxy, which emits the logs.
+        let mut aggregators: HashMap<Address, Aggregator> = HashMap::new();
+        for configuration in self.aggregators.iter() {
+            let proxy =
+                Contract::from_json(web3.eth(), configuration.address, AGGREGATOR_ABI.as_bytes())?;
+            let decimals: u8 = proxy
+                .query("decimals", (), None, Options::default(), None)
+                .await?;
+            let aggregator_address: Address = proxy
+                .query("aggregator", (), None, Options::default(), None)
+                .await?;
+
+            aggregators.insert(
+                aggregator_address,
+                Aggregator {
+                    configuration: configuration.clone(),
+                    proxy,
+                    decimals: decimals as u32,
+                    stale: false,
+                },
+            );
+        }
+
+        let answers_filter = FilterBuilder::default()
+            .address(aggregators.keys().cloned().collect())
+            .topics(Some(vec![self.answer_updated]), None, None, None)
+            .build();
+        let mut sub = web3.eth_subscribe().subscribe_logs(answers_filter).await?;
+        health.connected();
+
+        let mut poll = tokio::time::interval(self.poll_interval);
+        loop {
+            tokio::select! {
+                log = sub.next() => {
+                    let log: Log = match log {
+                        Some(log) => log?,
+                        None => return Ok(()),
+                    };
+                    if log.removed == Some(true) {
+                        continue;
+                    }
+                    // The answer and the round id are indexed, the time is in the data.
+                    if let (Some(aggregator), [_, current, round_id]) =
+                        (aggregators.get_mut(&log.address), log.topics.as_slice())
+                    {
+                        let round_id = U256::from_big_endian(round_id.as_bytes());
+                        let round = Round {
+                            round_id,
+                            answer: U256::from_big_endian(current.as_bytes()),
+                            updated_at: U256::from_big_endian(&log.data.0),
+                            answered_in_round: round_id,
+                        };
+                        Chainlink::send_round(aggregator, &round, &events_queue).await?;
+                    }
+                }
+                _ = poll.tick() => {
+                    for aggregator in aggregators.values_mut() {
+                        let round = Chainlink::read_latest_round(aggregator).await?;
+                        Chainlink::send_round(aggregator, &round, &events_queue).await?;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,19 +1,30 @@
 use std::str::FromStr;
+use std::time::Duration;
 
+use async_trait::async_trait;
 use futures_util::sink::SinkExt;
+use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::StreamExt;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::deserialize_number_from_string;
 use serde_json::Value;
-use tokio_tungstenite::connect_async;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::Sender;
 use tokio_tungstenite::tungstenite::protocol;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 
 use crate::events;
-use crate::types::{CurrencyCode, Exchange, Pair};
+use crate::feeds::supervisor::{Feed, FeedError, FeedHealth};
+use crate::types::{CurrencyCode, Exchange, Pair, Price};
 use events::Event;
 
 const URL: &str = "wss://ws-feed.exchange.coinbase.com";
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsWrite = SplitSink<WsStream, protocol::Message>;
+type WsRead = SplitStream<WsStream>;
 
 #[derive(Debug, Serialize)]
 struct Channel {
@@ -41,8 +52,7 @@ struct Ticker {
     low_24h: f64,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     open_24h: f64,
-    #[serde(deserialize_with = "deserialize_number_from_string")]
-    price: f64,
+    price: String,
     product_id: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     sequence: u64,
@@ -63,85 +73,140 @@ struct Heartbeat {
     time: String,
 }
 
-pub async fn run(events_queue: tokio::sync::mpsc::Sender<Event>) {
-    let url = url::Url::parse(URL).unwrap();
-
-    let (ws_stream, _) = connect_async(url).await.unwrap();
-
-    let (mut ws_write, mut ws_read) = ws_stream.split();
-
-    let subscribe_request = SubscribeRequest {
-        channels: vec![
-            Channel {
-                name: String::from("heartbeat"),
-                product_ids: vec![String::from("ETH-USD"), String::from("DAI-USD")],
-            },
-            Channel {
-                name: String::from("ticker"),
-                product_ids: vec![String::from("ETH-USD"), String::from("DAI-USD")],
-            },
-        ],
-    };
-    let subscribe_request_json = serde_json::to_string(&subscribe_request).unwrap();
-    let subscribe_request_result = ws_write
-        .send(protocol::Message::text(subscribe_request_json))
-        .await;
-    if let Err(result) = subscribe_request_result {
-        // TODO write a retry mechanism
-        panic!("Error subscribing to Coinbase ws channels: {}", result);
-    }
-    let subscribe_response = ws_read.next().await.unwrap();
-    let _res = match subscribe_response {
-        Ok(protocol::Message::Text(payload)) => {
-            let _response: serde_json::Value = serde_json::from_str(&payload).unwrap();
-            // TODO parse coinbase subscribe response
-            Ok(())
+pub struct Coinbase {
+    product_ids: Vec<String>,
+}
+
+impl Coinbase {
+    pub fn new() -> Self {
+        Self {
+            product_ids: vec![String::from("ETH-USD"), String::from("DAI-USD")],
         }
-        Ok(_) => Err(()),
-        Err(_) => Err(()),
-    };
-
-    ws_read
-        .for_each(|message| async {
-            match message {
-                Ok(protocol::Message::Text(payload)) => {
-                    let msg: serde_json::Value = serde_json::from_str(&payload).unwrap();
-                    let msg_type = &msg["type"];
-                    if let Value::String(t) = msg_type {
-                        match t.as_str() {
-                            "heartbeat" => {
-                                let heartbeat: Heartbeat = serde_json::from_str(&payload).unwrap();
-                            }
-                            "ticker" => {
-                                let coinbase_ticker: Ticker =
-                                    serde_json::from_str(&payload).unwrap();
-                                let ticker = events::Ticker {
-                                    exchange: Exchange::Coinbase,
-                                    pair: parse_product_id(&coinbase_ticker.product_id),
-                                    price: coinbase_ticker.price,
-                                };
-                                let event = Event::Ticker(ticker);
-                                events_queue.send(event).await.unwrap();
-                            }
-                            _ => (),
-                        }
+    }
+
+    async fn subscribe(
+        &self,
+        ws_write: &mut WsWrite,
+        ws_read: &mut WsRead,
+    ) -> Result<(), FeedError> {
+        let subscribe_request = SubscribeRequest {
+            channels: vec![
+                Channel {
+                    name: String::from("heartbeat"),
+                    product_ids: self.product_ids.clone(),
+                },
+                Channel {
+                    name: String::from("ticker"),
+                    product_ids: self.product_ids.clone(),
+                },
+            ],
+        };
+        let subscribe_request_json = serde_json::to_string(&subscribe_request).unwrap();
+        ws_write
+            .send(protocol::Message::text(subscribe_request_json))
+            .await?;
+
+        match ws_read.next().await {
+            Some(Ok(protocol::Message::Text(payload))) => {
+                let response: serde_json::Value = serde_json::from_str(&payload)?;
+                match &response["type"] {
+                    Value::String(t) if t == "error" => {
+                        Err(format!("subscription refused: {}", response["message"]).into())
                     }
+                    _ => Ok(()),
                 }
-                Ok(_) => (),
-                Err(_) => (),
             }
-        })
-        .await;
+            Some(Ok(_)) => Err("unexpected subscription response".into()),
+            Some(Err(error)) => Err(error.into()),
+            None => Err("connection closed while subscribing".into()),
+        }
+    }
 }
 
-fn parse_product_id(product_id: &str) -> Pair {
-    // Parses a Coinbase product_id in the form e.g. BTC-USD.
-    if let Some((first, second)) = product_id.split("-").collect_tuple() {
-        return Pair(
-            CurrencyCode::from_str(first).unwrap(),
-            CurrencyCode::from_str(second).unwrap(),
-        );
-    } else {
-        panic!("Expected two elements")
+impl Default for Coinbase {
+    fn default() -> Self {
+        Self::new()
     }
 }
+
+#[async_trait]
+impl Feed for Coinbase {
+    fn name(&self) -> &str {
+        "coinbase"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        let url = url::Url::parse(URL).unwrap();
+
+        let (ws_stream, _) = connect_async(url).await?;
+
+        let (mut ws_write, mut ws_read) = ws_stream.split();
+
+        self.subscribe(&mut ws_write, &mut ws_read).await?;
+        health.connected();
+
+        // Heartbeats are received every second, silence means the connection is dead.
+        while let Some(message) = tokio::time::timeout(IDLE_TIMEOUT, ws_read.next())
+            .await
+            .map_err(|_| "no message received in time")?
+        {
+            match message? {
+                // A message which cannot be parsed is skipped, the next ticker will do.
+                protocol::Message::Text(payload) => match parse_message(&payload) {
+                    Ok(Some(ticker)) => events_queue.send(Event::Ticker(ticker)).await?,
+                    Ok(None) => (),
+                    Err(error) => println!("Skipping Coinbase message {} ({})", payload, error),
+                },
+                protocol::Message::Close(_) => break,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a ticker message, returning `None` for other messages, e.g. heartbeats.
+fn parse_message(payload: &str) -> Result<Option<events::Ticker>, FeedError> {
+    let msg: serde_json::Value = serde_json::from_str(payload)?;
+    match &msg["type"] {
+        Value::String(t) if t == "heartbeat" => {
+            let _heartbeat: Heartbeat = serde_json::from_value(msg)?;
+            Ok(None)
+        }
+        Value::String(t) if t == "ticker" => {
+            let coinbase_ticker: Ticker = serde_json::from_value(msg)?;
+            Ok(Some(events::Ticker {
+                exchange: Exchange::Coinbase,
+                pair: parse_product_id(&coinbase_ticker.product_id)?,
+                price: Price::from_str(&coinbase_ticker.price)
+                    .map_err(|()| format!("invalid price {}", coinbase_ticker.price))?,
+                timestamp: parse_time(&coinbase_ticker.time)?,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn parse_product_id(product_id: &str) -> Result<Pair, FeedError> {
+    // Parses a Coinbase product_id in the form e.g. BTC-USD.
+    let unknown_product = || format!("unknown product {}", product_id);
+    let (first, second) = product_id
+        .split('-')
+        .collect_tuple()
+        .ok_or_else(unknown_product)?;
+
+    Ok(Pair(
+        CurrencyCode::from_str(first).map_err(|()| unknown_product())?,
+        CurrencyCode::from_str(second).map_err(|()| unknown_product())?,
+    ))
+}
+
+fn parse_time(time: &str) -> Result<u64, FeedError> {
+    // Parses a Coinbase time in the form e.g. 2022-08-01T12:34:56.789012Z.
+    Ok(chrono::DateTime::parse_from_rfc3339(time)?.timestamp() as u64)
+}
@@ -1,7 +1,14 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
 use web3::futures::StreamExt;
-use web3::types::{BlockId, BlockNumber};
+use web3::types::{BlockHeader, BlockId, BlockNumber, H256};
 
 use crate::events;
+use crate::feeds::supervisor::{Feed, FeedError, FeedHealth};
+
+// Number of recent block hashes remembered to detect chain reorganisations.
+const RECENT_BLOCKS_DEPTH: u64 = 128;
 
 pub struct Configuration {
     pub ethereum_provider_wss_url: String,
@@ -9,12 +16,15 @@ pub struct Configuration {
 
 pub struct EthereumBlocks {
     ethereum_provider_wss_url: String,
+    // Hashes of the latest canonical blocks, by block number.
+    recent_blocks: BTreeMap<u64, H256>,
 }
 
 impl EthereumBlocks {
     pub fn new(configuration: &Configuration) -> Self {
         Self {
             ethereum_provider_wss_url: configuration.ethereum_provider_wss_url.clone(),
+            recent_blocks: BTreeMap::new(),
         }
     }
 
@@ -31,34 +41,122 @@ impl EthereumBlocks {
             .unwrap();
 
         Ok(events::BlockHeader {
+            number: latest_block.number.unwrap_or_default().as_u64(),
+            hash: latest_block.hash.unwrap_or_default(),
+            parent_hash: latest_block.parent_hash,
             timestamp: latest_block.timestamp,
+            base_fee_per_gas: latest_block.base_fee_per_gas,
         })
     }
 
     pub async fn run(
-        &self,
+        &mut self,
         events_queue: tokio::sync::mpsc::Sender<events::Event>,
-    ) -> web3::Result {
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
         let ws = web3::transports::WebSocket::new(&self.ethereum_provider_wss_url).await?;
         let web3 = web3::Web3::new(ws.clone());
 
-        let feed = web3.eth_subscribe().subscribe_new_heads().await?;
+        let mut feed = web3.eth_subscribe().subscribe_new_heads().await?;
+        health.connected();
 
-        feed.for_each(|block_header| async {
+        while let Some(block_header) = feed.next().await {
             println!("BLOCK HEADER => {:?}", block_header);
-            if let Ok(block_header) = block_header {
-                let block_header_event = events::Event::BlockHeader(events::BlockHeader {
-                    timestamp: block_header.timestamp,
-                });
-                events_queue.send(block_header_event).await.unwrap();
-            } else {
-                // TODO handle error case
+            let block_header = to_block_header_event(&block_header?);
+
+            let removed_blocks = self.removed_blocks(&web3, &block_header).await?;
+            if !removed_blocks.is_empty() {
+                println!(
+                    "Chain reorganisation, removed blocks => {:?}",
+                    removed_blocks
+                );
+                let chain_reorganized =
+                    events::Event::ChainReorganized(events::ChainReorganized { removed_blocks });
+                events_queue.send(chain_reorganized).await?;
             }
 
-            ()
-        })
-        .await;
+            self.recent_blocks
+                .insert(block_header.number, block_header.hash);
+            let oldest_block = block_header.number.saturating_sub(RECENT_BLOCKS_DEPTH);
+            self.recent_blocks = self.recent_blocks.split_off(&oldest_block);
+
+            events_queue
+                .send(events::Event::BlockHeader(block_header))
+                .await?;
+        }
 
         Ok(())
     }
+
+    /// Returns the hashes of the known blocks which are no longer canonical once `block_header`
+    /// is the new head, walking back the new chain until a known ancestor is found.
+    async fn removed_blocks(
+        &mut self,
+        web3: &web3::Web3<web3::transports::WebSocket>,
+        block_header: &events::BlockHeader,
+    ) -> web3::Result<Vec<H256>> {
+        let mut ancestor_number = block_header.number.saturating_sub(1);
+        let mut ancestor_hash = block_header.parent_hash;
+
+        // After a reconnection, first walk back the blocks missed in between to find out which
+        // block the new chain has at the height of the latest known block.
+        if let Some(&latest_known_number) = self.recent_blocks.keys().next_back() {
+            let oldest_number = block_header.number.saturating_sub(RECENT_BLOCKS_DEPTH);
+            while ancestor_number > latest_known_number.max(oldest_number) {
+                let ancestor = web3
+                    .eth()
+                    .block(BlockId::Hash(ancestor_hash))
+                    .await?
+                    .ok_or_else(|| web3::Error::InvalidResponse(String::from("unknown block")))?;
+                ancestor_hash = ancestor.parent_hash;
+                ancestor_number -= 1;
+            }
+        }
+
+        while let Some(known_hash) = self.recent_blocks.get(&ancestor_number) {
+            if *known_hash == ancestor_hash || ancestor_number == 0 {
+                break;
+            }
+
+            let ancestor = web3
+                .eth()
+                .block(BlockId::Hash(ancestor_hash))
+                .await?
+                .ok_or_else(|| web3::Error::InvalidResponse(String::from("unknown block")))?;
+            ancestor_hash = ancestor.parent_hash;
+            ancestor_number -= 1;
+        }
+
+        // Every known block above the common ancestor has been replaced.
+        Ok(self
+            .recent_blocks
+            .split_off(&(ancestor_number + 1))
+            .into_values()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Feed for EthereumBlocks {
+    fn name(&self) -> &str {
+        "ethereum_blocks"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: tokio::sync::mpsc::Sender<events::Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        EthereumBlocks::run(self, events_queue, health).await
+    }
+}
+
+fn to_block_header_event(block_header: &BlockHeader) -> events::BlockHeader {
+    events::BlockHeader {
+        number: block_header.number.unwrap_or_default().as_u64(),
+        hash: block_header.hash.unwrap_or_default(),
+        parent_hash: block_header.parent_hash,
+        timestamp: block_header.timestamp,
+        base_fee_per_gas: block_header.base_fee_per_gas,
+    }
 }
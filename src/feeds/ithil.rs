@@ -1,335 +1,551 @@
-use std::str::FromStr;
+use std::cmp;
+use std::collections::HashMap;
 
-use web3::ethabi::{EventParam, LogParam, ParamType, RawLog};
+use async_trait::async_trait;
+use web3::ethabi::{Contract, Event, EventParam, LogParam, ParamType, RawLog, Token};
 use web3::futures::StreamExt;
-use web3::types::{BlockNumber, FilterBuilder, Log, H160, H256, U64};
+use web3::types::{Address, BlockNumber, FilterBuilder, Log, H256, U256, U64};
 
 use crate::events;
-use events::{PositionWasClosed, PositionWasLiquidated, PositionWasOpened, RiskFactorWasUpdated};
+use crate::feeds::supervisor::{Feed, FeedError, FeedHealth};
+use crate::token_registry::{self, TokenRegistry};
+use events::{
+    LogMetadata, PositionWasClosed, PositionWasLiquidated, PositionWasOpened, RiskFactorWasUpdated,
+};
+
+pub struct StrategyConfiguration {
+    pub name: String,
+    pub address: Address,
+    pub abi: Contract,
+}
 
 pub struct Configuration {
     pub ethereum_provider_https_url: String,
     pub ethereum_provider_wss_url: String,
-    pub margin_trading_strategy_address: String,
-}
-
-fn make_position_was_opened_event() -> web3::ethabi::Event {
-    let position_was_opened_event_params = vec![
-        EventParam {
-            name: "id".to_string(),
-            kind: ParamType::Uint(256),
-            indexed: true,
-        },
-        EventParam {
-            name: "owner".to_string(),
-            kind: ParamType::Address,
-            indexed: true,
-        },
-        EventParam {
-            name: "owedToken".to_string(),
-            kind: ParamType::Address,
-            indexed: false,
-        },
-        EventParam {
-            name: "heldToken".to_string(),
-            kind: ParamType::Address,
-            indexed: false,
-        },
-        EventParam {
-            name: "collateralToken".to_string(),
-            kind: ParamType::Address,
-            indexed: false,
-        },
-        EventParam {
-            name: "collateral".to_string(),
-            kind: ParamType::Uint(256),
-            indexed: false,
-        },
-        EventParam {
-            name: "principal".to_string(),
-            kind: ParamType::Uint(256),
-            indexed: false,
-        },
-        EventParam {
-            name: "allowance".to_string(),
-            kind: ParamType::Uint(256),
-            indexed: false,
-        },
-        EventParam {
-            name: "fees".to_string(),
-            kind: ParamType::Uint(256),
-            indexed: false,
-        },
-        EventParam {
-            name: "createdAt".to_string(),
-            kind: ParamType::Uint(256),
-            indexed: false,
-        },
-    ];
-
-    web3::ethabi::Event {
-        name: "PositionWasOpened".to_string(),
-        inputs: position_was_opened_event_params,
-        anonymous: false,
-    }
+    pub deployment_block: u64,
+    /// Maximum number of blocks covered by a single `eth_getLogs` request.
+    pub log_page_size: u64,
+    pub strategies: Vec<StrategyConfiguration>,
+    /// Address of the vault, whose newly whitelisted tokens are added to the token registry.
+    pub vault_address: Option<Address>,
 }
 
-fn make_position_was_closed_event() -> web3::ethabi::Event {
-    let position_was_closed_event_params = vec![EventParam {
+pub fn make_position_was_liquidated_event() -> web3::ethabi::Event {
+    let position_was_liquidated_event_params = vec![EventParam {
         name: "id".to_string(),
         kind: ParamType::Uint(256),
         indexed: true,
     }];
 
     web3::ethabi::Event {
-        name: "PositionWasClosed".to_string(),
-        inputs: position_was_closed_event_params,
+        name: "PositionWasLiquidated".to_string(),
+        inputs: position_was_liquidated_event_params,
         anonymous: false,
     }
 }
 
-fn make_position_was_liquidated_event() -> web3::ethabi::Event {
-    let position_was_liquidated_event_params = vec![EventParam {
-        name: "id".to_string(),
-        kind: ParamType::Uint(256),
+fn make_token_was_whitelisted_event() -> web3::ethabi::Event {
+    let token_was_whitelisted_event_params = vec![EventParam {
+        name: "token".to_string(),
+        kind: ParamType::Address,
         indexed: true,
     }];
 
     web3::ethabi::Event {
-        name: "PositionWasLiquidated".to_string(),
-        inputs: position_was_liquidated_event_params,
+        name: "TokenWasWhitelisted".to_string(),
+        inputs: token_was_whitelisted_event_params,
         anonymous: false,
     }
 }
 
-fn make_risk_factor_was_updated_event() -> web3::ethabi::Event {
-    let risk_factor_was_updated_event_params = vec![
-        EventParam {
-            name: "token".to_string(),
-            kind: ParamType::Address,
-            indexed: true,
-        },
-        EventParam {
-            name: "newRiskFactor".to_string(),
-            kind: ParamType::Uint(256),
-            indexed: false,
-        },
-    ];
+/// Value of the parameter `name` of a decoded log.
+fn param(log_params: &[LogParam], name: &str) -> Option<Token> {
+    log_params
+        .iter()
+        .find(|param| param.name == name)
+        .map(|param| param.value.clone())
+}
 
-    web3::ethabi::Event {
-        name: "RiskFactorWasUpdated".to_string(),
-        inputs: risk_factor_was_updated_event_params,
-        anonymous: false,
-    }
+fn parse_position_was_opened_event(
+    log: LogMetadata,
+    strategy: Address,
+    log_params: &[LogParam],
+) -> Option<events::Event> {
+    let uint = |name: &str| param(log_params, name)?.into_uint();
+    let address = |name: &str| param(log_params, name)?.into_address();
+
+    Some(events::Event::PositionWasOpened(PositionWasOpened {
+        log,
+        strategy,
+        id: uint("id")?,
+        owner: address("owner")?,
+        owed_token: address("owedToken")?,
+        held_token: address("heldToken")?,
+        collateral_token: address("collateralToken")?,
+        collateral: uint("collateral")?,
+        principal: uint("principal")?,
+        allowance: uint("allowance")?,
+        fees: uint("fees")?,
+        created_at: uint("createdAt")?,
+    }))
 }
 
-fn parse_position_was_opened_event(log_params: &Vec<LogParam>) -> events::Event {
-    return events::Event::PositionWasOpened(PositionWasOpened {
-        id: log_params[0].value.clone().into_uint().unwrap(),
-        owner: log_params[1].value.clone().into_address().unwrap(),
-        owed_token: log_params[2].value.clone().into_address().unwrap(),
-        held_token: log_params[3].value.clone().into_address().unwrap(),
-        collateral_token: log_params[4].value.clone().into_address().unwrap(),
-        collateral: log_params[5].value.clone().into_uint().unwrap(),
-        principal: log_params[6].value.clone().into_uint().unwrap(),
-        allowance: log_params[7].value.clone().into_uint().unwrap(),
-        fees: log_params[8].value.clone().into_uint().unwrap(),
-        created_at: log_params[9].value.clone().into_uint().unwrap(),
-    });
+fn parse_position_was_closed_event(
+    log: LogMetadata,
+    strategy: Address,
+    log_params: &[LogParam],
+) -> Option<events::Event> {
+    Some(events::Event::PositionWasClosed(PositionWasClosed {
+        log,
+        strategy,
+        id: param(log_params, "id")?.into_uint()?,
+    }))
 }
 
-fn parse_position_was_closed_event(log_params: &Vec<LogParam>) -> events::Event {
-    events::Event::PositionWasClosed(PositionWasClosed {
-        id: log_params[0].value.clone().into_uint().unwrap(),
-    })
+fn parse_position_was_liquidated_event(
+    log: LogMetadata,
+    strategy: Address,
+    log_params: &[LogParam],
+) -> Option<events::Event> {
+    Some(events::Event::PositionWasLiquidated(
+        PositionWasLiquidated {
+            log,
+            strategy,
+            id: param(log_params, "id")?.into_uint()?,
+        },
+    ))
 }
 
-fn parse_position_was_liquidated_event(log_params: &Vec<LogParam>) -> events::Event {
-    events::Event::PositionWasLiquidated(PositionWasLiquidated {
-        id: log_params[0].value.clone().into_uint().unwrap(),
-    })
+fn parse_risk_factor_was_updated_event(
+    log: LogMetadata,
+    strategy: Address,
+    log_params: &[LogParam],
+) -> Option<events::Event> {
+    Some(events::Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+        log,
+        strategy,
+        token: param(log_params, "token")?.into_address()?,
+        new_risk_factor: param(log_params, "newRiskFactor")?.into_uint()?,
+    }))
 }
 
-fn parse_risk_factor_was_updated_event(log_params: &Vec<LogParam>) -> events::Event {
-    events::Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
-        token: log_params[0].value.clone().into_address().unwrap(),
-        new_risk_factor: log_params[0].value.clone().into_uint().unwrap(),
-    })
+type ParseLog = fn(LogMetadata, Address, &[LogParam]) -> Option<events::Event>;
+
+/// Events of a strategy, as declared in its own ABI. Strategies may lay out their events
+/// differently, so their logs are decoded with their own ABI and their parameters read by name.
+/// Strategies need not emit every event, e.g. only some of them have risk factors.
+pub struct StrategyEvents {
+    position_was_opened: Option<Event>,
+    position_was_closed: Option<Event>,
+    position_was_liquidated: Option<Event>,
+    risk_factor_was_updated: Option<Event>,
 }
 
-struct EventSignature {
-    position_was_opened: H256,
-    position_was_closed: H256,
-    position_was_liquidated: H256,
-    risk_factor_was_updated: H256,
+impl StrategyEvents {
+    pub fn from_abi(abi: &Contract) -> Self {
+        let event = |name: &str| abi.event(name).ok().cloned();
+
+        Self {
+            position_was_opened: event("PositionWasOpened"),
+            position_was_closed: event("PositionWasClosed"),
+            position_was_liquidated: event("PositionWasLiquidated"),
+            risk_factor_was_updated: event("RiskFactorWasUpdated"),
+        }
+    }
+
+    fn events(&self) -> Vec<(&Event, ParseLog)> {
+        let events: [(&Option<Event>, ParseLog); 4] = [
+            (&self.position_was_opened, parse_position_was_opened_event),
+            (&self.position_was_closed, parse_position_was_closed_event),
+            (
+                &self.position_was_liquidated,
+                parse_position_was_liquidated_event,
+            ),
+            (
+                &self.risk_factor_was_updated,
+                parse_risk_factor_was_updated_event,
+            ),
+        ];
+
+        events
+            .into_iter()
+            .filter_map(|(event, parse)| Some((event.as_ref()?, parse)))
+            .collect()
+    }
+
+    /// Signatures of the events the strategy emits.
+    pub fn topics(&self) -> Vec<H256> {
+        self.events()
+            .into_iter()
+            .map(|(event, _)| event.signature())
+            .collect()
+    }
+
+    /// Decodes a log emitted by `log.address`, returning `None` if it is not one of the events of
+    /// the strategy or does not match their layout.
+    pub fn parse(&self, log: &Log) -> Option<events::Event> {
+        let strategy = log.address;
+        let metadata = log_metadata(log)?;
+        let topic = *log.topics.first()?;
+
+        let (event, parse) = match self
+            .events()
+            .into_iter()
+            .find(|(event, _)| event.signature() == topic)
+        {
+            Some(event) => event,
+            None => {
+                println!("Unparsed data");
+                return None;
+            }
+        };
+
+        let raw_log = RawLog {
+            topics: log.topics.clone(),
+            data: log.data.0.clone(),
+        };
+        let parsed = event
+            .parse_log(raw_log)
+            .ok()
+            .and_then(|parsed| parse(metadata, strategy, &parsed.params));
+        if parsed.is_none() {
+            println!(
+                "Cannot decode {} log of strategy {:?}",
+                event.name, strategy
+            );
+        }
+
+        parsed
+    }
 }
 
 pub struct Ithil {
-    event_signature: EventSignature,
-    events_filter: web3::types::Filter,
-    margin_trading_strategy_contract: web3::contract::Contract<web3::transports::WebSocket>,
+    // Strategy contracts are indexed by address, as logs only tell us which contract emitted them.
+    strategies: HashMap<Address, StrategyEvents>,
+    topics: Vec<H256>,
+    log_page_size: u64,
+    ethereum_provider_wss_url: String,
     web3: web3::Web3<web3::transports::WebSocket>,
+    // First block whose logs may not have been sent yet, where the feed resumes after a
+    // reconnection.
+    next_block: u64,
+    seen_logs: SeenLogs,
+    vault: Option<Address>,
+    tokens: TokenRegistry,
 }
 
 impl Ithil {
-    pub async fn new(configuration: &Configuration) -> Result<Self, web3::Error> {
-        let ws = web3::transports::WebSocket::new(&configuration.ethereum_provider_wss_url).await?;
-        let web3 = web3::Web3::new(ws.clone());
+    pub async fn new(
+        configuration: &Configuration,
+        tokens: TokenRegistry,
+    ) -> Result<Self, web3::Error> {
+        let web3 = connect(&configuration.ethereum_provider_wss_url).await?;
 
         println!("Connected!");
-        println!("Configuring contract ...");
-
-        let margin_trading_strategy_contract_address =
-            H160::from_str(&configuration.margin_trading_strategy_address).unwrap();
-        let margin_trading_strategy_contract = web3::contract::Contract::from_json(
-            web3.eth(),
-            margin_trading_strategy_contract_address,
-            include_bytes!("../../deployed/goerli/abi/MarginTradingStrategy.json"),
-        )
-        .unwrap();
-
-        let position_was_opened_signature = margin_trading_strategy_contract
-            .abi()
-            .event("PositionWasOpened")
-            .unwrap()
-            .signature();
-        let position_was_closed_signature = margin_trading_strategy_contract
-            .abi()
-            .event("PositionWasClosed")
-            .unwrap()
-            .signature();
-        let position_was_liquidated_signature = margin_trading_strategy_contract
-            .abi()
-            .event("PositionWasLiquidated")
-            .unwrap()
-            .signature();
-        let risk_factor_was_updated_signature = margin_trading_strategy_contract
-            .abi()
-            .event("RiskFactorWasUpdated")
-            .unwrap()
-            .signature();
-
-        let events_filter = FilterBuilder::default()
-            .address(vec![margin_trading_strategy_contract.address()])
-            .from_block(BlockNumber::Number(U64::from(7200738 as i32)))
-            .to_block(BlockNumber::Latest)
-            .topics(
-                Some(vec![
-                    position_was_opened_signature,
-                    position_was_closed_signature,
-                    position_was_liquidated_signature,
-                    risk_factor_was_updated_signature,
-                ]),
-                None,
-                None,
-                None,
-            )
-            .build();
+        println!("Configuring contracts ...");
+
+        let mut strategies = HashMap::new();
+        for strategy in configuration.strategies.iter() {
+            println!("Strategy {} => {:?}", strategy.name, strategy.address);
+
+            strategies.insert(strategy.address, StrategyEvents::from_abi(&strategy.abi));
+        }
+
+        let mut topics: Vec<H256> = strategies
+            .values()
+            .flat_map(|strategy_events| strategy_events.topics())
+            .collect();
+        let vault = configuration.vault_address;
+        if vault.is_some() {
+            topics.push(make_token_was_whitelisted_event().signature());
+        }
+        topics.sort();
+        topics.dedup();
 
         Ok(Self {
-            event_signature: EventSignature {
-                position_was_opened: position_was_opened_signature,
-                position_was_closed: position_was_closed_signature,
-                position_was_liquidated: position_was_liquidated_signature,
-                risk_factor_was_updated: risk_factor_was_updated_signature,
-            },
-            events_filter,
-            margin_trading_strategy_contract,
+            strategies,
+            topics,
+            log_page_size: configuration.log_page_size,
+            ethereum_provider_wss_url: configuration.ethereum_provider_wss_url.clone(),
             web3,
+            next_block: configuration.deployment_block,
+            seen_logs: SeenLogs::default(),
+            vault,
+            tokens,
         })
     }
 
-    fn parse_event(&self, log: &Log) -> Option<events::Event> {
-        let raw_log = RawLog {
-            topics: log.topics.clone(),
-            data: log.data.0.clone(),
-        };
+    fn events_filter(&self) -> FilterBuilder {
+        FilterBuilder::default()
+            .address(self.strategies.keys().cloned().chain(self.vault).collect())
+            .topics(Some(self.topics.clone()), None, None, None)
+    }
 
-        match log.topics[0] {
-            s if s == self.event_signature.position_was_opened => {
-                let log_params = make_position_was_opened_event()
-                    .parse_log(raw_log)
-                    .unwrap()
-                    .params;
-                let position_was_opened = parse_position_was_opened_event(&log_params);
-                Some(position_was_opened)
-            }
-            s if s == self.event_signature.position_was_closed => {
-                let log_params = make_position_was_closed_event()
-                    .parse_log(raw_log)
-                    .unwrap()
-                    .params;
-                let position_was_closed = parse_position_was_closed_event(&log_params);
-                Some(position_was_closed)
-            }
-            s if s == self.event_signature.position_was_liquidated => {
-                let log_params = make_position_was_liquidated_event()
-                    .parse_log(raw_log)
-                    .unwrap()
-                    .params;
-                let position_was_liquidated = parse_position_was_liquidated_event(&log_params);
-                Some(position_was_liquidated)
-            }
-            s if s == self.event_signature.risk_factor_was_updated => {
-                let log_params = make_risk_factor_was_updated_event()
-                    .parse_log(raw_log)
-                    .unwrap()
-                    .params;
-                let risk_factor_was_updated = parse_risk_factor_was_updated_event(&log_params);
-                Some(risk_factor_was_updated)
-            }
-            _ => {
-                // TODO handle unknown event
-                println!("Unparsed data");
-                None
-            }
-        }
+    fn parse_event(&self, log: &Log) -> Option<events::Event> {
+        self.strategies.get(&log.address)?.parse(log)
     }
 
-    pub async fn bootstrap_positions_state(&self) -> web3::Result<Vec<events::Event>> {
-        let logs_filter = self
-            .web3
-            .eth_filter()
-            .create_logs_filter(self.events_filter.clone())
-            .await?;
+    /// Fetches the logs emitted between `from_block` and `to_block` included, in pages of
+    /// `log_page_size` blocks.
+    async fn fetch_logs(&self, from_block: u64, to_block: u64) -> web3::Result<Vec<Log>> {
+        let mut logs = vec![];
+        let mut page_start = from_block;
+        while page_start <= to_block {
+            let page_end = cmp::min(page_start + self.log_page_size - 1, to_block);
+            println!(
+                "Fetching logs from block {} to {} ...",
+                page_start, page_end
+            );
+
+            let logs_filter = self
+                .events_filter()
+                .from_block(BlockNumber::Number(U64::from(page_start)))
+                .to_block(BlockNumber::Number(U64::from(page_end)))
+                .build();
+            logs.extend(self.web3.eth().logs(logs_filter).await?);
+
+            page_start = page_end + 1;
+        }
 
-        let logs = logs_filter.logs().await?;
+        Ok(logs)
+    }
 
-        println!("Got logs => {:?}", logs);
+    /// Fetches all the events emitted from `from_block` up to the latest block, ending with the
+    /// last block they cover. The live feed then starts from the following block.
+    pub async fn bootstrap_positions_state(
+        &mut self,
+        from_block: u64,
+    ) -> web3::Result<Vec<events::Event>> {
+        let latest_block = self.web3.eth().block_number().await?.as_u64();
+
+        let mut events: Vec<events::Event> = vec![];
+        for log in self.fetch_logs(from_block, latest_block).await? {
+            self.learn_token(&log).await;
+            events.extend(self.parse_event(&log));
+        }
 
-        let events = logs
-            .into_iter()
-            .filter_map(|log| self.parse_event(&log))
-            .collect();
+        println!(
+            "Fetched {} events from block {} to {}",
+            events.len(),
+            from_block,
+            latest_block
+        );
 
-        println!("Events => {:?}", events);
+        events.push(events::Event::LogsSynced(latest_block));
+        self.next_block = latest_block + 1;
 
         Ok(events)
     }
 
+    /// Sends every event emitted since the last run, or since the bootstrap, then keeps listening
+    /// for new events.
+    ///
+    /// The subscription is opened before fetching the events emitted since then, so that nothing
+    /// emitted in between is lost. Logs received both ways are only sent once.
+    ///
+    /// The last block whose logs have all been sent is reported after the backfill and for every
+    /// new block, so that checkpoints never get ahead of the feed.
     pub async fn run(
-        &self,
+        &mut self,
         events_queue: tokio::sync::mpsc::Sender<events::Event>,
-    ) -> web3::Result {
-        let sub = self
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        self.web3 = connect(&self.ethereum_provider_wss_url).await?;
+
+        let mut sub = self
             .web3
             .eth_subscribe()
-            .subscribe_logs(self.events_filter.clone())
+            .subscribe_logs(self.events_filter().build())
             .await?;
+        let mut heads = self.web3.eth_subscribe().subscribe_new_heads().await?;
 
         println!("Got subscription id {:?}", sub.id());
+        health.connected();
 
-        sub.for_each(|msg| async {
-            if msg.is_ok() {
-                let log = msg.unwrap();
-                let parsed_event = self.parse_event(&log);
-                if let Some(event) = parsed_event {
-                    let _ = events_queue.send(event);
+        // Live logs are buffered by the subscription while the gap is backfilled.
+        loop {
+            let latest_block = self.web3.eth().block_number().await?.as_u64();
+            if latest_block < self.next_block {
+                break;
+            }
+
+            for log in self.fetch_logs(self.next_block, latest_block).await? {
+                self.forward_log(&log, &events_queue).await?;
+            }
+            self.next_block = latest_block + 1;
+        }
+        events_queue
+            .send(events::Event::LogsSynced(self.next_block - 1))
+            .await?;
+
+        loop {
+            tokio::select! {
+                // Logs received before a block header are sent before it.
+                biased;
+                log = sub.next() => {
+                    let log = match log {
+                        Some(log) => log?,
+                        None => return Ok(()),
+                    };
+                    self.forward_log(&log, &events_queue).await?;
+
+                    // Other logs of the same block may still be in flight, so that block is
+                    // fetched again if the connection drops.
+                    if let Some(block_number) = log.block_number {
+                        self.next_block = cmp::max(self.next_block, block_number.as_u64());
+                    }
+                }
+                head = heads.next() => {
+                    let head = match head {
+                        Some(head) => head?,
+                        None => return Ok(()),
+                    };
+                    // The logs of a block are published before the following block.
+                    if let Some(number) = head.number {
+                        let synced_block = number.as_u64().saturating_sub(1);
+                        events_queue
+                            .send(events::Event::LogsSynced(synced_block))
+                            .await?;
+                    }
                 }
             }
-        })
-        .await;
+        }
+    }
+
+    async fn forward_log(
+        &mut self,
+        log: &Log,
+        events_queue: &tokio::sync::mpsc::Sender<events::Event>,
+    ) -> Result<(), FeedError> {
+        // Logs of blocks dropped by a chain reorganisation are sent again with `removed` set.
+        if log.removed == Some(true) {
+            if let Some(metadata) = log_metadata(log) {
+                self.seen_logs.remove(&metadata);
+                events_queue
+                    .send(events::Event::LogWasRemoved(metadata))
+                    .await?;
+            }
+            return Ok(());
+        }
+
+        if !self.seen_logs.insert(log) {
+            return Ok(());
+        }
+
+        // Tokens are learned before any event of a position in them is sent.
+        self.learn_token(log).await;
+        if let Some(event) = self.parse_event(log) {
+            events_queue.send(event).await?;
+        }
 
         Ok(())
     }
+
+    /// Adds the token whitelisted by a vault log to the registry, reading its metadata from its
+    /// ERC20 contract.
+    async fn learn_token(&self, log: &Log) {
+        let token_was_whitelisted = make_token_was_whitelisted_event();
+        if self.vault != Some(log.address)
+            || log.topics.first() != Some(&token_was_whitelisted.signature())
+        {
+            return;
+        }
+
+        let raw_log = RawLog {
+            topics: log.topics.clone(),
+            data: log.data.0.clone(),
+        };
+        let address = match token_was_whitelisted
+            .parse_log(raw_log)
+            .ok()
+            .and_then(|parsed| param(&parsed.params, "token")?.into_address())
+        {
+            Some(address) => address,
+            None => {
+                println!("Cannot decode whitelisting log {:?}", log.transaction_hash);
+                return;
+            }
+        };
+        if self.tokens.contains(&address) {
+            return;
+        }
+
+        match token_registry::fetch_token(self.web3.eth(), address).await {
+            Ok(token) => {
+                println!("Token {:?} was whitelisted => {:?}", token.symbol, address);
+                self.tokens.insert(token);
+            }
+            Err(error) => println!("Cannot read token {:?}: {}", address, error),
+        }
+    }
+}
+
+#[async_trait]
+impl Feed for Ithil {
+    fn name(&self) -> &str {
+        "ithil"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: tokio::sync::mpsc::Sender<events::Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        Ithil::run(self, events_queue, health).await
+    }
+}
+
+async fn connect(
+    ethereum_provider_wss_url: &str,
+) -> web3::Result<web3::Web3<web3::transports::WebSocket>> {
+    let ws = web3::transports::WebSocket::new(ethereum_provider_wss_url).await?;
+    Ok(web3::Web3::new(ws))
+}
+
+fn log_metadata(log: &Log) -> Option<LogMetadata> {
+    Some(LogMetadata {
+        block_number: log.block_number?.as_u64(),
+        block_hash: log.block_hash?,
+        log_index: log.log_index?,
+    })
+}
+
+// Duplicates can only come from the overlap between backfilled and live logs, including after a
+// reconnection, so logs are forgotten once they are this many blocks old.
+pub const SEEN_LOGS_DEPTH: u64 = 256;
+
+/// Logs already forwarded, identified by `(block_hash, log_index)`.
+#[derive(Default)]
+pub struct SeenLogs {
+    logs: HashMap<(H256, U256), u64>,
+    latest_block: u64,
+}
+
+impl SeenLogs {
+    /// Records a log, returning `false` if it had already been seen.
+    pub fn insert(&mut self, log: &Log) -> bool {
+        let (block_hash, log_index, block_number) =
+            match (log.block_hash, log.log_index, log.block_number) {
+                (Some(block_hash), Some(log_index), Some(block_number)) => {
+                    (block_hash, log_index, block_number.as_u64())
+                }
+                // Pending logs cannot be told apart, let them through.
+                _ => return true,
+            };
+
+        if block_number > self.latest_block {
+            self.latest_block = block_number;
+            let oldest_block = block_number.saturating_sub(SEEN_LOGS_DEPTH);
+            self.logs
+                .retain(|_, block_number| *block_number >= oldest_block);
+        }
+
+        self.logs
+            .insert((block_hash, log_index), block_number)
+            .is_none()
+    }
+
+    pub fn remove(&mut self, metadata: &LogMetadata) {
+        self.logs.remove(&(metadata.block_hash, metadata.log_index));
+    }
 }
@@ -0,0 +1,157 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use futures_util::sink::SinkExt;
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc::Sender;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol;
+
+use crate::events;
+use crate::feeds::supervisor::{Feed, FeedError, FeedHealth};
+use crate::types::{CurrencyCode, Exchange, Pair, Price};
+use events::Event;
+
+const URL: &str = "wss://ws.kraken.com";
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize)]
+struct Subscription {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SubscribeRequest {
+    event: String,
+    pair: Vec<String>,
+    subscription: Subscription,
+}
+
+pub struct Kraken {
+    pairs: Vec<String>,
+}
+
+impl Kraken {
+    pub fn new() -> Self {
+        Self {
+            pairs: vec![
+                String::from("ETH/USD"),
+                String::from("XBT/USD"),
+                String::from("DAI/USD"),
+            ],
+        }
+    }
+}
+
+impl Default for Kraken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Feed for Kraken {
+    fn name(&self) -> &str {
+        "kraken"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        let (mut ws_stream, _) = connect_async(URL).await?;
+
+        let subscribe_request = SubscribeRequest {
+            event: String::from("subscribe"),
+            pair: self.pairs.clone(),
+            subscription: Subscription {
+                name: String::from("ticker"),
+            },
+        };
+        ws_stream
+            .send(protocol::Message::text(
+                serde_json::to_string(&subscribe_request).unwrap(),
+            ))
+            .await?;
+
+        // Heartbeats are received every second when there is no ticker, silence means the
+        // connection is dead.
+        while let Some(message) = tokio::time::timeout(IDLE_TIMEOUT, ws_stream.next())
+            .await
+            .map_err(|_| "no message received in time")?
+        {
+            let payload = match message? {
+                protocol::Message::Text(payload) => payload,
+                protocol::Message::Close(_) => break,
+                _ => continue,
+            };
+            // A message which cannot be parsed is skipped, the next ticker will do.
+            let msg: Value = match serde_json::from_str(&payload) {
+                Ok(msg) => msg,
+                Err(error) => {
+                    println!("Skipping Kraken message {} ({})", payload, error);
+                    continue;
+                }
+            };
+
+            match &msg {
+                // Events are objects, e.g. {"event": "subscriptionStatus", "status": "subscribed"}
+                Value::Object(event) => match (&event["event"], &event["status"]) {
+                    (Value::String(name), Value::String(status))
+                        if name == "subscriptionStatus" && status == "subscribed" =>
+                    {
+                        health.connected();
+                    }
+                    (Value::String(name), Value::String(status))
+                        if name == "subscriptionStatus" && status == "error" =>
+                    {
+                        return Err(
+                            format!("subscription refused: {}", event["errorMessage"]).into()
+                        );
+                    }
+                    _ => (),
+                },
+                // Tickers are arrays, e.g. [340, {"c": ["1234.50000", "0.1"], ...}, "ticker", "ETH/USD"]
+                Value::Array(ticker) if ticker.len() == 4 && ticker[2] == "ticker" => {
+                    let pair = match ticker[3].as_str().and_then(parse_pair) {
+                        Some(pair) => pair,
+                        None => continue,
+                    };
+                    let price = match ticker[1]["c"][0].as_str().map(Price::from_str) {
+                        Some(Ok(price)) => price,
+                        _ => continue,
+                    };
+                    // Kraken tickers carry no time, they are stamped on reception.
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs();
+                    let ticker = events::Ticker {
+                        exchange: Exchange::Kraken,
+                        pair,
+                        price,
+                        timestamp,
+                    };
+                    events_queue.send(Event::Ticker(ticker)).await?;
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_pair(pair: &str) -> Option<Pair> {
+    // Kraken uses XBT for BTC, which is used as a proxy for WBTC.
+    match pair {
+        "ETH/USD" => Some(Pair(CurrencyCode::WETH, CurrencyCode::USD)),
+        "XBT/USD" => Some(Pair(CurrencyCode::WBTC, CurrencyCode::USD)),
+        "DAI/USD" => Some(Pair(CurrencyCode::DAI, CurrencyCode::USD)),
+        _ => None,
+    }
+}
@@ -1,6 +1,16 @@
+pub mod binance;
+pub mod chainlink;
 pub mod coinbase;
 pub mod ethereum_blocks;
 pub mod ithil;
+pub mod kraken;
+pub mod supervisor;
+pub mod uniswap;
 
+pub use binance::Binance;
+pub use chainlink::Chainlink;
+pub use coinbase::Coinbase;
 pub use ethereum_blocks::EthereumBlocks;
 pub use ithil::Ithil;
+pub use kraken::Kraken;
+pub use uniswap::Uniswap;
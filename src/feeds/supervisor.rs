@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::{self, Sender};
+
+use crate::events::Event;
+
+pub type FeedError = Box<dyn Error + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Configuration {
+    /// Delay before the first reconnection attempt, doubled after every failed attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A source of events running over a connection which may drop at any time.
+#[async_trait]
+pub trait Feed: Send {
+    fn name(&self) -> &str;
+
+    /// Connects, subscribes and forwards events until the connection drops. Implementations call
+    /// `health.connected()` once subscribed, and pick up where the previous run stopped.
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FeedStatus {
+    Connecting,
+    Connected,
+    Disconnected { error: String, retry_in: Duration },
+}
+
+#[derive(Clone, Debug)]
+pub struct FeedState {
+    pub status: FeedStatus,
+    /// When the feed entered its current status.
+    pub since: SystemTime,
+    /// Number of times the connection was lost since startup.
+    pub disconnections: u64,
+    /// When the feed sent its latest event, if any.
+    pub last_event: Option<SystemTime>,
+}
+
+/// Status of a part of the bot other than its feeds, e.g. the executor.
+#[derive(Clone, Debug)]
+pub struct ComponentState {
+    pub healthy: bool,
+    pub detail: String,
+    /// When the component last reported its status.
+    pub updated_at: SystemTime,
+}
+
+/// Status of every feed and component, shared with the rest of the bot.
+#[derive(Clone, Default)]
+pub struct HealthRegistry {
+    feeds: Arc<RwLock<HashMap<String, FeedState>>>,
+    components: Arc<RwLock<HashMap<String, ComponentState>>>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, feed: &str) -> Option<FeedState> {
+        self.feeds.read().unwrap().get(feed).cloned()
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, FeedState> {
+        self.feeds.read().unwrap().clone()
+    }
+
+    /// Returns `true` if every registered feed is currently connected.
+    pub fn all_connected(&self) -> bool {
+        self.feeds
+            .read()
+            .unwrap()
+            .values()
+            .all(|state| state.status == FeedStatus::Connected)
+    }
+
+    pub fn component(&self, component: &str) -> Option<ComponentState> {
+        self.components.read().unwrap().get(component).cloned()
+    }
+
+    pub fn components(&self) -> HashMap<String, ComponentState> {
+        self.components.read().unwrap().clone()
+    }
+
+    /// Records the status of `component`, described by `detail`.
+    pub fn set_component(&self, component: &str, healthy: bool, detail: String) {
+        self.components.write().unwrap().insert(
+            String::from(component),
+            ComponentState {
+                healthy,
+                detail,
+                updated_at: SystemTime::now(),
+            },
+        );
+    }
+
+    fn set_status(&self, feed: &str, status: FeedStatus) {
+        let mut feeds = self.feeds.write().unwrap();
+        let state = feeds.entry(String::from(feed)).or_insert(FeedState {
+            status: FeedStatus::Connecting,
+            since: SystemTime::now(),
+            disconnections: 0,
+            last_event: None,
+        });
+        if let FeedStatus::Disconnected { .. } = status {
+            state.disconnections += 1;
+        }
+        state.status = status;
+        state.since = SystemTime::now();
+    }
+
+    fn received(&self, feed: &str) {
+        if let Some(state) = self.feeds.write().unwrap().get_mut(feed) {
+            state.last_event = Some(SystemTime::now());
+        }
+    }
+}
+
+/// Handle given to a running feed to report its status.
+#[derive(Clone)]
+pub struct FeedHealth {
+    name: String,
+    registry: HealthRegistry,
+}
+
+impl FeedHealth {
+    pub fn connected(&self) {
+        println!("Feed {} connected", self.name);
+        self.registry.set_status(&self.name, FeedStatus::Connected);
+    }
+
+    fn is_connected(&self) -> bool {
+        self.registry
+            .get(&self.name)
+            .is_some_and(|state| state.status == FeedStatus::Connected)
+    }
+}
+
+/// Exponential backoff between reconnection attempts.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(configuration: &Configuration) -> Self {
+        Self {
+            initial: configuration.initial_backoff,
+            max: configuration.max_backoff,
+            next: configuration.initial_backoff,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = self.next.saturating_mul(2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
+
+/// Runs `feed` forever, reconnecting with exponential backoff whenever its connection drops,
+/// fails or the feed panics. Returns once nobody listens to the events anymore.
+pub async fn supervise(
+    feed: impl Feed + 'static,
+    events_queue: Sender<Event>,
+    registry: HealthRegistry,
+    configuration: &Configuration,
+) {
+    let health = FeedHealth {
+        name: String::from(feed.name()),
+        registry: registry.clone(),
+    };
+    let mut backoff = Backoff::new(configuration);
+    // Every run is a task of its own, so that a panic is a disconnection rather than the end of
+    // the feed. The feed outlives the run, to pick up where it stopped.
+    let feed = Arc::new(tokio::sync::Mutex::new(feed));
+
+    // Events go through the supervisor, which records when the feed last sent one.
+    let (feed_tx, mut feed_rx) = mpsc::channel(1024);
+    let forwarded_queue = events_queue.clone();
+    let forwarded_registry = registry.clone();
+    let name = health.name.clone();
+    tokio::spawn(async move {
+        while let Some(event) = feed_rx.recv().await {
+            forwarded_registry.received(&name);
+            if forwarded_queue.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while !events_queue.is_closed() {
+        registry.set_status(&health.name, FeedStatus::Connecting);
+        let run = {
+            let feed = feed.clone();
+            let feed_tx = feed_tx.clone();
+            let health = health.clone();
+            tokio::spawn(async move { feed.lock().await.run(feed_tx, &health).await })
+        };
+        let result = run.await;
+
+        // A feed which managed to connect starts over with a short delay.
+        if health.is_connected() {
+            backoff.reset();
+        }
+
+        let error = match result {
+            Ok(Ok(())) => String::from("connection closed"),
+            Ok(Err(error)) => error.to_string(),
+            Err(error) => format!("feed stopped: {}", error),
+        };
+        let retry_in = backoff.next_delay();
+        println!(
+            "Feed {} disconnected ({}), reconnecting in {:?}",
+            health.name, error, retry_in
+        );
+        registry.set_status(&health.name, FeedStatus::Disconnected { error, retry_in });
+
+        tokio::time::sleep(retry_in).await;
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::Sender;
+use web3::contract::{Contract, Options};
+use web3::types::{Address, U256};
+
+use crate::events;
+use crate::feeds::supervisor::{Feed, FeedError, FeedHealth};
+use crate::token_registry::TokenRegistry;
+use crate::types::{CurrencyCode, Exchange, Pair, Ratio};
+use crate::uniswap_math;
+use events::Event;
+
+/// The parts of `IUniswapV3Pool` used by the feed.
+const POOL_ABI: &str = r#"[
+    {"type": "function", "name": "token0", "inputs": [], "outputs": [{"name": "", "type": "address"}], "stateMutability": "view"},
+    {"type": "function", "name": "token1", "inputs": [], "outputs": [{"name": "", "type": "address"}], "stateMutability": "view"},
+    {"type": "function", "name": "slot0", "inputs": [], "outputs": [
+        {"name": "sqrtPriceX96", "type": "uint160"},
+        {"name": "tick", "type": "int24"},
+        {"name": "observationIndex", "type": "uint16"},
+        {"name": "observationCardinality", "type": "uint16"},
+        {"name": "observationCardinalityNext", "type": "uint16"},
+        {"name": "feeProtocol", "type": "uint8"},
+        {"name": "unlocked", "type": "bool"}
+    ], "stateMutability": "view"},
+    {"type": "function", "name": "observe", "inputs": [{"name": "secondsAgos", "type": "uint32[]"}], "outputs": [
+        {"name": "tickCumulatives", "type": "int56[]"},
+        {"name": "secondsPerLiquidityCumulativeX128s", "type": "uint160[]"}
+    ], "stateMutability": "view"}
+]"#;
+
+/// Decimals of the published prices, pool prices being exact fractions.
+pub const PRICE_DECIMALS: u32 = 18;
+
+pub struct Configuration {
+    pub ethereum_provider_wss_url: String,
+    /// Addresses of the Uniswap V3 pools prices are read from.
+    pub pools: Vec<Address>,
+    /// Length in seconds of the time-weighted average price, 0 to read the spot price.
+    pub twap_window: u32,
+    /// Whether pools whose observations do not cover the TWAP window are priced with their spot
+    /// price rather than skipped.
+    pub spot_fallback: bool,
+    /// Interval between two reads of the pools.
+    pub poll_interval: Duration,
+}
+
+/// A pool and its two tokens, sorted by address as in Uniswap.
+#[derive(Clone, Debug)]
+pub struct Pool {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+}
+
+/// A swap through a single pool of a route.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hop {
+    pub pool: Address,
+    /// Whether token0 is priced in token1, rather than token1 in token0.
+    pub zero_for_one: bool,
+}
+
+/// Returns the shortest route from `from` to `to` through `pools`, so that tokens without a pool
+/// against `to` are priced through an intermediate token, e.g. WETH.
+pub fn find_route(pools: &[Pool], from: Address, to: Address) -> Option<Vec<Hop>> {
+    // Breadth-first search, remembering the hop which first reached every token.
+    let mut reached_by: HashMap<Address, Option<(Address, Hop)>> = HashMap::new();
+    reached_by.insert(from, None);
+    let mut queue = VecDeque::from([from]);
+
+    while let Some(token) = queue.pop_front() {
+        if token == to {
+            let mut route = vec![];
+            let mut current = to;
+            while let Some(Some((previous, hop))) = reached_by.get(&current) {
+                route.push(hop.clone());
+                current = *previous;
+            }
+            route.reverse();
+            return Some(route);
+        }
+
+        for pool in pools {
+            let (next, zero_for_one) = if pool.token0 == token {
+                (pool.token1, true)
+            } else if pool.token1 == token {
+                (pool.token0, false)
+            } else {
+                continue;
+            };
+            if let Entry::Vacant(entry) = reached_by.entry(next) {
+                let hop = Hop {
+                    pool: pool.address,
+                    zero_for_one,
+                };
+                entry.insert(Some((token, hop)));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+struct Route {
+    pair: Pair,
+    hops: Vec<Hop>,
+}
+
+pub struct Uniswap {
+    ethereum_provider_wss_url: String,
+    pools: Vec<Address>,
+    twap_window: u32,
+    spot_fallback: bool,
+    poll_interval: Duration,
+    tokens: TokenRegistry,
+}
+
+impl Uniswap {
+    pub fn new(configuration: Configuration, tokens: TokenRegistry) -> Self {
+        Self {
+            ethereum_provider_wss_url: configuration.ethereum_provider_wss_url,
+            pools: configuration.pools,
+            twap_window: configuration.twap_window,
+            spot_fallback: configuration.spot_fallback,
+            poll_interval: configuration.poll_interval,
+            tokens,
+        }
+    }
+
+    fn decimals(&self, token: &Address) -> u32 {
+        self.tokens.get(token).unwrap().decimals as u32
+    }
+
+    /// Returns the route of every known token to USDC, through the pools between known tokens.
+    fn routes(&self, pools: &[Pool]) -> Vec<Route> {
+        // XXX USDC is used as a proxy for USD.
+        let usdc = match self.tokens.find_by_symbol(&CurrencyCode::USDC) {
+            Some(usdc) => usdc.address,
+            None => return vec![],
+        };
+        let known_pools: Vec<Pool> = pools
+            .iter()
+            .filter(|pool| self.tokens.contains(&pool.token0) && self.tokens.contains(&pool.token1))
+            .cloned()
+            .collect();
+
+        self.tokens
+            .tokens()
+            .into_iter()
+            .filter(|token| token.address != usdc)
+            .filter_map(|token| {
+                Some(Route {
+                    hops: find_route(&known_pools, token.address, usdc)?,
+                    pair: Pair(token.symbol, CurrencyCode::USD),
+                })
+            })
+            .collect()
+    }
+
+    /// Reads the square root price of a pool, averaged over the TWAP window if any. Returns
+    /// `None` if the pool cannot be priced, e.g. its observations do not cover the TWAP window and
+    /// spot prices are not allowed. Only a lost connection is an error.
+    async fn read_sqrt_price(
+        &self,
+        pool: &Contract<web3::transports::WebSocket>,
+    ) -> Result<Option<U256>, FeedError> {
+        if self.twap_window > 0 {
+            match self.read_twap_sqrt_price(pool).await {
+                Ok(Ok(sqrt_price_x96)) => return Ok(Some(sqrt_price_x96)),
+                Ok(Err(reason)) => {
+                    println!(
+                        "Cannot read TWAP of Uniswap pool {:?}: {}",
+                        pool.address(),
+                        reason
+                    )
+                }
+                Err(error) if is_connection_error(&error) => return Err(error.into()),
+                // Calls revert with "OLD" when the observations are too recent for the window.
+                Err(error) => {
+                    println!(
+                        "Cannot read TWAP of Uniswap pool {:?}: {}",
+                        pool.address(),
+                        error
+                    )
+                }
+            }
+            if !self.spot_fallback {
+                return Ok(None);
+            }
+        }
+
+        let slot0: web3::contract::Result<(U256, U256, U256, U256, U256, U256, bool)> = pool
+            .query("slot0", (), None, Options::default(), None)
+            .await;
+        match slot0 {
+            Ok((sqrt_price_x96, _, _, _, _, _, _)) => Ok(Some(sqrt_price_x96)),
+            Err(error) if is_connection_error(&error) => Err(error.into()),
+            Err(error) => {
+                println!(
+                    "Cannot read price of Uniswap pool {:?}: {}",
+                    pool.address(),
+                    error
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads the square root price of a pool averaged over the TWAP window, or the reason it is
+    /// out of range.
+    async fn read_twap_sqrt_price(
+        &self,
+        pool: &Contract<web3::transports::WebSocket>,
+    ) -> web3::contract::Result<Result<U256, String>> {
+        let (tick_cumulatives, _): (Vec<U256>, Vec<U256>) = pool
+            .query(
+                "observe",
+                (vec![self.twap_window, 0u32],),
+                None,
+                Options::default(),
+                None,
+            )
+            .await?;
+        if tick_cumulatives.len() != 2 {
+            return Ok(Err(format!("{} observations", tick_cumulatives.len())));
+        }
+        // int56 values are sign extended, their low 64 bits are their two's complement.
+        let tick = uniswap_math::average_tick(
+            tick_cumulatives[0].low_u64() as i64,
+            tick_cumulatives[1].low_u64() as i64,
+            self.twap_window,
+        );
+
+        Ok(uniswap_math::sqrt_ratio_at_tick(tick).ok_or_else(|| format!("invalid tick {}", tick)))
+    }
+}
+
+#[async_trait]
+impl Feed for Uniswap {
+    fn name(&self) -> &str {
+        "uniswap"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        let ws = web3::transports::WebSocket::new(&self.ethereum_provider_wss_url).await?;
+        let web3 = web3::Web3::new(ws);
+
+        let mut pools = vec![];
+        let mut contracts = HashMap::new();
+        for address in self.pools.iter().copied() {
+            let contract = Contract::from_json(web3.eth(), address, POOL_ABI.as_bytes())?;
+            let token0: Address = contract
+                .query("token0", (), None, Options::default(), None)
+                .await?;
+            let token1: Address = contract
+                .query("token1", (), None, Options::default(), None)
+                .await?;
+            pools.push(Pool {
+                address,
+                token0,
+                token1,
+            });
+            contracts.insert(address, contract);
+        }
+
+        health.connected();
+
+        let mut poll = tokio::time::interval(self.poll_interval);
+        loop {
+            poll.tick().await;
+
+            // Routes are found again at every poll, as tokens may have been whitelisted since.
+            // Only pools between known tokens can be priced, their decimals being needed.
+            let routes = self.routes(&pools);
+
+            // Pools shared by several routes are read once per poll. Routes through a pool which
+            // cannot be priced are skipped until the next poll.
+            let mut sqrt_prices: HashMap<Address, Option<U256>> = HashMap::new();
+            'routes: for route in routes.iter() {
+                let mut price = Ratio::one();
+                for hop in route.hops.iter() {
+                    let sqrt_price_x96 = match sqrt_prices.get(&hop.pool) {
+                        Some(sqrt_price_x96) => *sqrt_price_x96,
+                        None => {
+                            let sqrt_price_x96 =
+                                self.read_sqrt_price(&contracts[&hop.pool]).await?;
+                            sqrt_prices.insert(hop.pool, sqrt_price_x96);
+                            sqrt_price_x96
+                        }
+                    };
+                    let sqrt_price_x96 = match sqrt_price_x96 {
+                        Some(sqrt_price_x96) => sqrt_price_x96,
+                        None => continue 'routes,
+                    };
+                    let pool = pools.iter().find(|pool| pool.address == hop.pool).unwrap();
+                    price = price.mul(&uniswap_math::pool_price(
+                        sqrt_price_x96,
+                        self.decimals(&pool.token0),
+                        self.decimals(&pool.token1),
+                        hop.zero_for_one,
+                    ));
+                }
+
+                let price = match price.to_price(PRICE_DECIMALS) {
+                    Some(price) => price,
+                    None => {
+                        println!("Cannot use Uniswap {:?} price: out of range", route.pair);
+                        continue;
+                    }
+                };
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                let ticker = events::Ticker {
+                    exchange: Exchange::Uniswap,
+                    pair: route.pair.clone(),
+                    price,
+                    timestamp,
+                };
+                events_queue.send(Event::Ticker(ticker)).await?;
+            }
+        }
+    }
+}
+
+/// Whether `error` comes from the connection to the node rather than from the call itself, in
+/// which case the feed needs to reconnect.
+pub fn is_connection_error(error: &web3::contract::Error) -> bool {
+    matches!(
+        error,
+        web3::contract::Error::Api(web3::Error::Transport(_) | web3::Error::Unreachable)
+    )
+}
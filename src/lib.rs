@@ -1,6 +1,15 @@
 pub mod events;
+pub mod executor;
 pub mod feeds;
 pub mod liquidation_bot;
 pub mod liquidator;
+pub mod metrics;
+pub mod oracle;
+pub mod profitability;
+pub mod readiness;
+pub mod store;
+pub mod token_registry;
 pub mod types;
+pub mod uniswap_math;
 pub mod utils;
+pub mod vault_math;
@@ -1,22 +1,17 @@
 use std::collections::HashMap;
-use std::str::FromStr;
-
-use secp256k1::SecretKey;
-
-use web3::contract::tokens::Tokenize;
-use web3::contract::Options;
-use web3::ethabi;
-use web3::signing::SecretKeyRef;
-use web3::types::H160;
 
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
 
-use web3::types::Address;
-
 use crate::events;
+use crate::executor;
 use crate::feeds;
+use crate::feeds::supervisor::{self, Feed, HealthRegistry};
 use crate::liquidator;
+use crate::metrics::{self, Metrics};
+use crate::readiness;
+use crate::store::{Checkpoint, Store};
+use crate::token_registry::TokenRegistry;
 use crate::types;
 use crate::types::Token;
 use events::Event;
@@ -24,84 +19,147 @@ use liquidator::Liquidator;
 use types::Liquidation;
 
 pub struct Configuration {
-    pub liquidator_address: String,
     pub ethereum_feed_configuration: feeds::ethereum_blocks::Configuration,
+    pub chainlink_feed_configuration: feeds::chainlink::Configuration,
+    pub uniswap_feed_configuration: feeds::uniswap::Configuration,
     pub ithil_feed_configuration: feeds::ithil::Configuration,
-    pub secret: String,
+    pub supervisor_configuration: supervisor::Configuration,
+    pub liquidator_configuration: liquidator::Configuration,
+    pub executor_configuration: executor::Configuration,
+    pub readiness_configuration: readiness::Configuration,
+    pub store_path: String,
+    pub checkpoint_interval: u64,
     pub tokens: Vec<Token>,
 }
 
-pub async fn run(configuration: Configuration) {
+/// Runs the bot, reporting the status of every feed to `health` and its activity to `metrics`.
+pub async fn run(configuration: Configuration, health: HealthRegistry, metrics: Metrics) {
     let (tx, mut rx): (Sender<Event>, Receiver<Event>) = mpsc::channel(1024);
 
-    let tokens: HashMap<Address, Token> = configuration
-        .tokens
-        .into_iter()
-        .map(|token| (token.address, token))
-        .collect();
+    // Tokens whitelisted after the deployment are learned by the Ithil feed.
+    let tokens = TokenRegistry::new(configuration.tokens);
+    let has_uniswap_pools = !configuration.uniswap_feed_configuration.pools.is_empty();
+    let uniswap_feed =
+        feeds::Uniswap::new(configuration.uniswap_feed_configuration, tokens.clone());
 
     // 0. Get block events from Ethereum network
     // This feed helps to keep a synchronized clock with the blockchain.
+    // Every feed is supervised, i.e. reconnected with backoff whenever its connection drops.
     let ethereum_blocks_feed: feeds::EthereumBlocks =
         feeds::EthereumBlocks::new(&configuration.ethereum_feed_configuration);
 
     let latest_block = ethereum_blocks_feed.get_latest_block().await.unwrap();
 
-    let tx_ethereum = tx.clone();
-    tokio::spawn(async move {
-        ethereum_blocks_feed.run(tx_ethereum).await.unwrap();
-    });
+    spawn_feed(
+        ethereum_blocks_feed,
+        &tx,
+        &health,
+        &configuration.supervisor_configuration,
+    );
 
-    let margin_trading_strategy_address = Address::from_str(
-        &configuration
-            .ithil_feed_configuration
-            .margin_trading_strategy_address,
-    )
-    .unwrap();
-    let mut liquidator = Liquidator::new(latest_block, margin_trading_strategy_address, tokens);
+    let mut liquidator = Liquidator::new(
+        configuration.liquidator_configuration,
+        latest_block,
+        tokens.clone(),
+    );
 
-    // 1. Set up Ithil Ethereum events feed from Ithil smart contract.
+    // 1. Set up Ithil Ethereum events feed from Ithil strategy contracts.
     //    This feed should be used to keep track of open positions and their state.
-    let ithil_feed: feeds::Ithil = feeds::Ithil::new(&configuration.ithil_feed_configuration)
-        .await
-        .unwrap();
+    let mut ithil_feed: feeds::Ithil =
+        feeds::Ithil::new(&configuration.ithil_feed_configuration, tokens)
+            .await
+            .unwrap();
+
+    // 2. Build current positions from the last checkpoint and the events emitted since then
+    let store = Store::open(&configuration.store_path).unwrap();
+    let from_block = match store.load_checkpoint().unwrap() {
+        Some(checkpoint) => {
+            println!(
+                "Resuming from checkpoint at block {}",
+                checkpoint.block_number
+            );
+            liquidator.restore(checkpoint.state);
+            checkpoint.block_number + 1
+        }
+        None => configuration.ithil_feed_configuration.deployment_block,
+    };
 
-    // 2. Build current position from past events
-    ithil_feed
-        .bootstrap_positions_state()
+    let past_events = ithil_feed
+        .bootstrap_positions_state(from_block)
         .await
-        .unwrap()
-        .into_iter()
-        .for_each(|event| {
-            println!("Event => {:?}", event);
-            liquidator.run(&event);
-        });
-
-    // 3. Listen for new events
-    let tx_ithil = tx.clone();
-    tokio::spawn(async move {
-        ithil_feed.run(tx_ithil).await.unwrap();
-    });
+        .unwrap();
+    for event in past_events.iter() {
+        liquidator.run(event);
+    }
 
-    // 4. Set up Coinbase feed to get real time prices.
-    //    Eventually we may use multiple exchanges, including DEXes, to make the bot more robust.
-    println!("Setup Coinbase feed ...");
-    let tx_coinbase = tx.clone();
-    tokio::spawn(async move {
-        feeds::coinbase::run(tx_coinbase).await;
-    });
+    // Only finalized events are checkpointed, the others are fetched again on restart.
+    store
+        .save_checkpoint(&Checkpoint {
+            block_number: liquidator.checkpoint_block(),
+            state: liquidator.snapshot(),
+        })
+        .unwrap();
+    health.set_component(
+        readiness::BOOTSTRAP,
+        true,
+        format!("{} open positions", liquidator.open_positions().len()),
+    );
+
+    // 3. Listen for new events, starting right after the bootstrap so that none is missed
+    spawn_feed(
+        ithil_feed,
+        &tx,
+        &health,
+        &configuration.supervisor_configuration,
+    );
+
+    // 4. Set up exchange feeds to get real time prices.
+    //    Prices from every exchange are aggregated, so that a single faulty source cannot
+    //    trigger liquidations.
+    println!("Setup exchange feeds ...");
+    let supervisor_configuration = &configuration.supervisor_configuration;
+    spawn_feed(
+        feeds::Coinbase::new(),
+        &tx,
+        &health,
+        supervisor_configuration,
+    );
+    spawn_feed(
+        feeds::Binance::new(),
+        &tx,
+        &health,
+        supervisor_configuration,
+    );
+    spawn_feed(feeds::Kraken::new(), &tx, &health, supervisor_configuration);
+    // On-chain prices are those the protocol itself trusts.
+    if !configuration
+        .chainlink_feed_configuration
+        .aggregators
+        .is_empty()
+    {
+        let chainlink_feed = feeds::Chainlink::new(configuration.chainlink_feed_configuration);
+        spawn_feed(chainlink_feed, &tx, &health, supervisor_configuration);
+    }
+    // Long-tail tokens are only traded on-chain.
+    if has_uniswap_pools {
+        spawn_feed(uniswap_feed, &tx, &health, supervisor_configuration);
+    }
 
     // 5. Set up a thread to execute liquidation commands
+    //    Liquidations which are not sent, e.g. because they would revert, are reported back as
+    //    events.
     let (liquidation_tx, liquidation_rx): (Sender<Liquidation>, Receiver<Liquidation>) =
         mpsc::channel(1024);
+    let executor_tx = tx.clone();
+    let executor_metrics = metrics.clone();
+    let executor_health = health.clone();
     tokio::spawn(async move {
-        liquidate_positions(
+        executor::liquidate_positions(
             liquidation_rx,
-            &configuration
-                .ithil_feed_configuration
-                .ethereum_provider_wss_url,
-            &configuration.liquidator_address,
-            &configuration.secret,
+            executor_tx,
+            executor_metrics,
+            executor_health,
+            &configuration.executor_configuration,
         )
         .await
         .unwrap();
@@ -113,62 +171,45 @@ pub async fn run(configuration: Configuration) {
     while let Some(event) = rx.recv().await {
         // println!("{:?}", event);
         let liquidations = liquidator.run(&event);
+        metrics.observe(&event, metrics::now());
+
+        if let Event::BlockHeader(block_header) = &event {
+            // Positions are counted once per block, scoring all of them on every price is costly.
+            let mut open_positions = HashMap::new();
+            for position in liquidator.open_positions() {
+                *open_positions.entry(position.strategy).or_insert(0) += 1;
+            }
+            metrics.set_positions(&open_positions, liquidator.underwater_positions());
+
+            if block_header.number % configuration.checkpoint_interval == 0 {
+                // The snapshot leaves out the events which could still be reverted by a chain
+                // reorganisation, they are fetched again on restart.
+                let checkpoint = Checkpoint {
+                    block_number: liquidator.checkpoint_block(),
+                    state: liquidator.snapshot(),
+                };
+                if let Err(error) = store.save_checkpoint(&checkpoint) {
+                    println!("Error saving checkpoint: {}", error);
+                }
+            }
+        }
+
         for liquidation in liquidations {
             liquidation_tx.send(liquidation).await.unwrap();
         }
     }
 }
 
-impl Tokenize for Liquidation {
-    fn into_tokens(self) -> Vec<ethabi::Token> {
-        vec![
-            ethabi::Token::Address(self.strategy),
-            ethabi::Token::Int(self.position_id),
-        ]
-    }
-}
-
-async fn liquidate_positions(
-    mut liquidation_rx: Receiver<Liquidation>,
-    ethereum_provider_wss_url: &String,
-    liquidator_address: &String,
-    secret: &String,
-) -> web3::Result {
-    let ws = web3::transports::WebSocket::new(ethereum_provider_wss_url).await?;
-    let web3 = web3::Web3::new(ws.clone());
-
-    let liquidator_contract_address = H160::from_str(&liquidator_address).unwrap();
-    let liquidator_contract = web3::contract::Contract::from_json(
-        web3.eth(),
-        liquidator_contract_address,
-        include_bytes!("../deployed/goerli/abi/Liquidator.json"),
-    )
-    .unwrap();
-
-    while let Some(liquidation) = liquidation_rx.recv().await {
-        println!("LIQUIDATION => {:?}", liquidation);
-        let receipt = liquidator_contract
-            .signed_call_with_confirmations(
-                "liquidateSingle",
-                liquidation,
-                Options {
-                    gas: None,
-                    gas_price: None,
-                    value: None,
-                    nonce: None,
-                    condition: None,
-                    transaction_type: None,
-                    access_list: None,
-                    max_fee_per_gas: None,
-                    max_priority_fee_per_gas: None,
-                },
-                3,
-                SecretKeyRef::new(&SecretKey::from_str(secret).unwrap()),
-            )
-            .await
-            .unwrap();
-        println!("LIQUIDATION RECEIPT => {:?}", receipt);
-    }
-
-    Ok(())
+fn spawn_feed(
+    feed: impl Feed + 'static,
+    tx: &Sender<Event>,
+    health: &HealthRegistry,
+    supervisor_configuration: &supervisor::Configuration,
+) {
+    let tx = tx.clone();
+    let health = health.clone();
+    let supervisor_configuration = supervisor_configuration.clone();
+    tokio::spawn(async move {
+        supervisor::supervise(feed, tx, health, &supervisor_configuration).await;
+    });
 }
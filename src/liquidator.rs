@@ -1,17 +1,24 @@
-use std::{collections::HashMap, str::FromStr};
+use std::collections::HashMap;
 
 use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
 use web3::types::{Address, U256};
 
 use crate::events;
 use events::{
-    BlockHeader, Event, PositionWasClosed, PositionWasLiquidated, PositionWasOpened,
-    RiskFactorWasUpdated, Ticker,
+    BlockHeader, ChainReorganized, Event, LiquidationSucceeded, LogMetadata, PositionWasClosed,
+    PositionWasLiquidated, PositionWasOpened, RiskFactorWasUpdated, Ticker,
 };
 
-use crate::types::{CurrencyCode, Liquidation, Pair, Token};
+use crate::oracle::{self, Conversion, Oracle, OracleError};
+use crate::profitability;
+use crate::token_registry::TokenRegistry;
+use crate::types::{CurrencyCode, Exchange, Liquidation, Pair, PricePoint, RequiredFunds, Token};
+use crate::vault_math;
 
-#[derive(Debug, PartialEq)]
+const ETH_DECIMALS: u32 = 18;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum PositionStatus {
     Opened,
     Closed,
@@ -19,8 +26,9 @@ pub enum PositionStatus {
     LiquidationRequested,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Position {
+    pub strategy: Address,
     pub id: U256,
     pub owner: Address,
     pub owed_token: Address,
@@ -35,37 +43,196 @@ pub struct Position {
     pub status: PositionStatus,
 }
 
+/// The part of the liquidator state rebuilt from on-chain events, which can be persisted and
+/// restored instead of replaying the whole event history.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LiquidatorState {
+    pub positions: Vec<Position>,
+    pub risk_factors: Vec<(Address, Address, U256)>,
+    /// Tokens learned since the deployment, whose whitelisting events are not fetched again.
+    #[serde(default)]
+    pub tokens: Vec<Token>,
+}
+
+pub struct Configuration {
+    /// Number of blocks after which an event can no longer be undone by a chain reorganisation.
+    pub confirmations: u64,
+    /// Age in seconds after which a price is too old to liquidate positions, unless overridden
+    /// for its pair in `max_price_ages`.
+    pub max_price_age: u64,
+    pub max_price_ages: HashMap<Pair, u64>,
+    /// Age in seconds after which a Chainlink price is too old, i.e. the heartbeat of its feed.
+    /// Other sources of the pair keep their own maximum age.
+    pub chainlink_max_price_ages: HashMap<Pair, u64>,
+    pub oracle: oracle::Configuration,
+    /// Share of the collateral of a position paid to its liquidator, in basis points, if known.
+    pub liquidation_reward: Option<u32>,
+    /// Number of failed liquidations of a position after which it is not liquidated anymore.
+    pub max_liquidation_attempts: u32,
+    /// Delay in seconds before liquidating a position again after a failure, doubled after every
+    /// failure.
+    pub liquidation_retry_backoff: u64,
+    /// Positions whose liquidation would make too little are left alone. Positions whose reward or
+    /// gas cost cannot be estimated, e.g. on networks without a base fee, are still liquidated.
+    pub profitability: profitability::Configuration,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            confirmations: 12,
+            max_price_age: 60,
+            max_price_ages: HashMap::new(),
+            chainlink_max_price_ages: HashMap::new(),
+            oracle: oracle::Configuration::default(),
+            liquidation_reward: None,
+            max_liquidation_attempts: 5,
+            liquidation_retry_backoff: 60,
+            profitability: profitability::Configuration::default(),
+        }
+    }
+}
+
+// Position ids are only unique within a strategy, and each strategy has its own risk factors.
+type PositionKey = (Address, U256);
+// Risk factors are recorded by token address, as they may be set before the token is known.
+type RiskFactorKey = (Address, Address);
+
+/// How to revert the effect of an event: the previous value of the entry it changed.
+#[derive(Debug)]
+enum Undo {
+    Position(PositionKey, Option<Box<Position>>),
+    RiskFactor(RiskFactorKey, Option<U256>),
+}
+
+/// Failed liquidations of a position.
+#[derive(Debug, Default)]
+struct LiquidationAttempts {
+    failures: u32,
+    // Time before which the position is not liquidated again.
+    retry_at: u64,
+}
+
+#[derive(Debug)]
+struct JournalEntry {
+    event: Event,
+    undo: Undo,
+}
+
 pub struct Liquidator {
+    configuration: Configuration,
     latest_block: BlockHeader,
-    open_positions: HashMap<U256, Position>,
-    prices: HashMap<Pair, f64>,
-    risk_factors: HashMap<CurrencyCode, web3::types::U256>,
-    strategy_address: Address,
-    tokens: HashMap<Address, Token>,
+    open_positions: HashMap<PositionKey, Position>,
+    oracle: Oracle,
+    // Timestamp of the most recent price, which may be ahead of the latest block.
+    latest_price_timestamp: u64,
+    risk_factors: HashMap<RiskFactorKey, web3::types::U256>,
+    tokens: TokenRegistry,
+    liquidation_attempts: HashMap<PositionKey, LiquidationAttempts>,
+    // Last block whose logs have all been received.
+    synced_block: u64,
+    // Events from blocks which are not checkpointed yet, in the order they were applied.
+    journal: Vec<JournalEntry>,
 }
 
-const VAULT_RESOLUTION: u32 = 10000;
-const VAULT_TIME_FEE_PERIOD: u32 = 86400;
-
 impl Liquidator {
     pub fn new(
+        configuration: Configuration,
         latest_block: BlockHeader,
-        strategy_address: Address,
-        tokens: HashMap<Address, Token>,
+        tokens: TokenRegistry,
     ) -> Self {
         Liquidator {
+            oracle: Oracle::new(configuration.oracle.clone()),
+            configuration,
             latest_block,
             open_positions: HashMap::new(),
-            prices: HashMap::new(),
+            latest_price_timestamp: 0,
             risk_factors: HashMap::new(),
-            strategy_address,
             tokens,
+            liquidation_attempts: HashMap::new(),
+            synced_block: 0,
+            journal: vec![],
+        }
+    }
+
+    /// Latest block whose events cannot be reverted anymore.
+    pub fn finalized_block(&self) -> u64 {
+        self.latest_block
+            .number
+            .saturating_sub(self.configuration.confirmations)
+    }
+
+    /// Latest block whose events can be checkpointed: it is final and all its logs have been
+    /// received, even if the feed of logs lags behind the blocks.
+    pub fn checkpoint_block(&self) -> u64 {
+        self.finalized_block().min(self.synced_block)
+    }
+
+    /// Returns the state as of `checkpoint_block`, i.e. without the effects of the events which
+    /// could still be reverted or whose block is not fully received.
+    pub fn snapshot(&self) -> LiquidatorState {
+        let checkpoint_block = self.checkpoint_block();
+        let mut open_positions = self.open_positions.clone();
+        let mut risk_factors = self.risk_factors.clone();
+        for entry in self
+            .journal
+            .iter()
+            .rev()
+            .take_while(|entry| is_after(entry, checkpoint_block))
+        {
+            undo(&mut open_positions, &mut risk_factors, &entry.undo);
+        }
+
+        LiquidatorState {
+            positions: open_positions.into_values().collect(),
+            risk_factors: risk_factors
+                .into_iter()
+                .map(|((strategy, token), risk_factor)| (strategy, token, risk_factor))
+                .collect(),
+            tokens: self.tokens.tokens(),
+        }
+    }
+
+    pub fn restore(&mut self, state: LiquidatorState) {
+        // Liquidations requested before the snapshot may never have been sent, so every position
+        // is considered again.
+        self.open_positions = state
+            .positions
+            .into_iter()
+            .map(|position| {
+                (
+                    (position.strategy, position.id),
+                    Position {
+                        status: PositionStatus::Opened,
+                        ..position
+                    },
+                )
+            })
+            .collect();
+        self.risk_factors = state
+            .risk_factors
+            .into_iter()
+            .map(|(strategy, token, risk_factor)| ((strategy, token), risk_factor))
+            .collect();
+        for token in state.tokens {
+            self.tokens.insert(token);
         }
     }
 
     pub fn run(&mut self, event: &Event) -> Vec<Liquidation> {
+        match event.log() {
+            Some(log) => self.apply_log_event(log, event),
+            None => self.apply(event),
+        }
+    }
+
+    fn apply(&mut self, event: &Event) -> Vec<Liquidation> {
         match event {
             Event::BlockHeader(block_header) => self.on_block_header(block_header),
+            Event::ChainReorganized(chain_reorganized) => {
+                self.on_chain_reorganized(chain_reorganized)
+            }
+            Event::LogWasRemoved(log) => self.on_log_removed(log),
             Event::PositionWasClosed(position_was_closed) => {
                 self.on_position_closed(position_was_closed)
             }
@@ -78,18 +245,137 @@ impl Liquidator {
             Event::RiskFactorWasUpdated(risk_factor_was_updated) => {
                 self.on_risk_factor_updated(risk_factor_was_updated)
             }
+            Event::LogsSynced(block_number) => {
+                self.synced_block = self.synced_block.max(*block_number);
+                vec![]
+            }
             Event::Ticker(ticker) => self.on_price_ticker(ticker),
+            Event::LiquidationSucceeded(liquidation_succeeded) => {
+                self.on_liquidation_succeeded(liquidation_succeeded)
+            }
+            Event::LiquidationFailed(liquidation_failed) => self.retry_liquidation_later(
+                (liquidation_failed.strategy, liquidation_failed.position_id),
+                &format!("failed: {}", liquidation_failed.reason),
+            ),
+            Event::LiquidationTimedOut(liquidation_timed_out) => self.retry_liquidation_later(
+                (
+                    liquidation_timed_out.strategy,
+                    liquidation_timed_out.position_id,
+                ),
+                "timed out",
+            ),
+        }
+    }
+
+    /// Applies an event decoded from a log, keeping track of how to revert it until its block is
+    /// final.
+    fn apply_log_event(&mut self, log: &LogMetadata, event: &Event) -> Vec<Liquidation> {
+        let undo = match event {
+            Event::PositionWasOpened(PositionWasOpened { strategy, id, .. })
+            | Event::PositionWasClosed(PositionWasClosed { strategy, id, .. })
+            | Event::PositionWasLiquidated(PositionWasLiquidated { strategy, id, .. }) => {
+                let key = (*strategy, *id);
+                let previous_position = self.open_positions.get(&key).cloned().map(Box::new);
+                Undo::Position(key, previous_position)
+            }
+            Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+                strategy, token, ..
+            }) => {
+                let key = (*strategy, *token);
+                let previous_risk_factor = self.risk_factors.get(&key).cloned();
+                Undo::RiskFactor(key, previous_risk_factor)
+            }
+            _ => unreachable!("only contract events have a log"),
+        };
+
+        let liquidations = self.apply(event);
+
+        if log.block_number > self.finalized_block() {
+            self.journal.push(JournalEntry {
+                event: event.clone(),
+                undo,
+            });
+        }
+
+        liquidations
+    }
+
+    /// Reverts the events matching `is_removed`, along with the events applied after them which
+    /// are then applied again.
+    fn roll_back(&mut self, is_removed: impl Fn(&LogMetadata) -> bool) {
+        let is_removed_entry = |entry: &JournalEntry| entry.event.log().is_some_and(&is_removed);
+
+        let first_removed = match self.journal.iter().position(is_removed_entry) {
+            Some(first_removed) => first_removed,
+            None => return,
+        };
+
+        let entries = self.journal.split_off(first_removed);
+        for entry in entries.iter().rev() {
+            undo(
+                &mut self.open_positions,
+                &mut self.risk_factors,
+                &entry.undo,
+            );
+
+            // The liquidation of a position brought back may have been reverted along with its
+            // block, so the position is considered again.
+            if let Undo::Position(key, Some(_)) = &entry.undo {
+                if let Some(position) = self.open_positions.get_mut(key) {
+                    position.status = PositionStatus::Opened;
+                }
+            }
+        }
+        for entry in entries {
+            if !is_removed_entry(&entry) {
+                self.run(&entry.event);
+            }
         }
     }
 
     fn on_block_header(&mut self, block_header: &BlockHeader) -> Vec<Liquidation> {
         self.latest_block = block_header.clone();
 
+        let checkpoint_block = self.checkpoint_block();
+        self.journal
+            .retain(|entry| is_after(entry, checkpoint_block));
+
+        // Failed attempts are forgotten once their position is gone for good, so that a close or
+        // a liquidation reverted by a reorganisation does not reset them.
+        let journal = &self.journal;
+        let open_positions = &self.open_positions;
+        self.liquidation_attempts.retain(|key, _| {
+            open_positions.contains_key(key)
+                || journal.iter().any(
+                    |entry| matches!(&entry.undo, Undo::Position(position, _) if position == key),
+                )
+        });
+
+        vec![]
+    }
+
+    fn on_chain_reorganized(&mut self, chain_reorganized: &ChainReorganized) -> Vec<Liquidation> {
+        self.roll_back(|log| chain_reorganized.removed_blocks.contains(&log.block_hash));
+
+        vec![]
+    }
+
+    fn on_log_removed(&mut self, removed_log: &LogMetadata) -> Vec<Liquidation> {
+        if removed_log.block_number <= self.finalized_block() {
+            println!(
+                "Cannot revert log from finalized block {}, consider more confirmations",
+                removed_log.block_number
+            );
+        }
+
+        self.roll_back(|log| log == removed_log);
+
         vec![]
     }
 
     fn on_position_opened(&mut self, position_opened: &PositionWasOpened) -> Vec<Liquidation> {
         let position = Position {
+            strategy: position_opened.strategy,
             id: position_opened.id,
             owner: position_opened.owner,
             owed_token: position_opened.owed_token,
@@ -103,22 +389,25 @@ impl Liquidator {
             status: PositionStatus::Opened,
         };
 
-        self.open_positions.insert(position.id, position);
+        self.open_positions
+            .insert((position.strategy, position.id), position);
 
-        return vec![];
+        vec![]
     }
 
     fn on_position_closed(&mut self, position_closed: &PositionWasClosed) -> Vec<Liquidation> {
-        self.open_positions.remove(&position_closed.id);
+        self.open_positions
+            .remove(&(position_closed.strategy, position_closed.id));
 
-        return vec![];
+        vec![]
     }
 
     fn on_position_liquidated(
         &mut self,
         position_liquidated: &PositionWasLiquidated,
     ) -> Vec<Liquidation> {
-        self.open_positions.remove(&position_liquidated.id);
+        self.open_positions
+            .remove(&(position_liquidated.strategy, position_liquidated.id));
 
         vec![]
     }
@@ -127,57 +416,126 @@ impl Liquidator {
         &mut self,
         risk_factor_was_updated: &RiskFactorWasUpdated,
     ) -> Vec<Liquidation> {
-        let token = self.tokens.get(&risk_factor_was_updated.token).unwrap();
-
         self.risk_factors.insert(
-            token.symbol.clone(),
+            (
+                risk_factor_was_updated.strategy,
+                risk_factor_was_updated.token,
+            ),
             risk_factor_was_updated.new_risk_factor,
         );
 
         vec![]
     }
 
-    fn on_price_ticker(&mut self, ticker: &Ticker) -> Vec<Liquidation> {
-        self.prices.insert(ticker.pair.clone(), ticker.price);
+    fn on_liquidation_succeeded(
+        &mut self,
+        liquidation_succeeded: &LiquidationSucceeded,
+    ) -> Vec<Liquidation> {
+        let key = (
+            liquidation_succeeded.strategy,
+            liquidation_succeeded.position_id,
+        );
+        println!(
+            "Liquidated position {} of strategy {:?} => {:?}",
+            key.1, key.0, liquidation_succeeded.transaction_hash
+        );
 
-        // XXX we assume pairs have the form WBTC-USD
-        // We assume all pairs are relative to USD
-        let token = self
-            .tokens
-            .iter()
-            .find(|(_, token)| token.symbol == ticker.pair.0)
-            .unwrap()
-            .0
-            .clone();
+        // The position is removed by the `PositionWasLiquidated` event of the transaction.
+        if let Some(position) = self.open_positions.get_mut(&key) {
+            position.status = PositionStatus::Liquidated;
+        }
 
+        vec![]
+    }
+
+    /// Makes a position whose liquidation did not go through liquidable again, after a delay
+    /// doubled with every failure, unless it failed too many times already.
+    fn retry_liquidation_later(&mut self, key: PositionKey, outcome: &str) -> Vec<Liquidation> {
+        let now = self.now();
+        let attempts = self.liquidation_attempts.entry(key).or_default();
+        attempts.failures += 1;
+        let backoff = self
+            .configuration
+            .liquidation_retry_backoff
+            .saturating_mul(1 << (attempts.failures - 1).min(16));
+        attempts.retry_at = now.saturating_add(backoff);
+
+        if attempts.failures >= self.configuration.max_liquidation_attempts {
+            println!(
+                "Liquidation of position {} of strategy {:?} {}, giving up after {} attempts",
+                key.1, key.0, outcome, attempts.failures
+            );
+        } else {
+            println!(
+                "Liquidation of position {} of strategy {:?} {}, retrying in {}s",
+                key.1, key.0, outcome, backoff
+            );
+        }
+
+        if let Some(position) = self.open_positions.get_mut(&key) {
+            if position.status == PositionStatus::LiquidationRequested {
+                position.status = PositionStatus::Opened;
+            }
+        }
+
+        vec![]
+    }
+
+    /// Returns `false` while a position whose liquidation failed must not be liquidated again.
+    fn may_liquidate(&self, key: &PositionKey) -> bool {
+        match self.liquidation_attempts.get(key) {
+            Some(attempts) => {
+                attempts.failures < self.configuration.max_liquidation_attempts
+                    && attempts.retry_at <= self.now()
+            }
+            None => true,
+        }
+    }
+
+    fn on_price_ticker(&mut self, ticker: &Ticker) -> Vec<Liquidation> {
+        self.oracle.update(
+            &ticker.pair,
+            PricePoint {
+                price: ticker.price.clone(),
+                exchange: ticker.exchange,
+                timestamp: ticker.timestamp,
+            },
+        );
+        self.latest_price_timestamp = self.latest_price_timestamp.max(ticker.timestamp);
+
+        // Any price may be on the conversion path between the tokens of a position, so every
+        // position is scored again.
         let liquidations: Vec<Liquidation> = self
             .open_positions
             .iter()
             .filter(|(_, position)| position.status == PositionStatus::Opened)
-            .filter(|(_, position)| position.held_token == token || position.owed_token == token)
-            .filter(
-                |(_, position)| match self.compute_liquidation_score(position) {
-                    Some(liquidation_score) => liquidation_score > BigInt::from(0),
-                    None => false,
-                },
-            )
-            .map(|(id, _)| Liquidation {
-                strategy: self.strategy_address,
-                position_id: id.clone(),
+            .filter(|(key, _)| self.may_liquidate(key))
+            .filter(|(_, position)| match self.score(position) {
+                Ok(Some(liquidation_score)) => vault_math::is_liquidable(&liquidation_score),
+                Ok(None) => false,
+                Err(error) => {
+                    println!(
+                        "Cannot score position {} of strategy {:?}: {}",
+                        position.id, position.strategy, error
+                    );
+                    false
+                }
             })
+            .filter_map(|(_, position)| self.liquidation(position))
             .collect();
 
         // Set the position status to liquidation in progress to avoid multiple liquidation
         // attempts on the same position.
         liquidations.iter().for_each(|liquidation| {
-            let position = self.open_positions.get(&liquidation.position_id).unwrap();
+            let key = (liquidation.strategy, liquidation.position_id);
+            let position = self.open_positions.get(&key).unwrap();
 
             self.open_positions
                 .insert(
-                    position.id,
+                    key,
                     Position {
                         status: PositionStatus::LiquidationRequested,
-                        ..*position
+                        ..position.clone()
                     },
                 )
                 .unwrap();
@@ -188,137 +546,260 @@ impl Liquidator {
 
     fn compute_pair_risk_factor(
         &self,
-        token0: &CurrencyCode,
-        token1: &CurrencyCode,
-    ) -> Option<U256> {
-        let maybe_token_0_risk_factor = self.risk_factors.get(token0);
-        let maybe_token_1_risk_factor = self.risk_factors.get(token1);
+        strategy: &Address,
+        token0: &Address,
+        token1: &Address,
+    ) -> Option<BigInt> {
+        let maybe_token_0_risk_factor = self.risk_factors.get(&(*strategy, *token0));
+        let maybe_token_1_risk_factor = self.risk_factors.get(&(*strategy, *token1));
 
         match (maybe_token_0_risk_factor, maybe_token_1_risk_factor) {
-            (Some(token_0_risk_factor), Some(token_1_risk_factor)) => {
-                Some((token_0_risk_factor + token_1_risk_factor) / 2)
-            }
+            (Some(token_0_risk_factor), Some(token_1_risk_factor)) => Some(
+                vault_math::pair_risk_factor(*token_0_risk_factor, *token_1_risk_factor),
+            ),
             _ => None,
         }
     }
 
-    fn quote(&self, src: &Token, dst: &Token, amount: U256) -> Option<U256> {
-        // Returns amount * src_price * 10^(dst_decimals) / (dst_price * 10^(src_decimals))
-        // if all prices are present, None otherwise.
-        // We also convert prices from f64 to U256 so this computation is not exact.
-        let src_token_to_usd = Pair(src.symbol.clone(), CurrencyCode::USD);
-        let dst_token_to_usd = Pair(dst.symbol.clone(), CurrencyCode::USD);
-
-        let maybe_src_price = self.prices.get(&src_token_to_usd);
-        let maybe_dst_price = self.prices.get(&dst_token_to_usd);
-
-        let quote = match (maybe_src_price, maybe_dst_price) {
-            (Some(src_price), Some(dst_price)) => {
-                // src_price and dst_price are &f64
-                // float64 can go until 2^1023, while the following one goes maximum until 2^(256 * 3) = 2^768
-                // therefore, no overflow occurs
-                let numerator = (U256::low_u64(&amount) as f64)
-                    * src_price
-                    * ((10 as i64).pow(dst.decimals as u32) as f64);
-                let denominator = dst_price * ((10 as i64).pow(src.decimals as u32) as f64);
-                // unfortunately, the maximum precision integer primitive in Rust seems to be i128
-                // we cast to that int to reduce overflows (which can occur for very high numerators and low denominators)
-                Some(U256::from((numerator / denominator) as i128))
-                // let scaled_src_price_float = BigDecimal::from_str(&src_price.to_string()).unwrap()
-                //     * BigDecimal::from_str(&BigInt::from(10).pow(dst.decimals as u32).to_string())
-                //         .unwrap();
-                // println!(
-                //     "scaled_src_price_float => {}",
-                //     scaled_src_price_float.to_string()
-                // );
-                // // TODO convert scaled_src_price_float to hex string before U256
-                // let scaled_src_price = U256::from_str(&scaled_src_price_float.to_string()).unwrap();
-                // // let scaled_src_price = U256::from_str(
-                // //     &BigInt::from_str(&(
-                // //         BigFloat::from(*src_price)
-                // //             * BigFloat::from(10.0).pow(&BigFloat::from(dst.decimals))).int().to_string()
-                // //     )
-                // //     .unwrap()
-                // //     .to_string(),
-                // // )
-                // // .unwrap();
-                // println!("scaled_src_price => {:?}", scaled_src_price);
-
-                // let scaled_dst_price = U256::from_str(
-                //     &BigInt::from_f64(dst_price * 10_f64.powf(src.decimals as f64))
-                //         .unwrap()
-                //         .to_string(),
-                // )
-                // .unwrap();
-
-                // println!("scaled_src_price => {:?}", scaled_src_price);
-                // println!("scaled_dst_price => {:?}", scaled_dst_price);
-
-                // let raw_rate = src_price / dst_price;
-                // let scaled_raw_rate = raw_rate * VAULT_RESOLUTION as f64;
-                // println!("raw_rate: {}", raw_rate);
-                // let rate = if src.decimals == dst.decimals {
-                //     U256::from(scaled_raw_rate as u64)
-                // } else {
-                //     U256::from(scaled_raw_rate as u64).saturating_mul(U256::from(10).pow(U256::from(src.decimals))) / U256::from(10).pow(U256::from(dst.decimals))
-                // };
-
-                // println!("amount: {}; rate: {}", amount, rate);
-
-                // Some(amount * rate / VAULT_RESOLUTION)
-
-                // Some(amount * scaled_src_price / scaled_dst_price)
-            }
-            _ => None,
+    fn quote(&self, src: &Token, dst: &Token, amount: &BigInt) -> Result<BigInt, OracleError> {
+        // Returns amount * rate * 10^(dst_decimals) / 10^(src_decimals) if the tokens can be
+        // converted through trusted prices, the reason they cannot otherwise.
+        let conversion = self.trusted_conversion(&src.symbol, &dst.symbol)?;
+
+        vault_math::convert(
+            amount,
+            &conversion.rate,
+            src.decimals as u32,
+            dst.decimals as u32,
+        )
+        .ok_or_else(|| OracleError::NoConversion {
+            from: src.symbol.clone(),
+            to: dst.symbol.clone(),
+        })
+    }
+
+    /// Current time as far as we know, i.e. the latest of the block and price timestamps.
+    fn now(&self) -> u64 {
+        self.latest_block
+            .timestamp
+            .low_u64()
+            .max(self.latest_price_timestamp)
+    }
+
+    /// Converts `from` into `to` through recent prices confirmed by enough sources, outliers
+    /// left aside.
+    fn trusted_conversion(
+        &self,
+        from: &CurrencyCode,
+        to: &CurrencyCode,
+    ) -> Result<Conversion, OracleError> {
+        let max_age = |pair: &Pair, exchange: Exchange| {
+            let chainlink_max_age = match exchange {
+                Exchange::Chainlink => self.configuration.chainlink_max_price_ages.get(pair),
+                _ => None,
+            };
+            *chainlink_max_age
+                .or_else(|| self.configuration.max_price_ages.get(pair))
+                .unwrap_or(&self.configuration.max_price_age)
         };
 
-        quote
+        self.oracle
+            .trusted_conversion(from, to, self.now(), max_age)
     }
 
-    fn compute_liquidation_score(&self, position: &Position) -> Option<BigInt> {
-        let collateral_in_owed_token = position.collateral_token != position.held_token;
+    /// Reward of liquidating `position` in wei, i.e. the reward share of its collateral priced in
+    /// ETH.
+    fn expected_reward(&self, position: &Position) -> Option<U256> {
+        let liquidation_reward = self.configuration.liquidation_reward?;
+        let collateral_token = self.tokens.get(&position.collateral_token)?;
+        let conversion = self
+            .trusted_conversion(&collateral_token.symbol, &CurrencyCode::WETH)
+            .ok()?;
+
+        let reward = vault_math::to_big_int(position.collateral) * liquidation_reward
+            / vault_math::RESOLUTION;
+        let reward = vault_math::convert(
+            &reward,
+            &conversion.rate,
+            collateral_token.decimals as u32,
+            ETH_DECIMALS,
+        )?;
+        vault_math::from_big_int(&reward)
+    }
 
-        let held_token = self.tokens.get(&position.held_token).unwrap();
-        let owed_token = self.tokens.get(&position.owed_token).unwrap();
+    /// Liquidation of `position`, unless it is expected to make less than the minimum profit.
+    /// Without `liquidation_reward` or a base fee, the profit is unknown and the position is
+    /// liquidated whatever the minimum profit.
+    fn liquidation(&self, position: &Position) -> Option<Liquidation> {
+        let expected_reward = self.expected_reward(position);
+        let profitability = match (expected_reward, self.latest_block.base_fee_per_gas) {
+            (Some(reward), Some(base_fee_per_gas)) => Some(profitability::estimate(
+                reward,
+                base_fee_per_gas,
+                &self.configuration.profitability,
+            )),
+            _ => None,
+        };
 
-        let pair_risk_factor =
-            match self.compute_pair_risk_factor(&held_token.symbol, &owed_token.symbol) {
-                Some(pair_risk_factor) => pair_risk_factor,
-                None => return None,
-            };
+        if let Some(profitability) = &profitability {
+            let min_profit = self.configuration.profitability.min_profit;
+            if !profitability.is_worth_it(min_profit) {
+                println!(
+                    "Not liquidating position {} of strategy {:?}: profit {} wei below {} wei ({:?})",
+                    position.id, position.strategy, profitability.net_profit, min_profit, profitability
+                );
+                return None;
+            }
+        }
 
+        Some(Liquidation {
+            strategy: position.strategy,
+            position_id: position.id,
+            expected_reward,
+            expected_profit: profitability.map(|profitability| profitability.net_profit),
+            required_funds: self.required_funds(position),
+        })
+    }
+
+    fn due_fees(&self, position: &Position) -> BigInt {
         // let position_fees = position.principal * fixedFees;
         // XXX use fake hardcoded value while we wait for this data to be added to token
         // whitelisting events.
-        let position_fees = U256::from_str("1").unwrap();
+        let position_fees = U256::from(1);
 
         // XXX field position.fees should be ranamed to position.interest_rate
-        let due_fees = position_fees
-            + (position.fees
-                * (self.latest_block.timestamp - position.created_at)
-                * position.principal)
-                / (VAULT_TIME_FEE_PERIOD * VAULT_RESOLUTION);
+        vault_math::due_fees(
+            position_fees,
+            position.fees,
+            position.principal,
+            position.created_at,
+            self.latest_block.timestamp,
+        )
+    }
+
+    /// Debt of `position` to pay to liquidate it, and the fee of borrowing it in wei.
+    fn required_funds(&self, position: &Position) -> RequiredFunds {
+        let debt = vault_math::to_big_int(position.principal) + self.due_fees(position);
+        let amount = vault_math::from_big_int(&debt).unwrap_or_else(U256::max_value);
+
+        let flash_loan_fee =
+            profitability::flash_loan_fee(amount, &self.configuration.profitability);
+        let flash_loan_fee = self
+            .tokens
+            .get(&position.owed_token)
+            .and_then(|owed_token| {
+                let conversion = self
+                    .trusted_conversion(&owed_token.symbol, &CurrencyCode::WETH)
+                    .ok()?;
+                let fee = vault_math::convert(
+                    &vault_math::to_big_int(flash_loan_fee),
+                    &conversion.rate,
+                    owed_token.decimals as u32,
+                    ETH_DECIMALS,
+                )?;
+                vault_math::from_big_int(&fee)
+            });
+
+        RequiredFunds {
+            token: position.owed_token,
+            amount,
+            flash_loan_fee,
+        }
+    }
+
+    /// Returns the open positions, including those opened in blocks which are not final yet.
+    pub fn open_positions(&self) -> Vec<&Position> {
+        self.open_positions.values().collect()
+    }
+
+    /// Returns the number of open positions which can be liquidated at the current prices.
+    pub fn underwater_positions(&self) -> usize {
+        self.open_positions
+            .values()
+            .filter(|position| {
+                self.score(position)
+                    .is_ok_and(|score| score.is_some_and(|score| vault_math::is_liquidable(&score)))
+            })
+            .count()
+    }
+
+    /// Returns the liquidation score of an open position, or `None` if the position is unknown
+    /// or some risk factors are still missing or prices are not trusted.
+    pub fn liquidation_score(&self, strategy: &Address, position_id: &U256) -> Option<BigInt> {
+        let position = self.open_positions.get(&(*strategy, *position_id))?;
+
+        self.score(position).ok().flatten()
+    }
+
+    /// Liquidation score of `position` at trusted prices, `None` if some of its tokens or risk
+    /// factors are still unknown, or the reason its prices are not trusted.
+    fn score(&self, position: &Position) -> Result<Option<BigInt>, OracleError> {
+        let collateral_in_owed_token = position.collateral_token != position.held_token;
+
+        // Positions in tokens whose metadata is not known yet cannot be scored.
+        let (held_token, owed_token) = match (
+            self.tokens.get(&position.held_token),
+            self.tokens.get(&position.owed_token),
+        ) {
+            (Some(held_token), Some(owed_token)) => (held_token, owed_token),
+            _ => return Ok(None),
+        };
+
+        let pair_risk_factor = match self.compute_pair_risk_factor(
+            &position.strategy,
+            &position.held_token,
+            &position.owed_token,
+        ) {
+            Some(pair_risk_factor) => pair_risk_factor,
+            None => return Ok(None),
+        };
 
-        let held_token = self.tokens.get(&position.held_token).unwrap();
-        let owed_token = self.tokens.get(&position.owed_token).unwrap();
+        let due_fees = self.due_fees(position);
+        let principal = vault_math::to_big_int(position.principal);
+        let allowance = vault_math::to_big_int(position.allowance);
 
-        match collateral_in_owed_token {
+        let profit_and_loss = match collateral_in_owed_token {
             true => self
-                .quote(held_token, owed_token, position.allowance)
-                .map(|expected_tokens| {
-                    BigInt::from_str(&expected_tokens.to_string()).unwrap()
-                        - (BigInt::from_str(&(position.principal + due_fees).to_string())).unwrap()
-                }),
+                .quote(&held_token, &owed_token, &allowance)
+                .map(|expected_tokens| expected_tokens - (principal + due_fees)),
             false => self
-                .quote(owed_token, held_token, position.principal + due_fees)
-                .map(|expected_tokens| {
-                    BigInt::from_str(&position.allowance.to_string()).unwrap()
-                        - BigInt::from_str(&expected_tokens.to_string()).unwrap()
-                }),
+                .quote(&owed_token, &held_token, &(principal + due_fees))
+                .map(|expected_tokens| allowance - expected_tokens),
+        }?;
+
+        Ok(Some(vault_math::liquidation_score(
+            position.collateral,
+            &pair_risk_factor,
+            &profit_and_loss,
+        )))
+    }
+}
+
+/// Whether the event of `entry` is from a block after `block_number`.
+fn is_after(entry: &JournalEntry, block_number: u64) -> bool {
+    entry
+        .event
+        .log()
+        .is_some_and(|log| log.block_number > block_number)
+}
+
+fn undo(
+    open_positions: &mut HashMap<PositionKey, Position>,
+    risk_factors: &mut HashMap<RiskFactorKey, U256>,
+    undo: &Undo,
+) {
+    match undo {
+        Undo::Position(key, Some(position)) => {
+            open_positions.insert(*key, position.as_ref().clone());
+        }
+        Undo::Position(key, None) => {
+            open_positions.remove(key);
+        }
+        Undo::RiskFactor(key, Some(risk_factor)) => {
+            risk_factors.insert(*key, *risk_factor);
+        }
+        Undo::RiskFactor(key, None) => {
+            risk_factors.remove(key);
         }
-        .map(|pl| {
-            BigInt::from_str(&(position.collateral * pair_risk_factor).to_string()).unwrap()
-                - pl * VAULT_RESOLUTION
-        })
     }
 }
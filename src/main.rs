@@ -1,22 +1,79 @@
-use actix_rt;
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use std::time::SystemTime;
 
+use clap::Parser;
+use serde_json::json;
+
+use liquidation_bot::feeds::supervisor::HealthRegistry;
+use liquidation_bot::metrics::{self, Metrics};
+use liquidation_bot::readiness;
 use liquidation_bot::utils;
 
+#[derive(Parser)]
+#[command(about = "Automated liquidation bot for the Ithil protocol")]
+struct Cli {
+    /// Configuration file declaring the network profiles.
+    #[arg(long, default_value = "config.toml")]
+    config: String,
+
+    /// Network profile to run on, e.g. mainnet, goerli, arbitrum or local.
+    #[arg(long)]
+    network: String,
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let config = utils::load_config().unwrap();
+    let cli = Cli::parse();
+
+    let config = utils::load_config(&cli.config, &cli.network)
+        .unwrap_or_else(|error| panic!("Cannot load {} configuration: {}", cli.network, error));
 
     println!("Tokens => {:?}", config.tokens);
 
     // Start liquidation bot
+    let health = HealthRegistry::new();
+    let metrics = Metrics::new();
+    let readiness_configuration = config.readiness_configuration.clone();
+    let bot_health = health.clone();
+    let bot_metrics = metrics.clone();
     actix_rt::spawn(async {
-        liquidation_bot::liquidation_bot::run(config).await;
+        liquidation_bot::liquidation_bot::run(config, bot_health, bot_metrics).await;
     });
 
     // Start local webserver
-    HttpServer::new(|| App::new().route("/", web::get().to(|| async { "ok" })))
-        .bind(("0.0.0.0", 8080))?
-        .run()
-        .await
+    HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(metrics.clone()))
+            .app_data(web::Data::new(health.clone()))
+            .app_data(web::Data::new(readiness_configuration.clone()))
+            .route("/", web::get().to(|| async { "ok" }))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+            .route("/metrics", web::get().to(render_metrics))
+    })
+    .bind(("0.0.0.0", 8080))?
+    .run()
+    .await
+}
+
+async fn render_metrics(metrics: web::Data<Metrics>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render(metrics::now()))
+}
+
+/// The process is alive as long as it answers.
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "alive" }))
+}
+
+async fn readyz(
+    health: web::Data<HealthRegistry>,
+    configuration: web::Data<readiness::Configuration>,
+) -> HttpResponse {
+    let readiness = readiness::check(&health, &configuration, SystemTime::now());
+    match readiness.ready {
+        true => HttpResponse::Ok().json(readiness),
+        false => HttpResponse::ServiceUnavailable().json(readiness),
+    }
 }
@@ -0,0 +1,240 @@
+//! Prometheus metrics of the bot, served on `/metrics`.
+//!
+//! Metrics are updated from the events flowing through the bot and from the executor, then
+//! rendered in the Prometheus text format when scraped.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use prometheus::{
+    Counter, Encoder, GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use web3::types::{Address, TransactionReceipt};
+
+use crate::events::Event;
+
+const NAMESPACE: &str = "liquidation_bot";
+
+/// Metrics of the bot, shared by all its tasks.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    events: IntCounterVec,
+    open_positions: IntGaugeVec,
+    underwater_positions: IntGauge,
+    liquidations_submitted: IntCounter,
+    liquidations_succeeded: IntCounter,
+    liquidations_failed: IntCounter,
+    liquidations_timed_out: IntCounter,
+    gas_spent: Counter,
+    feed_lag: GaugeVec,
+    price_age: GaugeVec,
+    latest_block_timestamp: IntGauge,
+    /// Timestamp of the latest price of every pair, to compute their age when scraped.
+    price_timestamps: Arc<RwLock<HashMap<String, u64>>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let opts = |name: &str, help: &str| Opts::new(name, help).namespace(NAMESPACE);
+        let metrics = Self {
+            registry: Registry::new(),
+            events: IntCounterVec::new(opts("events_total", "Events processed"), &["type"])
+                .unwrap(),
+            open_positions: IntGaugeVec::new(
+                opts("open_positions", "Open positions"),
+                &["strategy"],
+            )
+            .unwrap(),
+            underwater_positions: IntGauge::with_opts(opts(
+                "underwater_positions",
+                "Open positions which can be liquidated",
+            ))
+            .unwrap(),
+            liquidations_submitted: IntCounter::with_opts(opts(
+                "liquidations_submitted_total",
+                "Liquidations sent to the network or to the relay",
+            ))
+            .unwrap(),
+            liquidations_succeeded: IntCounter::with_opts(opts(
+                "liquidations_succeeded_total",
+                "Liquidations mined successfully",
+            ))
+            .unwrap(),
+            liquidations_failed: IntCounter::with_opts(opts(
+                "liquidations_failed_total",
+                "Liquidations not sent or reverted",
+            ))
+            .unwrap(),
+            liquidations_timed_out: IntCounter::with_opts(opts(
+                "liquidations_timed_out_total",
+                "Liquidations cancelled or dropped",
+            ))
+            .unwrap(),
+            gas_spent: Counter::with_opts(opts(
+                "gas_spent_wei_total",
+                "Wei spent on gas by the mined transactions",
+            ))
+            .unwrap(),
+            feed_lag: GaugeVec::new(
+                opts(
+                    "feed_lag_seconds",
+                    "Delay between the latest event of a source and its reception",
+                ),
+                &["source"],
+            )
+            .unwrap(),
+            price_age: GaugeVec::new(
+                opts("price_age_seconds", "Age of the latest price of a pair"),
+                &["pair"],
+            )
+            .unwrap(),
+            latest_block_timestamp: IntGauge::with_opts(opts(
+                "latest_block_timestamp_seconds",
+                "Timestamp of the latest block",
+            ))
+            .unwrap(),
+            price_timestamps: Arc::new(RwLock::new(HashMap::new())),
+        };
+
+        let registry = &metrics.registry;
+        registry.register(Box::new(metrics.events.clone())).unwrap();
+        registry
+            .register(Box::new(metrics.open_positions.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.underwater_positions.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.liquidations_submitted.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.liquidations_succeeded.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.liquidations_failed.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.liquidations_timed_out.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.gas_spent.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.feed_lag.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.price_age.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(metrics.latest_block_timestamp.clone()))
+            .unwrap();
+
+        metrics
+    }
+
+    /// Counts `event`, received at `now` (in seconds since the Unix epoch).
+    pub fn observe(&self, event: &Event, now: u64) {
+        self.events.with_label_values(&[event_type(event)]).inc();
+
+        match event {
+            Event::BlockHeader(block_header) => {
+                let timestamp = block_header.timestamp.low_u64();
+                self.latest_block_timestamp.set(timestamp as i64);
+                self.feed_lag
+                    .with_label_values(&["ethereum"])
+                    .set(now.saturating_sub(timestamp) as f64);
+            }
+            Event::Ticker(ticker) => {
+                let source = format!("{:?}", ticker.exchange).to_lowercase();
+                self.feed_lag
+                    .with_label_values(&[&source])
+                    .set(now.saturating_sub(ticker.timestamp) as f64);
+
+                let pair = format!("{}-{}", ticker.pair.0, ticker.pair.1);
+                let mut price_timestamps = self.price_timestamps.write().unwrap();
+                let timestamp = price_timestamps.entry(pair).or_insert(0);
+                *timestamp = (*timestamp).max(ticker.timestamp);
+            }
+            Event::LiquidationSucceeded(_) => self.liquidations_succeeded.inc(),
+            Event::LiquidationFailed(_) => self.liquidations_failed.inc(),
+            Event::LiquidationTimedOut(_) => self.liquidations_timed_out.inc(),
+            _ => {}
+        }
+    }
+
+    /// Sets the number of open positions of every strategy, and of those which can be liquidated.
+    pub fn set_positions(&self, open_positions: &HashMap<Address, usize>, underwater: usize) {
+        // Strategies without open positions are dropped rather than left at their last count.
+        self.open_positions.reset();
+        for (strategy, count) in open_positions {
+            self.open_positions
+                .with_label_values(&[&format!("{:?}", strategy)])
+                .set(*count as i64);
+        }
+        self.underwater_positions.set(underwater as i64);
+    }
+
+    pub fn liquidations_submitted(&self, count: usize) {
+        self.liquidations_submitted.inc_by(count as u64);
+    }
+
+    /// Adds the gas paid by the transaction of `receipt`.
+    pub fn gas_spent(&self, receipt: &TransactionReceipt) {
+        if let (Some(gas_used), Some(gas_price)) = (receipt.gas_used, receipt.effective_gas_price) {
+            let wei = gas_used.saturating_mul(gas_price);
+            // Counters are floats, large amounts of wei lose some precision.
+            self.gas_spent
+                .inc_by(wei.to_string().parse::<f64>().unwrap());
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text format, the age of prices being computed at
+    /// `now` (in seconds since the Unix epoch).
+    pub fn render(&self, now: u64) -> String {
+        for (pair, timestamp) in self.price_timestamps.read().unwrap().iter() {
+            self.price_age
+                .with_label_values(&[pair])
+                .set(now.saturating_sub(*timestamp) as f64);
+        }
+
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn event_type(event: &Event) -> &'static str {
+    match event {
+        Event::BlockHeader(_) => "block_header",
+        Event::ChainReorganized(_) => "chain_reorganized",
+        Event::LogWasRemoved(_) => "log_was_removed",
+        Event::PositionWasOpened(_) => "position_was_opened",
+        Event::PositionWasClosed(_) => "position_was_closed",
+        Event::PositionWasLiquidated(_) => "position_was_liquidated",
+        Event::RiskFactorWasUpdated(_) => "risk_factor_was_updated",
+        Event::LogsSynced(_) => "logs_synced",
+        Event::Ticker(_) => "ticker",
+        Event::LiquidationSucceeded(_) => "liquidation_succeeded",
+        Event::LiquidationFailed(_) => "liquidation_failed",
+        Event::LiquidationTimedOut(_) => "liquidation_timed_out",
+    }
+}
@@ -0,0 +1,425 @@
+//! Aggregation of the prices published by several sources into a single price per pair.
+//!
+//! Every source keeps its own latest price. The aggregated price is the median of the sources
+//! which agree with it, sources deviating too much from the median of all sources being rejected
+//! as outliers. Liquidations additionally require a minimum number of fresh sources.
+//!
+//! Pairs form a graph between currencies, so that any currency can be converted into another one
+//! by chaining the prices of the pairs along a path, e.g. WBTC-WETH then WETH-USD.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+
+use num_bigint::{BigInt, Sign};
+use num_traits::{Pow, Signed};
+use web3::types::U256;
+
+use crate::types::{CurrencyCode, Exchange, Pair, Price, PricePoint, Ratio};
+use crate::vault_math;
+
+/// Maximum number of pairs chained to convert a currency into another one.
+const MAX_PATH_LENGTH: usize = 4;
+
+#[derive(Clone)]
+pub struct Configuration {
+    /// Minimum number of fresh sources agreeing on a price before it can trigger liquidations.
+    pub min_sources: usize,
+    /// Maximum deviation from the median, in basis points, for a source to be taken into account.
+    pub max_deviation: u32,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            min_sources: 1,
+            max_deviation: 200,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregatedPrice {
+    pub price: Price,
+    /// Sources the price was computed from.
+    pub sources: Vec<Exchange>,
+    /// Sources rejected as outliers.
+    pub outliers: Vec<Exchange>,
+    /// Timestamp of the oldest price used.
+    pub timestamp: u64,
+}
+
+/// Rate at which a currency converts into another one, derived from a path of pairs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conversion {
+    /// Units of the destination currency per unit of the source currency.
+    pub rate: Ratio,
+    /// Pairs the rate was derived from, from the source to the destination currency.
+    pub path: Vec<Pair>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OracleError {
+    MissingPrice(Pair),
+    NoConversion {
+        from: CurrencyCode,
+        to: CurrencyCode,
+    },
+    StalePrice {
+        pair: Pair,
+        age: u64,
+        max_age: u64,
+    },
+    QuorumNotReached {
+        pair: Pair,
+        sources: Vec<Exchange>,
+        outliers: Vec<Exchange>,
+        min_sources: usize,
+    },
+}
+
+impl fmt::Display for OracleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OracleError::MissingPrice(pair) => write!(f, "no {:?} price", pair),
+            OracleError::NoConversion { from, to } => {
+                write!(f, "no pairs to convert {} into {}", from, to)
+            }
+            OracleError::StalePrice { pair, age, max_age } => {
+                write!(f, "{:?} price is {}s old (max {}s)", pair, age, max_age)
+            }
+            OracleError::QuorumNotReached {
+                pair,
+                sources,
+                outliers,
+                min_sources,
+            } => write!(
+                f,
+                "{:?} price has {} agreeing sources {:?} (min {}), outliers {:?}",
+                pair,
+                sources.len(),
+                sources,
+                min_sources,
+                outliers
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OracleError {}
+
+pub struct Oracle {
+    configuration: Configuration,
+    prices: HashMap<Pair, HashMap<Exchange, PricePoint>>,
+}
+
+impl Oracle {
+    pub fn new(configuration: Configuration) -> Self {
+        Self {
+            configuration,
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Records the latest price of `pair` published by `price_point.exchange`. Prices arriving
+    /// out of order never replace a newer price from the same source.
+    pub fn update(&mut self, pair: &Pair, price_point: PricePoint) {
+        let sources = self.prices.entry(pair.clone()).or_default();
+        let is_newer = sources
+            .get(&price_point.exchange)
+            .is_none_or(|latest| price_point.timestamp >= latest.timestamp);
+        if is_newer {
+            sources.insert(price_point.exchange, price_point);
+        }
+    }
+
+    /// Returns the latest price of every source for `pair`.
+    pub fn sources(&self, pair: &Pair) -> Vec<&PricePoint> {
+        self.prices
+            .get(pair)
+            .map(|sources| sources.values().collect())
+            .unwrap_or_default()
+    }
+
+    /// Aggregates the latest price of every source, whatever its age.
+    pub fn price(&self, pair: &Pair) -> Option<AggregatedPrice> {
+        aggregate(&self.sources(pair), self.configuration.max_deviation)
+    }
+
+    /// Aggregates the prices published by each source at most `max_age(exchange)` seconds before
+    /// `now`, failing unless enough sources agree on the price.
+    pub fn trusted_price(
+        &self,
+        pair: &Pair,
+        now: u64,
+        max_age: impl Fn(Exchange) -> u64,
+    ) -> Result<AggregatedPrice, OracleError> {
+        let sources = self.sources(pair);
+        let freshest = *sources
+            .iter()
+            .max_by_key(|price_point| price_point.timestamp)
+            .ok_or_else(|| OracleError::MissingPrice(pair.clone()))?;
+
+        let fresh_sources: Vec<&PricePoint> = sources
+            .into_iter()
+            .filter(|price_point| {
+                now.saturating_sub(price_point.timestamp) <= max_age(price_point.exchange)
+            })
+            .collect();
+        if fresh_sources.is_empty() {
+            return Err(OracleError::StalePrice {
+                pair: pair.clone(),
+                age: now.saturating_sub(freshest.timestamp),
+                max_age: max_age(freshest.exchange),
+            });
+        }
+
+        let quorum_not_reached = |sources, outliers| OracleError::QuorumNotReached {
+            pair: pair.clone(),
+            sources,
+            outliers,
+            min_sources: self.configuration.min_sources,
+        };
+        match aggregate(&fresh_sources, self.configuration.max_deviation) {
+            Some(aggregated_price)
+                if aggregated_price.sources.len() >= self.configuration.min_sources =>
+            {
+                Ok(aggregated_price)
+            }
+            Some(aggregated_price) => Err(quorum_not_reached(
+                aggregated_price.sources,
+                aggregated_price.outliers,
+            )),
+            // No two sources agree with each other.
+            None => Err(quorum_not_reached(
+                vec![],
+                fresh_sources
+                    .iter()
+                    .map(|price_point| price_point.exchange)
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Converts `from` into `to` through the pairs with a price, whatever their age.
+    pub fn conversion(&self, from: &CurrencyCode, to: &CurrencyCode) -> Option<Conversion> {
+        let prices: HashMap<Pair, AggregatedPrice> = self
+            .prices
+            .keys()
+            .filter_map(|pair| Some((pair.clone(), self.price(pair)?)))
+            .collect();
+
+        find_conversion(from, to, &prices)
+    }
+
+    /// Converts `from` into `to` through the pairs whose price is trusted, the price of a pair
+    /// published by a source being at most `max_age(pair, exchange)` seconds old. Fails with the
+    /// reason the best path is not trusted if there is no trusted path.
+    pub fn trusted_conversion(
+        &self,
+        from: &CurrencyCode,
+        to: &CurrencyCode,
+        now: u64,
+        max_age: impl Fn(&Pair, Exchange) -> u64,
+    ) -> Result<Conversion, OracleError> {
+        let mut trusted_prices = HashMap::new();
+        let mut errors = HashMap::new();
+        for pair in self.prices.keys() {
+            match self.trusted_price(pair, now, |exchange| max_age(pair, exchange)) {
+                Ok(aggregated_price) => {
+                    trusted_prices.insert(pair.clone(), aggregated_price);
+                }
+                Err(error) => {
+                    errors.insert(pair.clone(), error);
+                }
+            }
+        }
+
+        if let Some(conversion) = find_conversion(from, to, &trusted_prices) {
+            return Ok(conversion);
+        }
+        let no_conversion = || OracleError::NoConversion {
+            from: from.clone(),
+            to: to.clone(),
+        };
+        Err(match self.conversion(from, to) {
+            Some(conversion) => conversion
+                .path
+                .iter()
+                .find_map(|pair| errors.remove(pair))
+                .unwrap_or_else(no_conversion),
+            None => no_conversion(),
+        })
+    }
+}
+
+/// A pair of a path, followed from its base to its quote currency if `forward`.
+#[derive(Clone)]
+struct Edge<'a> {
+    pair: &'a Pair,
+    price: &'a AggregatedPrice,
+    forward: bool,
+}
+
+/// Finds the most reliable path from `from` to `to` through `prices`: the path with the fewest
+/// pairs, then the one whose least confirmed price has the most sources, then the one whose oldest
+/// price is the most recent.
+fn find_conversion(
+    from: &CurrencyCode,
+    to: &CurrencyCode,
+    prices: &HashMap<Pair, AggregatedPrice>,
+) -> Option<Conversion> {
+    let prices: Vec<(&Pair, &AggregatedPrice)> = prices.iter().collect();
+
+    let mut best: Option<Vec<Edge>> = None;
+    search_paths(from, to, &prices, &mut vec![from], &mut vec![], &mut best);
+
+    let path = best?;
+    Some(Conversion {
+        rate: path.iter().fold(Ratio::one(), |rate, edge| {
+            let price = Ratio::from(&edge.price.price);
+            rate.mul(&if edge.forward { price } else { price.inverse() })
+        }),
+        path: path.iter().map(|edge| edge.pair.clone()).collect(),
+    })
+}
+
+/// Depth-first search of the paths from `currency` to `to` which do not go through `visited`,
+/// keeping the best one in `best`.
+fn search_paths<'a>(
+    currency: &CurrencyCode,
+    to: &CurrencyCode,
+    prices: &[(&'a Pair, &'a AggregatedPrice)],
+    visited: &mut Vec<&'a CurrencyCode>,
+    path: &mut Vec<Edge<'a>>,
+    best: &mut Option<Vec<Edge<'a>>>,
+) {
+    if currency == to {
+        if best
+            .as_ref()
+            .is_none_or(|best| path_score(path) < path_score(best))
+        {
+            *best = Some(path.clone());
+        }
+        return;
+    }
+    if path.len() == MAX_PATH_LENGTH {
+        return;
+    }
+
+    for (pair, price) in prices.iter() {
+        let (next, forward) = if pair.0 == *currency {
+            (&pair.1, true)
+        } else if pair.1 == *currency {
+            (&pair.0, false)
+        } else {
+            continue;
+        };
+        // A zero price followed backwards gives an infinite rate, which cannot be used to convert.
+        if visited.contains(&next) || (!forward && price.price.value.is_zero()) {
+            continue;
+        }
+
+        visited.push(next);
+        path.push(Edge {
+            pair,
+            price,
+            forward,
+        });
+        search_paths(next, to, prices, visited, path, best);
+        path.pop();
+        visited.pop();
+    }
+}
+
+/// Orders paths from the most to the least reliable.
+fn path_score(path: &[Edge]) -> (usize, Reverse<usize>, Reverse<u64>) {
+    (
+        path.len(),
+        Reverse(
+            path.iter()
+                .map(|edge| edge.price.sources.len())
+                .min()
+                .unwrap_or(usize::MAX),
+        ),
+        Reverse(
+            path.iter()
+                .map(|edge| edge.price.timestamp)
+                .min()
+                .unwrap_or(u64::MAX),
+        ),
+    )
+}
+
+/// Returns the median of the prices which deviate at most `max_deviation` basis points from the
+/// median of all prices.
+fn aggregate(price_points: &[&PricePoint], max_deviation: u32) -> Option<AggregatedPrice> {
+    // Prices are compared as integers with the same number of decimals.
+    let decimals = price_points
+        .iter()
+        .map(|price_point| price_point.price.decimals)
+        .max()?;
+    let scaled = |price: &Price| {
+        vault_math::to_big_int(price.value) * BigInt::from(10).pow(decimals - price.decimals)
+    };
+
+    let values: Vec<BigInt> = price_points
+        .iter()
+        .map(|price_point| scaled(&price_point.price))
+        .collect();
+    let (median_value, median_decimals) = median(values)?;
+    // Brings the median to the common number of decimals, times 10 if it needed one more.
+    let scale = BigInt::from(10).pow(median_decimals);
+
+    let (accepted, rejected): (Vec<&PricePoint>, Vec<&PricePoint>) =
+        price_points.iter().partition(|price_point| {
+            let deviation = (scaled(&price_point.price) * &scale - &median_value).abs();
+            deviation * BigInt::from(vault_math::RESOLUTION)
+                <= &median_value * BigInt::from(max_deviation)
+        });
+
+    let (value, extra_decimals) = median(
+        accepted
+            .iter()
+            .map(|price_point| scaled(&price_point.price))
+            .collect(),
+    )?;
+
+    Some(AggregatedPrice {
+        price: Price::new(to_u256(&value), decimals + extra_decimals),
+        sources: accepted
+            .iter()
+            .map(|price_point| price_point.exchange)
+            .collect(),
+        outliers: rejected
+            .iter()
+            .map(|price_point| price_point.exchange)
+            .collect(),
+        timestamp: accepted
+            .iter()
+            .map(|price_point| price_point.timestamp)
+            .min()?,
+    })
+}
+
+/// Returns the exact median of `values` as `(value, extra_decimals)`: the mean of the two middle
+/// values of an even number of values is kept exact by adding a decimal.
+fn median(mut values: Vec<BigInt>) -> Option<(BigInt, u32)> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort();
+    let middle = values.len() / 2;
+    if values.len() % 2 == 1 {
+        Some((values[middle].clone(), 0))
+    } else {
+        Some(((&values[middle - 1] + &values[middle]) * 5, 1))
+    }
+}
+
+fn to_u256(value: &BigInt) -> U256 {
+    let (sign, bytes) = value.to_bytes_be();
+    assert!(sign != Sign::Minus, "prices are never negative");
+    U256::from_big_endian(&bytes)
+}
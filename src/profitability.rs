@@ -0,0 +1,83 @@
+//! Whether liquidating a position is worth it: its reward, less the gas it costs, the slippage of
+//! selling the reward and, when its debt is borrowed, the fee of the flash loan.
+//!
+//! Amounts are in wei, gas being paid in ETH and rewards being priced in ETH.
+
+use num_bigint::BigInt;
+use web3::types::U256;
+
+use crate::vault_math;
+
+pub struct Configuration {
+    /// Gas used by the liquidation of a position.
+    pub liquidation_gas: u64,
+    /// Tip paid on top of the base fee, in wei per gas.
+    pub priority_fee_per_gas: U256,
+    /// Share of the reward lost when selling it, in basis points.
+    pub slippage: u32,
+    /// Minimum net profit of a liquidation in wei, smaller ones are skipped. Only applied when
+    /// the reward of the liquidation is known.
+    pub min_profit: U256,
+    /// Fee of flash loans, in basis points of the amount borrowed.
+    pub flash_loan_fee: u32,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            liquidation_gas: 500_000,
+            priority_fee_per_gas: U256::from(2_000_000_000u64),
+            slippage: 50,
+            min_profit: U256::zero(),
+            flash_loan_fee: 5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profitability {
+    pub reward: U256,
+    pub gas_cost: U256,
+    pub slippage: U256,
+    /// Reward less gas cost and slippage, negative for liquidations losing money.
+    pub net_profit: BigInt,
+}
+
+impl Profitability {
+    pub fn is_worth_it(&self, min_profit: U256) -> bool {
+        self.net_profit >= vault_math::to_big_int(min_profit)
+    }
+}
+
+/// Net profit of a liquidation making `net_profit` when its debt is paid by the account, if its
+/// debt is borrowed with a flash loan costing `flash_loan_fee` instead.
+pub fn with_flash_loan(net_profit: &BigInt, flash_loan_fee: U256) -> BigInt {
+    net_profit - vault_math::to_big_int(flash_loan_fee)
+}
+
+/// Fee of borrowing `amount` with a flash loan, in the borrowed token.
+pub fn flash_loan_fee(amount: U256, configuration: &Configuration) -> U256 {
+    amount.saturating_mul(U256::from(configuration.flash_loan_fee))
+        / U256::from(vault_math::RESOLUTION)
+}
+
+/// Estimates the profit of a liquidation paying `reward` when blocks have `base_fee_per_gas`.
+pub fn estimate(
+    reward: U256,
+    base_fee_per_gas: U256,
+    configuration: &Configuration,
+) -> Profitability {
+    let gas_price = base_fee_per_gas.saturating_add(configuration.priority_fee_per_gas);
+    let gas_cost = gas_price.saturating_mul(U256::from(configuration.liquidation_gas));
+    let slippage = reward.saturating_mul(U256::from(configuration.slippage))
+        / U256::from(vault_math::RESOLUTION);
+
+    Profitability {
+        reward,
+        gas_cost,
+        slippage,
+        net_profit: vault_math::to_big_int(reward)
+            - vault_math::to_big_int(gas_cost)
+            - vault_math::to_big_int(slippage),
+    }
+}
@@ -0,0 +1,114 @@
+//! Whether the bot is ready to liquidate positions, served on `/readyz`.
+//!
+//! The bot is ready once it has rebuilt the open positions, every feed is connected and has sent
+//! events recently, and the executor reaches the node with accounts holding enough ETH.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+
+use crate::feeds::supervisor::{FeedStatus, HealthRegistry};
+
+/// Components reported by the bot itself, besides its feeds.
+pub const BOOTSTRAP: &str = "bootstrap";
+pub const EXECUTOR: &str = "executor";
+pub const BALANCES: &str = "balances";
+
+#[derive(Clone)]
+pub struct Configuration {
+    /// Time after which a feed without events, or an executor which did not check the chain, is
+    /// no longer ready.
+    pub max_silence: Duration,
+    /// Feeds which may stay silent for long, e.g. `ithil` which only sends events when positions
+    /// change, and are only required to be connected.
+    pub quiet_feeds: Vec<String>,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            max_silence: Duration::from_secs(120),
+            quiet_feeds: vec![String::from("ithil")],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ComponentReadiness {
+    pub ready: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Readiness of the bootstrap, the executor, the balances and every feed, the latter as
+    /// `feed:<name>`.
+    pub components: BTreeMap<String, ComponentReadiness>,
+}
+
+/// Checks the state of `registry` at `now`.
+pub fn check(
+    registry: &HealthRegistry,
+    configuration: &Configuration,
+    now: SystemTime,
+) -> Readiness {
+    let age = |time: SystemTime| now.duration_since(time).unwrap_or_default();
+    let mut components = BTreeMap::new();
+
+    for (component, may_go_silent) in [(BOOTSTRAP, true), (EXECUTOR, false), (BALANCES, false)] {
+        let readiness = match registry.component(component) {
+            None => ComponentReadiness {
+                ready: false,
+                detail: String::from("not reported yet"),
+            },
+            Some(state) if !may_go_silent && age(state.updated_at) > configuration.max_silence => {
+                ComponentReadiness {
+                    ready: false,
+                    detail: format!("not reported for {}s", age(state.updated_at).as_secs()),
+                }
+            }
+            Some(state) => ComponentReadiness {
+                ready: state.healthy,
+                detail: state.detail,
+            },
+        };
+        components.insert(String::from(component), readiness);
+    }
+
+    for (feed, state) in registry.snapshot() {
+        let quiet = configuration.quiet_feeds.contains(&feed);
+        let readiness = match (&state.status, state.last_event) {
+            (FeedStatus::Connecting, _) => ComponentReadiness {
+                ready: false,
+                detail: String::from("connecting"),
+            },
+            (FeedStatus::Disconnected { error, retry_in }, _) => ComponentReadiness {
+                ready: false,
+                detail: format!("disconnected ({}), retrying in {:?}", error, retry_in),
+            },
+            (FeedStatus::Connected, _) if quiet => ComponentReadiness {
+                ready: true,
+                detail: String::from("connected"),
+            },
+            (FeedStatus::Connected, None) => ComponentReadiness {
+                ready: age(state.since) <= configuration.max_silence,
+                detail: format!(
+                    "no event since connected {}s ago",
+                    age(state.since).as_secs()
+                ),
+            },
+            (FeedStatus::Connected, Some(last_event)) => ComponentReadiness {
+                ready: age(last_event) <= configuration.max_silence,
+                detail: format!("last event {}s ago", age(last_event).as_secs()),
+            },
+        };
+        components.insert(format!("feed:{}", feed), readiness);
+    }
+
+    Readiness {
+        ready: components.values().all(|component| component.ready),
+        components,
+    }
+}
@@ -0,0 +1,50 @@
+//! Local persistence of the state rebuilt from on-chain events, so that a restarted bot only has
+//! to fetch the events emitted since its last checkpoint.
+
+use serde::{Deserialize, Serialize};
+
+use crate::liquidator::LiquidatorState;
+
+const CHECKPOINT_KEY: &str = "checkpoint";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Checkpoint {
+    /// Last block whose events are all included in `state`.
+    pub block_number: u64,
+    pub state: LiquidatorState,
+}
+
+pub struct Store {
+    db: sled::Db,
+}
+
+impl Store {
+    pub fn open(path: &str) -> sled::Result<Self> {
+        Ok(Self {
+            db: sled::open(path)?,
+        })
+    }
+
+    pub fn load_checkpoint(&self) -> sled::Result<Option<Checkpoint>> {
+        let checkpoint =
+            self.db
+                .get(CHECKPOINT_KEY)?
+                .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                    Ok(checkpoint) => Some(checkpoint),
+                    Err(error) => {
+                        println!("Ignoring unreadable checkpoint: {}", error);
+                        None
+                    }
+                });
+
+        Ok(checkpoint)
+    }
+
+    pub fn save_checkpoint(&self, checkpoint: &Checkpoint) -> sled::Result<()> {
+        let bytes = serde_json::to_vec(checkpoint).unwrap();
+        self.db.insert(CHECKPOINT_KEY, bytes)?;
+        self.db.flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Metadata of the tokens positions are opened in.
+//!
+//! The registry starts from the token list of the deployment and learns the tokens whitelisted by
+//! the vault afterwards, reading their metadata from their ERC20 contract. It is shared between
+//! the feeds, which learn tokens, and the liquidator, which scores positions with them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use web3::api::Eth;
+use web3::contract::{Contract, Options};
+use web3::transports::WebSocket;
+use web3::types::{Address, H256};
+
+use crate::types::{CurrencyCode, Token};
+
+/// The ERC20 metadata functions.
+const ERC20_ABI: &str = r#"[
+    {"type": "function", "name": "name", "inputs": [], "outputs": [{"name": "", "type": "string"}], "stateMutability": "view"},
+    {"type": "function", "name": "symbol", "inputs": [], "outputs": [{"name": "", "type": "string"}], "stateMutability": "view"},
+    {"type": "function", "name": "decimals", "inputs": [], "outputs": [{"name": "", "type": "uint8"}], "stateMutability": "view"}
+]"#;
+
+/// The same functions for tokens predating the standard, such as MKR, which return `bytes32`.
+const ERC20_BYTES32_ABI: &str = r#"[
+    {"type": "function", "name": "name", "inputs": [], "outputs": [{"name": "", "type": "bytes32"}], "stateMutability": "view"},
+    {"type": "function", "name": "symbol", "inputs": [], "outputs": [{"name": "", "type": "bytes32"}], "stateMutability": "view"}
+]"#;
+
+#[derive(Clone, Default)]
+pub struct TokenRegistry {
+    tokens: Arc<RwLock<HashMap<Address, Token>>>,
+}
+
+impl TokenRegistry {
+    pub fn new(tokens: impl IntoIterator<Item = Token>) -> Self {
+        Self {
+            tokens: Arc::new(RwLock::new(
+                tokens
+                    .into_iter()
+                    .map(|token| (token.address, token))
+                    .collect(),
+            )),
+        }
+    }
+
+    pub fn get(&self, address: &Address) -> Option<Token> {
+        self.tokens.read().unwrap().get(address).cloned()
+    }
+
+    pub fn contains(&self, address: &Address) -> bool {
+        self.tokens.read().unwrap().contains_key(address)
+    }
+
+    pub fn find_by_symbol(&self, symbol: &CurrencyCode) -> Option<Token> {
+        self.tokens
+            .read()
+            .unwrap()
+            .values()
+            .find(|token| token.symbol == *symbol)
+            .cloned()
+    }
+
+    /// Records a token, returning `false` if it was already known.
+    pub fn insert(&self, token: Token) -> bool {
+        self.tokens
+            .write()
+            .unwrap()
+            .insert(token.address, token)
+            .is_none()
+    }
+
+    pub fn tokens(&self) -> Vec<Token> {
+        self.tokens.read().unwrap().values().cloned().collect()
+    }
+}
+
+impl From<HashMap<Address, Token>> for TokenRegistry {
+    fn from(tokens: HashMap<Address, Token>) -> Self {
+        TokenRegistry::new(tokens.into_values())
+    }
+}
+
+/// Reads the name, symbol and decimals of a token from its ERC20 contract.
+pub async fn fetch_token(eth: Eth<WebSocket>, address: Address) -> web3::contract::Result<Token> {
+    let contract = Contract::from_json(eth.clone(), address, ERC20_ABI.as_bytes())?;
+    let bytes32_contract = Contract::from_json(eth, address, ERC20_BYTES32_ABI.as_bytes())?;
+
+    let name: String = read_text(&contract, &bytes32_contract, "name").await?;
+    let symbol: String = read_text(&contract, &bytes32_contract, "symbol").await?;
+    let decimals: u8 = contract
+        .query("decimals", (), None, Options::default(), None)
+        .await?;
+
+    Ok(Token {
+        name,
+        address,
+        decimals: decimals as i32,
+        symbol: CurrencyCode::new(symbol),
+    })
+}
+
+/// Reads a `string` of a token, or a `bytes32` padded with zeros for older tokens.
+async fn read_text(
+    contract: &Contract<WebSocket>,
+    bytes32_contract: &Contract<WebSocket>,
+    function: &str,
+) -> web3::contract::Result<String> {
+    let text: web3::contract::Result<String> = contract
+        .query(function, (), None, Options::default(), None)
+        .await;
+    if text.is_ok() {
+        return text;
+    }
+
+    let text: H256 = bytes32_contract
+        .query(function, (), None, Options::default(), None)
+        .await?;
+    Ok(String::from_utf8_lossy(text.as_bytes())
+        .trim_end_matches('\0')
+        .to_string())
+}
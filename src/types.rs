@@ -1,21 +1,54 @@
+use std::borrow::Cow;
+use std::fmt;
 use std::str::FromStr;
 
+use num_bigint::BigInt;
+use num_traits::{Pow, Zero};
 use serde::{Deserialize, Serialize};
 use web3::ethabi::Address;
 use web3::types::U256;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Exchange {
+    Binance,
+    Chainlink,
     Coinbase,
+    Kraken,
+    Uniswap,
 }
 
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
-pub enum CurrencyCode {
-    DAI,
-    USD,
-    USDC,
-    WBTC,
-    WETH,
+/// Symbol of a currency, e.g. `WETH`. Tokens are identified by the symbol returned by their
+/// ERC20 `symbol()`, so any symbol is valid; the usual ones are provided as constants.
+#[derive(Clone, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct CurrencyCode(Cow<'static, str>);
+
+impl CurrencyCode {
+    pub const DAI: CurrencyCode = CurrencyCode(Cow::Borrowed("DAI"));
+    pub const USD: CurrencyCode = CurrencyCode(Cow::Borrowed("USD"));
+    pub const USDC: CurrencyCode = CurrencyCode(Cow::Borrowed("USDC"));
+    pub const WBTC: CurrencyCode = CurrencyCode(Cow::Borrowed("WBTC"));
+    pub const WETH: CurrencyCode = CurrencyCode(Cow::Borrowed("WETH"));
+
+    pub fn new(symbol: impl Into<String>) -> Self {
+        CurrencyCode(Cow::Owned(symbol.into()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for CurrencyCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for CurrencyCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
 impl FromStr for CurrencyCode {
@@ -23,12 +56,9 @@ impl FromStr for CurrencyCode {
 
     fn from_str(input: &str) -> Result<CurrencyCode, Self::Err> {
         match input {
-            "DAI" => Ok(CurrencyCode::DAI),
-            "USD" => Ok(CurrencyCode::USD),
-            "USDC" => Ok(CurrencyCode::USDC),
-            "WBTC" => Ok(CurrencyCode::WBTC),
+            "" => Err(()),
             "ETH" => Ok(CurrencyCode::WETH), // XXX Coinbase is very slow/buggy with WETH, so here we use ETH as a proxy for WETH prices.
-            _ => Err(()),
+            symbol => Ok(CurrencyCode::new(symbol)),
         }
     }
 }
@@ -36,6 +66,124 @@ impl FromStr for CurrencyCode {
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Pair(pub CurrencyCode, pub CurrencyCode);
 
+impl FromStr for Pair {
+    type Err = ();
+
+    /// Parses a pair in the form e.g. `WBTC-USD`.
+    fn from_str(input: &str) -> Result<Pair, Self::Err> {
+        let (base, quote) = input.split_once('-').ok_or(())?;
+
+        Ok(Pair(
+            CurrencyCode::from_str(base)?,
+            CurrencyCode::from_str(quote)?,
+        ))
+    }
+}
+
+/// An exact decimal price, worth `value / 10^decimals` units of the quote currency.
+///
+/// Prices are kept as scaled integers so that quotes can be computed with the same integer
+/// arithmetic used on-chain, without going through floating point.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Price {
+    pub value: U256,
+    pub decimals: u32,
+}
+
+impl Price {
+    pub fn new(value: U256, decimals: u32) -> Self {
+        Price { value, decimals }
+    }
+}
+
+impl FromStr for Price {
+    type Err = ();
+
+    /// Parses a non-negative decimal number such as `1234.5600` without losing precision.
+    fn from_str(input: &str) -> Result<Price, Self::Err> {
+        let (integer, fraction) = input.split_once('.').unwrap_or((input, ""));
+        let digits = format!("{}{}", integer, fraction);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(());
+        }
+
+        let value = U256::from_dec_str(&digits).map_err(|_| ())?;
+
+        Ok(Price {
+            value,
+            decimals: fraction.len() as u32,
+        })
+    }
+}
+
+/// Exact price of a currency in another one, as a fraction, e.g. the product of the prices
+/// along a path of pairs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ratio {
+    pub numerator: BigInt,
+    pub denominator: BigInt,
+}
+
+impl Ratio {
+    pub fn one() -> Self {
+        Self {
+            numerator: BigInt::from(1),
+            denominator: BigInt::from(1),
+        }
+    }
+
+    /// Chains two prices, e.g. a token in WETH and WETH in USDC into the token in USDC.
+    pub fn mul(&self, other: &Ratio) -> Ratio {
+        Ratio {
+            numerator: &self.numerator * &other.numerator,
+            denominator: &self.denominator * &other.denominator,
+        }
+    }
+
+    /// Price of the quote currency in the base currency.
+    pub fn inverse(&self) -> Ratio {
+        Ratio {
+            numerator: self.denominator.clone(),
+            denominator: self.numerator.clone(),
+        }
+    }
+
+    /// Rounds the ratio down to a price with `decimals` decimals, or `None` if it does not fit.
+    pub fn to_price(&self, decimals: u32) -> Option<Price> {
+        if self.denominator.is_zero() {
+            return None;
+        }
+        let value = &self.numerator * BigInt::from(10).pow(decimals) / &self.denominator;
+        let (_, bytes) = value.to_bytes_be();
+        if bytes.len() > 32 {
+            return None;
+        }
+
+        Some(Price::new(U256::from_big_endian(&bytes), decimals))
+    }
+}
+
+impl From<&Price> for Ratio {
+    fn from(price: &Price) -> Self {
+        let mut bytes = [0u8; 32];
+        price.value.to_big_endian(&mut bytes);
+
+        Ratio {
+            numerator: BigInt::from_bytes_be(num_bigint::Sign::Plus, &bytes),
+            denominator: BigInt::from(10).pow(price.decimals),
+        }
+    }
+}
+
+/// A price as last observed on an exchange.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PricePoint {
+    pub price: Price,
+    pub exchange: Exchange,
+    /// Unix time at which the exchange published the price.
+    pub timestamp: u64,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Token {
     pub name: String,
@@ -44,8 +192,24 @@ pub struct Token {
     pub symbol: CurrencyCode,
 }
 
+/// Tokens to pay to liquidate a position, borrowed with a flash loan when the liquidation account
+/// does not hold them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequiredFunds {
+    pub token: Address,
+    pub amount: U256,
+    /// Fee of borrowing `amount` with a flash loan in wei, if the token can be priced in ETH.
+    pub flash_loan_fee: Option<U256>,
+}
+
 #[derive(Debug)]
 pub struct Liquidation {
     pub strategy: Address,
     pub position_id: U256,
+    /// Reward of the liquidation in wei, if the collateral can be priced in ETH.
+    pub expected_reward: Option<U256>,
+    /// Net profit of the liquidation in wei, if its reward and the gas fees are known.
+    pub expected_profit: Option<BigInt>,
+    /// Debt of the position, i.e. its principal and due fees in the owed token.
+    pub required_funds: RequiredFunds,
 }
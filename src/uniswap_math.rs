@@ -0,0 +1,97 @@
+//! Exact integer versions of the Uniswap V3 price formulas.
+//!
+//! Pool prices are square roots in Q64.96 fixed point, they are turned into exact ratios which
+//! are only rounded when converted into a `Price`.
+
+use num_bigint::BigInt;
+use num_traits::Pow;
+use web3::types::U256;
+
+use crate::types::Ratio;
+use crate::vault_math;
+
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+
+/// `2^128 / sqrt(1.0001)^(2^i)` in Q128.128, for every bit `i` of a tick, as in `TickMath`.
+const TICK_RATIOS: [&str; 20] = [
+    "fffcb933bd6fad37aa2d162d1a594001",
+    "fff97272373d413259a46990580e213a",
+    "fff2e50f5f656932ef12357cf3c7fdcc",
+    "ffe5caca7e10e4e61c3624eaa0941cd0",
+    "ffcb9843d60f6159c9db58835c926644",
+    "ff973b41fa98c081472e6896dfb254c0",
+    "ff2ea16466c96a3843ec78b326b52861",
+    "fe5dee046a99a2a811c461f1969c3053",
+    "fcbe86c7900a88aedcffc83b479aa3a4",
+    "f987a7253ac413176f2b074cf7815e54",
+    "f3392b0822b70005940c7a398e4b70f3",
+    "e7159475a2c29b7443b29c7fa6e889d9",
+    "d097f3bdfd2022b8845ad8f792aa5825",
+    "a9f746462d870fdf8a65dc1f90e061e5",
+    "70d869a156d2a1b890bb3df62baf32f7",
+    "31be135f97d08fd981231505542fcfa6",
+    "9aa508b5b7a84e1c677de54f3e99bc9",
+    "5d6af8dedb81196699c329225ee604",
+    "2216e584f5fa1ea926041bedfe98",
+    "48a170391f7dc42444e8fa2",
+];
+
+/// Returns `sqrt(1.0001^tick) * 2^96`, rounded up exactly as `TickMath.getSqrtRatioAtTick`, or
+/// `None` if the tick is out of range.
+pub fn sqrt_ratio_at_tick(tick: i32) -> Option<U256> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 {
+        U256::from_str_radix(TICK_RATIOS[0], 16).unwrap()
+    } else {
+        U256::one() << 128
+    };
+    for (bit, tick_ratio) in TICK_RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << bit) != 0 {
+            // Both factors are below 2^128, so the product cannot overflow.
+            ratio = (ratio * U256::from_str_radix(tick_ratio, 16).unwrap()) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Back to Q64.96, rounding up so that the ratio never underestimates the tick.
+    let remainder = ratio & U256::from(u32::MAX);
+    Some((ratio >> 32) + if remainder.is_zero() { 0 } else { 1 })
+}
+
+/// Returns the average tick between two `observe()` tick cumulatives `window` seconds apart,
+/// rounded towards negative infinity as in `OracleLibrary.consult`.
+pub fn average_tick(tick_cumulative_start: i64, tick_cumulative_end: i64, window: u32) -> i32 {
+    let delta = tick_cumulative_end - tick_cumulative_start;
+    delta.div_euclid(window as i64) as i32
+}
+
+/// Converts the square root price of a pool into the exact price of one whole token in the
+/// other, taking the decimals of both tokens into account:
+///
+/// `sqrt_price_x96^2 * 10^decimals_0 / (2^192 * 10^decimals_1)` units of token1 per token0 if
+/// `zero_for_one`, its inverse otherwise.
+pub fn pool_price(
+    sqrt_price_x96: U256,
+    decimals_0: u32,
+    decimals_1: u32,
+    zero_for_one: bool,
+) -> Ratio {
+    let sqrt_price = vault_math::to_big_int(sqrt_price_x96);
+    let token_1_per_token_0 = Ratio {
+        numerator: &sqrt_price * &sqrt_price * BigInt::from(10).pow(decimals_0),
+        denominator: (BigInt::from(1) << 192) * BigInt::from(10).pow(decimals_1),
+    };
+
+    if zero_for_one {
+        token_1_per_token_0
+    } else {
+        token_1_per_token_0.inverse()
+    }
+}
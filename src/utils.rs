@@ -1,49 +1,745 @@
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
+use secp256k1::SecretKey;
+use serde::Deserialize;
+use web3::types::{Address, U256};
+
+use crate::executor::signer::{self, LocalSigner, RemoteSigner, Signer, SignerError};
+use crate::executor::{self, batch, flash_loan, gas};
 use crate::feeds;
 use crate::liquidation_bot::Configuration;
-use crate::types::Token;
-
-pub fn load_token_list() -> Result<Vec<Token>, ()> {
-    let file = fs::File::open("deployed/goerli/deployments/tokenlist.json").unwrap();
-    let json: serde_json::Value = serde_json::from_reader(file).unwrap();
-    if let Some(tokens_array) = json.get("tokens").unwrap().as_array() {
-        let tokens: Vec<Token> = tokens_array
-            .into_iter()
-            .map(|token| serde_json::from_value(token.clone()).unwrap())
-            .collect();
-        Ok(tokens)
-    } else {
-        Err(())
+use crate::liquidator;
+use crate::oracle;
+use crate::profitability;
+use crate::readiness;
+use crate::types::{Pair, Token};
+
+// Seconds between two reads of the Chainlink feeds, in case some `AnswerUpdated` log is missed.
+const CHAINLINK_POLL_INTERVAL: u64 = 30;
+// Seconds between two reads of the Uniswap pools.
+const UNISWAP_POLL_INTERVAL: u64 = 30;
+// Seconds between two checks of the pending liquidation transactions.
+const EXECUTOR_POLL_INTERVAL: u64 = 4;
+// Maximum number of positions liquidated in a single transaction.
+const MAX_BATCH_SIZE: usize = 20;
+
+#[derive(Debug)]
+pub enum ConfigurationError {
+    Io(String, std::io::Error),
+    InvalidFile(String, String),
+    UnknownNetwork(String),
+    MissingEnvironmentVariable(String),
+    InvalidKey(String, String),
+    Signer(SignerError),
+}
+
+impl fmt::Display for ConfigurationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigurationError::Io(path, error) => write!(f, "cannot read {}: {}", path, error),
+            ConfigurationError::InvalidFile(path, reason) => {
+                write!(f, "invalid file {}: {}", path, reason)
+            }
+            ConfigurationError::UnknownNetwork(network) => {
+                write!(f, "no profile for network {}", network)
+            }
+            ConfigurationError::MissingEnvironmentVariable(name) => {
+                write!(f, "environment variable {} is not set", name)
+            }
+            ConfigurationError::InvalidKey(name, reason) => {
+                write!(f, "invalid private key in {}: {}", name, reason)
+            }
+            ConfigurationError::Signer(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigurationError {}
+
+/// A named network profile, declared as e.g. `[networks.goerli]` in the configuration file.
+///
+/// Provider URLs may reference environment variables as `${NAME}`, so that API keys do not have
+/// to be written in the file.
+#[derive(Debug, Deserialize)]
+pub struct NetworkProfile {
+    pub ethereum_provider_https_url: String,
+    pub ethereum_provider_wss_url: String,
+    /// Directory holding the `abi/` and `deployments/` folders of the Ithil contracts.
+    pub deployment_dir: String,
+    /// Block where the Ithil contracts were deployed, i.e. where their event history starts.
+    #[serde(default)]
+    pub deployment_block: u64,
+    /// Maximum number of blocks covered by a single `eth_getLogs` request.
+    #[serde(default = "default_log_page_size")]
+    pub log_page_size: u64,
+    /// Directory of the local store holding checkpoints, `data/<network>` by default.
+    pub store_path: Option<String>,
+    /// Number of blocks after which events are considered final and safe from reorganisations.
+    #[serde(default = "default_confirmations")]
+    pub confirmations: u64,
+    /// Number of blocks between two checkpoints of the positions state.
+    #[serde(default = "default_checkpoint_interval")]
+    pub checkpoint_interval: u64,
+    /// Age in seconds after which a price can no longer trigger liquidations.
+    #[serde(default = "default_max_price_age")]
+    pub max_price_age: u64,
+    /// Per pair overrides of `max_price_age`, e.g. `max_price_ages = { "DAI-USD" = 300 }`.
+    #[serde(default)]
+    pub max_price_ages: HashMap<String, u64>,
+    /// Chainlink price feeds, by pair.
+    #[serde(default)]
+    pub chainlink_feeds: HashMap<String, ChainlinkFeedProfile>,
+    /// Uniswap V3 pools pricing the tokens, directly against USDC or through other tokens.
+    #[serde(default)]
+    pub uniswap_pools: Vec<String>,
+    /// Length in seconds of the Uniswap time-weighted average prices, 0 for spot prices.
+    #[serde(default = "default_uniswap_twap_window")]
+    pub uniswap_twap_window: u32,
+    /// Whether pools whose observations do not cover the TWAP window are priced with their spot
+    /// price rather than skipped.
+    #[serde(default)]
+    pub uniswap_spot_fallback: bool,
+    /// Minimum number of price sources agreeing on a price before it can trigger liquidations.
+    #[serde(default = "default_min_price_sources")]
+    pub min_price_sources: usize,
+    /// Maximum deviation of a source from the median price, in basis points.
+    #[serde(default = "default_max_price_deviation")]
+    pub max_price_deviation: u32,
+    /// Delay before reconnecting a dropped feed, doubled after every failed attempt.
+    #[serde(default = "default_reconnect_initial_backoff_ms")]
+    pub reconnect_initial_backoff_ms: u64,
+    #[serde(default = "default_reconnect_max_backoff_ms")]
+    pub reconnect_max_backoff_ms: u64,
+    /// Name of the environment variable holding the private key of the liquidation account, when
+    /// it is neither in a keystore nor held by a remote signer.
+    #[serde(default = "default_private_key_env")]
+    pub private_key_env: String,
+    /// Encrypted JSON keystore holding the key of the liquidation account.
+    pub keystore: Option<KeystoreProfile>,
+    /// Remote signer, e.g. Web3Signer, holding the key of the liquidation account.
+    pub remote_signer: Option<RemoteSignerProfile>,
+    /// Several liquidation accounts, declared as `[[networks.<name>.wallets]]` with the same
+    /// fields as above, to send liquidations in parallel. Replaces the single account above.
+    #[serde(default)]
+    pub wallets: Vec<WalletProfile>,
+    /// Balance in wei below which a liquidation account should be topped up.
+    #[serde(default = "default_min_wallet_balance")]
+    pub min_wallet_balance: u64,
+    /// Seconds without events after which a feed is no longer ready.
+    #[serde(default = "default_max_feed_silence")]
+    pub max_feed_silence: u64,
+    /// Feeds which may stay silent for long, whose readiness only requires them to be connected.
+    #[serde(default = "default_quiet_feeds")]
+    pub quiet_feeds: Vec<String>,
+    /// Share of the collateral of a position paid by the `Liquidator` contract, in basis points.
+    /// Gas spending is only capped against the reward when it is set.
+    pub liquidation_reward: Option<u32>,
+    /// `eip1559`, or `legacy` for networks without a base fee.
+    #[serde(default = "default_transaction_type")]
+    pub transaction_type: gas::TransactionType,
+    /// `low`, `medium` or `high`: the higher, the faster liquidations are included and the more
+    /// they pay for gas.
+    #[serde(default = "default_gas_urgency")]
+    pub gas_urgency: gas::Urgency,
+    /// Margin added to gas estimates, in basis points.
+    #[serde(default = "default_gas_limit_margin")]
+    pub gas_limit_margin: u32,
+    /// Maximum share of the reward of a liquidation spent on gas, in basis points.
+    #[serde(default = "default_max_gas_reward_share")]
+    pub max_gas_reward_share: u32,
+    /// Number of blocks after which a pending liquidation is sent again with higher fees.
+    #[serde(default = "default_stuck_blocks")]
+    pub stuck_blocks: u64,
+    /// Number of times a liquidation is sped up before being cancelled, and its cancellation
+    /// before being reported stuck.
+    #[serde(default = "default_max_speed_ups")]
+    pub max_speed_ups: u32,
+    /// Number of failed liquidations of a position after which it is left alone.
+    #[serde(default = "default_max_liquidation_attempts")]
+    pub max_liquidation_attempts: u32,
+    /// Delay in seconds before liquidating a position again after a failure, doubled after every
+    /// failure.
+    #[serde(default = "default_liquidation_retry_backoff")]
+    pub liquidation_retry_backoff: u64,
+    /// Gas used by the liquidation of a position, to estimate its cost.
+    #[serde(default = "default_liquidation_gas")]
+    pub liquidation_gas: u64,
+    /// Tip paid on top of the base fee, in wei per gas, to estimate the cost of liquidations.
+    #[serde(default = "default_priority_fee_per_gas")]
+    pub priority_fee_per_gas: u64,
+    /// Share of the reward lost when selling it, in basis points.
+    #[serde(default = "default_liquidation_slippage")]
+    pub liquidation_slippage: u32,
+    /// Minimum net profit of a liquidation in wei, dust positions making less are left alone.
+    /// Profits are estimated from `liquidation_reward`, which is then required.
+    #[serde(default)]
+    pub min_liquidation_profit: u64,
+    /// `aave` or `balancer`, to borrow the debt of positions when the liquidation account does
+    /// not hold it.
+    pub flash_loan_lender: Option<flash_loan::Lender>,
+    /// Address of the Aave pool or of the Balancer vault.
+    pub flash_loan_lender_address: Option<String>,
+    /// Contract receiving flash loans, which liquidates the position and pays the loan back.
+    pub flash_loan_receiver_address: Option<String>,
+    /// Fee of flash loans, in basis points of the amount borrowed.
+    #[serde(default = "default_flash_loan_fee")]
+    pub flash_loan_fee: u32,
+    /// Time during which the liquidations of a strategy are grouped into a single transaction.
+    #[serde(default = "default_batch_window_ms")]
+    pub batch_window_ms: u64,
+    /// Function of the `Liquidator` liquidating several positions of a strategy, if any.
+    pub batch_liquidation_function: Option<String>,
+    /// Bundle relay receiving liquidations privately, e.g. `https://relay.flashbots.net`, instead
+    /// of the public mempool.
+    pub relay_url: Option<String>,
+    /// Name of the environment variable holding the key signing bundles, required with
+    /// `relay_url`. It only identifies the bot to the relay, so it should hold no funds.
+    pub relay_signing_key_env: Option<String>,
+    /// Number of blocks targeted by a bundle before its liquidation is broadcast publicly.
+    #[serde(default = "default_relay_max_missed_blocks")]
+    pub relay_max_missed_blocks: u64,
+}
+
+fn default_log_page_size() -> u64 {
+    5000
+}
+
+fn default_confirmations() -> u64 {
+    12
+}
+
+fn default_checkpoint_interval() -> u64 {
+    100
+}
+
+fn default_max_price_age() -> u64 {
+    60
+}
+
+fn default_uniswap_twap_window() -> u32 {
+    600
+}
+
+fn default_min_price_sources() -> usize {
+    1
+}
+
+fn default_max_price_deviation() -> u32 {
+    200
+}
+
+fn default_reconnect_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_reconnect_max_backoff_ms() -> u64 {
+    60000
+}
+
+fn default_private_key_env() -> String {
+    String::from("PRIVATE_KEY")
+}
+
+fn default_min_wallet_balance() -> u64 {
+    100_000_000_000_000_000
+}
+
+fn default_max_feed_silence() -> u64 {
+    120
+}
+
+fn default_quiet_feeds() -> Vec<String> {
+    vec![String::from("ithil")]
+}
+
+fn default_transaction_type() -> gas::TransactionType {
+    gas::TransactionType::Eip1559
+}
+
+fn default_gas_urgency() -> gas::Urgency {
+    gas::Urgency::Medium
+}
+
+fn default_gas_limit_margin() -> u32 {
+    2000
+}
+
+fn default_max_gas_reward_share() -> u32 {
+    5000
+}
+
+fn default_stuck_blocks() -> u64 {
+    3
+}
+
+fn default_max_speed_ups() -> u32 {
+    3
+}
+
+fn default_max_liquidation_attempts() -> u32 {
+    5
+}
+
+fn default_liquidation_retry_backoff() -> u64 {
+    60
+}
+
+fn default_liquidation_gas() -> u64 {
+    500_000
+}
+
+fn default_priority_fee_per_gas() -> u64 {
+    2_000_000_000
+}
+
+fn default_liquidation_slippage() -> u32 {
+    50
+}
+
+fn default_flash_loan_fee() -> u32 {
+    5
+}
+
+fn default_batch_window_ms() -> u64 {
+    1000
+}
+
+fn default_relay_max_missed_blocks() -> u64 {
+    3
+}
+
+/// A Chainlink price feed, declared as e.g. `chainlink_feeds = { "ETH-USD" = { ... } }`.
+#[derive(Debug, Deserialize)]
+pub struct ChainlinkFeedProfile {
+    /// Address of the `AggregatorV3Interface` proxy.
+    pub address: String,
+    /// Maximum time in seconds between two rounds of the feed.
+    pub heartbeat: u64,
+}
+
+/// A keystore, declared as `keystore = { path = "...", password_file = "..." }`.
+#[derive(Debug, Deserialize)]
+pub struct KeystoreProfile {
+    pub path: String,
+    /// File holding the password of the keystore.
+    pub password_file: String,
+}
+
+/// A liquidation account, whose key is held by a remote signer, in a keystore or in an
+/// environment variable.
+#[derive(Debug, Deserialize)]
+pub struct WalletProfile {
+    pub private_key_env: Option<String>,
+    pub keystore: Option<KeystoreProfile>,
+    pub remote_signer: Option<RemoteSignerProfile>,
+}
+
+/// A remote signer, declared as `remote_signer = { url = "...", address = "..." }`.
+#[derive(Debug, Deserialize)]
+pub struct RemoteSignerProfile {
+    pub url: String,
+    /// Address of the liquidation account, whose key the signer holds.
+    pub address: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigurationFile {
+    networks: HashMap<String, NetworkProfile>,
+}
+
+fn read_file(path: &str) -> Result<String, ConfigurationError> {
+    fs::read_to_string(path).map_err(|error| ConfigurationError::Io(String::from(path), error))
+}
+
+fn read_json(path: &str) -> Result<serde_json::Value, ConfigurationError> {
+    serde_json::from_str(&read_file(path)?)
+        .map_err(|error| ConfigurationError::InvalidFile(String::from(path), error.to_string()))
+}
+
+/// Parses the address of `name`, declared in the file at `path`.
+fn parse_address(path: &str, name: &str, address: &str) -> Result<Address, ConfigurationError> {
+    Address::from_str(address).map_err(|error| {
+        ConfigurationError::InvalidFile(
+            String::from(path),
+            format!("invalid {} address {}: {}", name, address, error),
+        )
+    })
+}
+
+fn read_abi(path: &str) -> Result<web3::ethabi::Contract, ConfigurationError> {
+    web3::ethabi::Contract::load(read_file(path)?.as_bytes())
+        .map_err(|error| ConfigurationError::InvalidFile(String::from(path), error.to_string()))
+}
+
+fn read_env(name: &str) -> Result<String, ConfigurationError> {
+    env::var(name).map_err(|_| ConfigurationError::MissingEnvironmentVariable(String::from(name)))
+}
+
+fn read_secret(name: &str) -> Result<SecretKey, ConfigurationError> {
+    let secret = read_env(name)?;
+    SecretKey::from_str(secret.trim_start_matches("0x"))
+        .map_err(|error| ConfigurationError::InvalidKey(String::from(name), error.to_string()))
+}
+
+/// Signer of a liquidation account, and its key unless it is held by a remote signer.
+fn load_signer(
+    config_path: &str,
+    private_key_env: &str,
+    keystore: Option<&KeystoreProfile>,
+    remote_signer: Option<&RemoteSignerProfile>,
+) -> Result<Arc<dyn Signer>, ConfigurationError> {
+    if let Some(remote_signer) = remote_signer {
+        let address = Address::from_str(&remote_signer.address).map_err(|error| {
+            ConfigurationError::InvalidFile(
+                String::from(config_path),
+                format!("invalid remote signer address: {}", error),
+            )
+        })?;
+        let url = expand_env_vars(&remote_signer.url)?;
+        return Ok(Arc::new(RemoteSigner::new(&url, address)));
+    }
+
+    let secret = match keystore {
+        Some(keystore) => signer::read_keystore(&keystore.path, &keystore.password_file)
+            .map_err(ConfigurationError::Signer)?,
+        None => read_secret(private_key_env)?,
+    };
+    Ok(Arc::new(LocalSigner::new(secret)))
+}
+
+fn load_wallets(
+    config_path: &str,
+    profile: &NetworkProfile,
+) -> Result<Vec<Arc<dyn Signer>>, ConfigurationError> {
+    if profile.wallets.is_empty() {
+        return Ok(vec![load_signer(
+            config_path,
+            &profile.private_key_env,
+            profile.keystore.as_ref(),
+            profile.remote_signer.as_ref(),
+        )?]);
+    }
+
+    let wallets = profile
+        .wallets
+        .iter()
+        .map(|wallet| {
+            load_signer(
+                config_path,
+                wallet
+                    .private_key_env
+                    .as_deref()
+                    .unwrap_or(&profile.private_key_env),
+                wallet.keystore.as_ref(),
+                wallet.remote_signer.as_ref(),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The same account twice would have its nonces handed out by two wallets.
+    for (index, wallet) in wallets.iter().enumerate() {
+        if wallets[..index]
+            .iter()
+            .any(|other| other.address() == wallet.address())
+        {
+            return Err(ConfigurationError::InvalidFile(
+                String::from(config_path),
+                format!("wallet {:?} is declared more than once", wallet.address()),
+            ));
+        }
+    }
+
+    Ok(wallets)
+}
+
+/// Replaces every `${NAME}` in `value` with the content of the environment variable `NAME`.
+pub fn expand_env_vars(value: &str) -> Result<String, ConfigurationError> {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&read_env(&rest[start + 2..end])?);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+pub fn load_network_profile(
+    config_path: &str,
+    network: &str,
+) -> Result<NetworkProfile, ConfigurationError> {
+    let mut configuration_file: ConfigurationFile = toml::from_str(&read_file(config_path)?)
+        .map_err(|error| {
+            ConfigurationError::InvalidFile(String::from(config_path), error.to_string())
+        })?;
+
+    let profile = configuration_file
+        .networks
+        .remove(network)
+        .ok_or_else(|| ConfigurationError::UnknownNetwork(String::from(network)))?;
+
+    // Both are divisors of block ranges.
+    for (name, value) in [
+        ("log_page_size", profile.log_page_size),
+        ("checkpoint_interval", profile.checkpoint_interval),
+    ] {
+        if value == 0 {
+            return Err(ConfigurationError::InvalidFile(
+                String::from(config_path),
+                format!("{} must be positive", name),
+            ));
+        }
     }
+
+    Ok(profile)
+}
+
+pub fn load_token_list(deployment_dir: &str) -> Result<Vec<Token>, ConfigurationError> {
+    let path = format!("{}/deployments/tokenlist.json", deployment_dir);
+    let json = read_json(&path)?;
+
+    let invalid_token_list = |reason: String| ConfigurationError::InvalidFile(path.clone(), reason);
+    json.get("tokens")
+        .and_then(|tokens| tokens.as_array())
+        .ok_or_else(|| invalid_token_list(String::from("missing tokens array")))?
+        .iter()
+        .map(|token| {
+            serde_json::from_value(token.clone())
+                .map_err(|error| invalid_token_list(error.to_string()))
+        })
+        .collect()
 }
 
-pub fn load_config() -> Result<Configuration, ()> {
-    let file = fs::File::open("deployed/goerli/deployments/addresses.json").unwrap();
-    let json: serde_json::Value = serde_json::from_reader(file).unwrap();
-    let addresses = json.get("addresses").unwrap();
+pub fn load_config(config_path: &str, network: &str) -> Result<Configuration, ConfigurationError> {
+    let profile = load_network_profile(config_path, network)?;
+    let deployment_dir = &profile.deployment_dir;
+
+    let addresses_path = format!("{}/deployments/addresses.json", deployment_dir);
+    let json = read_json(&addresses_path)?;
+    let addresses: HashMap<String, String> = json
+        .get("addresses")
+        .cloned()
+        .and_then(|addresses| serde_json::from_value(addresses).ok())
+        .ok_or_else(|| {
+            ConfigurationError::InvalidFile(
+                addresses_path.clone(),
+                String::from("missing addresses map"),
+            )
+        })?;
 
-    let liquidator_address_value = addresses.get("Liquidator").unwrap();
-    let liquidator_address = liquidator_address_value.as_str().unwrap();
+    let liquidator_address = addresses.get("Liquidator").ok_or_else(|| {
+        ConfigurationError::InvalidFile(addresses_path.clone(), String::from("missing Liquidator"))
+    })?;
 
-    let margin_trading_strategy_value = addresses.get("MarginTradingStrategy").unwrap();
-    let margin_trading_strategy_address = margin_trading_strategy_value.as_str().unwrap();
+    // Every deployed strategy contract is monitored, e.g. MarginTradingStrategy, YearnStrategy.
+    let strategies = addresses
+        .iter()
+        .filter(|(name, _)| name.ends_with("Strategy"))
+        .map(|(name, address)| {
+            Ok(feeds::ithil::StrategyConfiguration {
+                name: name.clone(),
+                address: parse_address(&addresses_path, name, address)?,
+                abi: read_abi(&format!("{}/abi/{}.json", deployment_dir, name))?,
+            })
+        })
+        .collect::<Result<Vec<_>, ConfigurationError>>()?;
+    let vault_address = addresses
+        .get("Vault")
+        .map(|address| parse_address(&addresses_path, "Vault", address))
+        .transpose()?;
 
-    let infura_api_key = env::var("INFURA_API_KEY").unwrap();
-    let secret = env::var("PRIVATE_KEY").unwrap();
+    let parse_pair = |pair: &str| {
+        Pair::from_str(pair).map_err(|()| {
+            ConfigurationError::InvalidFile(
+                String::from(config_path),
+                format!("unknown pair {}", pair),
+            )
+        })
+    };
+    let max_price_ages = profile
+        .max_price_ages
+        .iter()
+        .map(|(pair, max_age)| Ok((parse_pair(pair)?, *max_age)))
+        .collect::<Result<HashMap<Pair, u64>, ConfigurationError>>()?;
+    let chainlink_aggregators = profile
+        .chainlink_feeds
+        .iter()
+        .map(|(pair, feed)| {
+            Ok(feeds::chainlink::AggregatorConfiguration {
+                pair: parse_pair(pair)?,
+                address: parse_address(
+                    config_path,
+                    &format!("{} Chainlink feed", pair),
+                    &feed.address,
+                )?,
+                heartbeat: feed.heartbeat,
+            })
+        })
+        .collect::<Result<Vec<_>, ConfigurationError>>()?;
+    let uniswap_pools = profile
+        .uniswap_pools
+        .iter()
+        .map(|pool| parse_address(config_path, "Uniswap pool", pool))
+        .collect::<Result<Vec<_>, ConfigurationError>>()?;
+    // Chainlink prices are as old as their round, which stays current up to the heartbeat.
+    let chainlink_max_price_ages = chainlink_aggregators
+        .iter()
+        .map(|aggregator| (aggregator.pair.clone(), aggregator.heartbeat))
+        .collect();
+
+    let ethereum_provider_https_url = expand_env_vars(&profile.ethereum_provider_https_url)?;
+    let ethereum_provider_wss_url = expand_env_vars(&profile.ethereum_provider_wss_url)?;
+    // Without the reward, profits cannot be estimated and every liquidation would be sent.
+    if profile.min_liquidation_profit > 0 && profile.liquidation_reward.is_none() {
+        return Err(ConfigurationError::InvalidFile(
+            String::from(config_path),
+            String::from("liquidation_reward is required with min_liquidation_profit"),
+        ));
+    }
+
+    let signers = load_wallets(config_path, &profile)?;
+    let relay = match &profile.relay_url {
+        Some(url) => Some(executor::relay::Configuration {
+            url: expand_env_vars(url)?,
+            signing_key: match &profile.relay_signing_key_env {
+                Some(name) => read_secret(name)?,
+                None => {
+                    return Err(ConfigurationError::InvalidFile(
+                        String::from(config_path),
+                        String::from("relay_signing_key_env is required with relay_url"),
+                    ))
+                }
+            },
+            max_missed_blocks: profile.relay_max_missed_blocks,
+        }),
+        None => None,
+    };
+    let flash_loan = match (
+        profile.flash_loan_lender,
+        &profile.flash_loan_lender_address,
+        &profile.flash_loan_receiver_address,
+    ) {
+        (Some(lender), Some(lender_address), Some(receiver_address)) => {
+            Some(flash_loan::Configuration {
+                lender,
+                lender_address: parse_address(config_path, "flash loan lender", lender_address)?,
+                receiver_address: parse_address(
+                    config_path,
+                    "flash loan receiver",
+                    receiver_address,
+                )?,
+                min_profit: U256::from(profile.min_liquidation_profit),
+            })
+        }
+        (None, None, None) => None,
+        // Flash loans would be silently left out.
+        _ => {
+            return Err(ConfigurationError::InvalidFile(
+                String::from(config_path),
+                String::from(
+                    "flash_loan_lender, flash_loan_lender_address and \
+                     flash_loan_receiver_address go together",
+                ),
+            ))
+        }
+    };
 
     Ok(Configuration {
-        liquidator_address: String::from(liquidator_address),
         ethereum_feed_configuration: feeds::ethereum_blocks::Configuration {
-            ethereum_provider_wss_url: format!("wss://goerli.infura.io/ws/v3/{}", infura_api_key),
+            ethereum_provider_wss_url: ethereum_provider_wss_url.clone(),
+        },
+        chainlink_feed_configuration: feeds::chainlink::Configuration {
+            ethereum_provider_wss_url: ethereum_provider_wss_url.clone(),
+            aggregators: chainlink_aggregators,
+            poll_interval: Duration::from_secs(CHAINLINK_POLL_INTERVAL),
+        },
+        uniswap_feed_configuration: feeds::uniswap::Configuration {
+            ethereum_provider_wss_url: ethereum_provider_wss_url.clone(),
+            pools: uniswap_pools,
+            twap_window: profile.uniswap_twap_window,
+            spot_fallback: profile.uniswap_spot_fallback,
+            poll_interval: Duration::from_secs(UNISWAP_POLL_INTERVAL),
+        },
+        executor_configuration: executor::Configuration {
+            ethereum_provider_wss_url: ethereum_provider_wss_url.clone(),
+            liquidator_address: liquidator_address.clone(),
+            liquidator_abi_path: format!("{}/abi/Liquidator.json", deployment_dir),
+            wallets: executor::wallets::Configuration {
+                signers,
+                min_balance: U256::from(profile.min_wallet_balance),
+            },
+            gas: gas::Configuration {
+                transaction_type: profile.transaction_type,
+                urgency: profile.gas_urgency,
+                gas_limit_margin: profile.gas_limit_margin,
+                max_reward_share: profile.max_gas_reward_share,
+                ..gas::Configuration::default()
+            },
+            poll_interval: Duration::from_secs(EXECUTOR_POLL_INTERVAL),
+            stuck_blocks: profile.stuck_blocks,
+            max_speed_ups: profile.max_speed_ups,
+            batch: batch::Configuration {
+                window: Duration::from_millis(profile.batch_window_ms),
+                max_size: MAX_BATCH_SIZE,
+                liquidator_function: profile.batch_liquidation_function.clone(),
+            },
+            relay,
+            flash_loan,
         },
         ithil_feed_configuration: feeds::ithil::Configuration {
-            ethereum_provider_https_url: format!("https://goerli.infura.io/v3/{}", infura_api_key),
-            ethereum_provider_wss_url: format!("wss://goerli.infura.io/ws/v3/{}", infura_api_key),
-            margin_trading_strategy_address: String::from(margin_trading_strategy_address),
+            ethereum_provider_https_url,
+            ethereum_provider_wss_url,
+            deployment_block: profile.deployment_block,
+            log_page_size: profile.log_page_size,
+            strategies,
+            vault_address,
+        },
+        supervisor_configuration: feeds::supervisor::Configuration {
+            initial_backoff: Duration::from_millis(profile.reconnect_initial_backoff_ms),
+            max_backoff: Duration::from_millis(profile.reconnect_max_backoff_ms),
+        },
+        liquidator_configuration: liquidator::Configuration {
+            confirmations: profile.confirmations,
+            max_price_age: profile.max_price_age,
+            max_price_ages,
+            chainlink_max_price_ages,
+            oracle: oracle::Configuration {
+                min_sources: profile.min_price_sources,
+                max_deviation: profile.max_price_deviation,
+            },
+            liquidation_reward: profile.liquidation_reward,
+            max_liquidation_attempts: profile.max_liquidation_attempts,
+            liquidation_retry_backoff: profile.liquidation_retry_backoff,
+            profitability: profitability::Configuration {
+                liquidation_gas: profile.liquidation_gas,
+                priority_fee_per_gas: U256::from(profile.priority_fee_per_gas),
+                slippage: profile.liquidation_slippage,
+                min_profit: U256::from(profile.min_liquidation_profit),
+                flash_loan_fee: profile.flash_loan_fee,
+            },
+        },
+        readiness_configuration: readiness::Configuration {
+            max_silence: Duration::from_secs(profile.max_feed_silence),
+            quiet_feeds: profile.quiet_feeds.clone(),
         },
-        secret,
-        tokens: load_token_list().unwrap(),
+        store_path: profile
+            .store_path
+            .clone()
+            .unwrap_or_else(|| format!("data/{}", network)),
+        checkpoint_interval: profile.checkpoint_interval,
+        tokens: load_token_list(deployment_dir)?,
     })
 }
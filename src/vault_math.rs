@@ -0,0 +1,89 @@
+//! Exact integer versions of the on-chain `VaultMath` formulas.
+//!
+//! All intermediate values are kept as `BigInt`, so none of these functions can overflow or lose
+//! precision, whatever the size of the position. Divisions round towards zero, as in Solidity.
+
+use num_bigint::{BigInt, Sign};
+use num_traits::{Pow, Zero};
+use web3::types::U256;
+
+use crate::types::Ratio;
+
+pub const RESOLUTION: u32 = 10000;
+pub const TIME_FEE_PERIOD: u32 = 86400;
+
+pub fn to_big_int(value: U256) -> BigInt {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    BigInt::from_bytes_be(Sign::Plus, &bytes)
+}
+
+/// Converts back a value which is neither negative nor larger than 256 bits.
+pub fn from_big_int(value: &BigInt) -> Option<U256> {
+    let (sign, bytes) = value.to_bytes_be();
+    if sign == Sign::Minus || bytes.len() > 32 {
+        return None;
+    }
+    Some(U256::from_big_endian(&bytes))
+}
+
+fn pow10(exponent: u32) -> BigInt {
+    BigInt::from(10).pow(exponent)
+}
+
+/// Converts `amount` base units of a token with `src_decimals` into base units of a token with
+/// `dst_decimals`, `rate` being the price of the source token in the destination token:
+///
+/// `amount * rate * 10^dst_decimals / 10^src_decimals`
+///
+/// Returns `None` if the rate is infinite.
+pub fn convert(
+    amount: &BigInt,
+    rate: &Ratio,
+    src_decimals: u32,
+    dst_decimals: u32,
+) -> Option<BigInt> {
+    if rate.denominator.is_zero() {
+        return None;
+    }
+
+    let numerator = amount * &rate.numerator * pow10(dst_decimals);
+    let denominator = &rate.denominator * pow10(src_decimals);
+
+    Some(numerator / denominator)
+}
+
+/// Risk factor of a pair of tokens, i.e. the average of the two token risk factors.
+pub fn pair_risk_factor(token_0_risk_factor: U256, token_1_risk_factor: U256) -> BigInt {
+    (to_big_int(token_0_risk_factor) + to_big_int(token_1_risk_factor)) / 2
+}
+
+/// Fees owed by a position at `timestamp`: the fixed fees plus the time fees accrued since the
+/// position was opened.
+pub fn due_fees(
+    fixed_fees: U256,
+    interest_rate: U256,
+    principal: U256,
+    created_at: U256,
+    timestamp: U256,
+) -> BigInt {
+    let elapsed = to_big_int(timestamp) - to_big_int(created_at);
+
+    to_big_int(fixed_fees)
+        + (to_big_int(interest_rate) * elapsed * to_big_int(principal))
+            / (BigInt::from(TIME_FEE_PERIOD) * BigInt::from(RESOLUTION))
+}
+
+/// Liquidation score of a position: strictly positive scores mean the position can be
+/// liquidated.
+pub fn liquidation_score(
+    collateral: U256,
+    pair_risk_factor: &BigInt,
+    profit_and_loss: &BigInt,
+) -> BigInt {
+    to_big_int(collateral) * pair_risk_factor - profit_and_loss * BigInt::from(RESOLUTION)
+}
+
+pub fn is_liquidable(liquidation_score: &BigInt) -> bool {
+    liquidation_score > &BigInt::zero()
+}
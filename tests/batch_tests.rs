@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use liquidation_bot::executor::batch::{self, BatchCall, Batcher};
+use liquidation_bot::types::{Liquidation, RequiredFunds};
+
+use tokio::time::Instant;
+use web3::ethabi::{self, Token};
+use web3::types::{Address, U256};
+
+const LIQUIDATOR_ABI: &str = r#"[
+    {"type": "function", "name": "liquidateSingle", "inputs": [{"name": "strategy", "type": "address"}, {"name": "positionId", "type": "uint256"}], "outputs": [], "stateMutability": "nonpayable"},
+    {"type": "function", "name": "liquidateBatch", "inputs": [{"name": "strategy", "type": "address"}, {"name": "positionIds", "type": "uint256[]"}], "outputs": [], "stateMutability": "nonpayable"}
+]"#;
+
+fn liquidation(strategy: u64, position_id: u64) -> Liquidation {
+    Liquidation {
+        strategy: Address::from_low_u64_be(strategy),
+        position_id: U256::from(position_id),
+        expected_reward: None,
+        expected_profit: None,
+        required_funds: RequiredFunds {
+            token: Address::zero(),
+            amount: U256::zero(),
+            flash_loan_fee: None,
+        },
+    }
+}
+
+fn configuration(liquidator_function: Option<&str>) -> batch::Configuration {
+    batch::Configuration {
+        window: Duration::from_millis(1000),
+        max_size: 3,
+        liquidator_function: liquidator_function.map(String::from),
+    }
+}
+
+#[test]
+fn test_liquidations_are_grouped_per_strategy() {
+    let start = Instant::now();
+    let mut batcher = Batcher::new(Duration::from_millis(1000), 3);
+    assert_eq!(batcher.deadline(), None);
+
+    assert!(batcher.push(liquidation(1, 1), start).is_none());
+    assert!(batcher
+        .push(liquidation(2, 1), start + Duration::from_millis(500))
+        .is_none());
+    assert!(batcher
+        .push(liquidation(1, 2), start + Duration::from_millis(600))
+        .is_none());
+    assert_eq!(
+        batcher.deadline(),
+        Some(start + Duration::from_millis(1000))
+    );
+
+    assert!(batcher
+        .take_due(start + Duration::from_millis(999))
+        .is_empty());
+    let due = batcher.take_due(start + Duration::from_millis(1000));
+    assert_eq!(due.len(), 1);
+    let position_ids: Vec<U256> = due[0].iter().map(|l| l.position_id).collect();
+    assert_eq!(position_ids, vec![U256::from(1), U256::from(2)]);
+    assert_eq!(
+        batcher.deadline(),
+        Some(start + Duration::from_millis(1500))
+    );
+
+    // Full batches are sent without waiting.
+    assert!(batcher.push(liquidation(2, 2), start).is_none());
+    assert_eq!(batcher.push(liquidation(2, 3), start).unwrap().len(), 3);
+    assert_eq!(batcher.deadline(), None);
+}
+
+#[test]
+fn test_batches_go_through_the_liquidator() {
+    let liquidator_abi = ethabi::Contract::load(LIQUIDATOR_ABI.as_bytes()).unwrap();
+    let strategy = Address::from_low_u64_be(0x2222);
+    let position_ids = [U256::from(1), U256::from(2)];
+
+    let batch_call =
+        BatchCall::new(&configuration(Some("liquidateBatch")), &liquidator_abi).unwrap();
+    assert_eq!(
+        batch_call.encode(strategy, &position_ids).0,
+        liquidator_abi
+            .function("liquidateBatch")
+            .unwrap()
+            .encode_input(&[
+                Token::Address(strategy),
+                Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into())])
+            ])
+            .unwrap()
+    );
+
+    // Without a batch function, positions are liquidated one by one.
+    assert!(BatchCall::new(&configuration(Some("liquidateMany")), &liquidator_abi).is_none());
+    assert!(BatchCall::new(&configuration(None), &liquidator_abi).is_none());
+}
@@ -0,0 +1,79 @@
+use liquidation_bot::feeds::chainlink::{
+    ticker_from_round, AggregatorConfiguration, Round, StaleRound,
+};
+use liquidation_bot::types::{CurrencyCode, Exchange, Pair, Price};
+
+use web3::types::U256;
+
+const NOW: u64 = 1_700_000_000;
+
+fn eth_usd_aggregator() -> AggregatorConfiguration {
+    AggregatorConfiguration {
+        pair: Pair(CurrencyCode::WETH, CurrencyCode::USD),
+        address: "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419"
+            .parse()
+            .unwrap(),
+        heartbeat: 3600,
+    }
+}
+
+fn round(answer: U256, updated_at: u64) -> Round {
+    Round {
+        round_id: U256::from(42),
+        answer,
+        updated_at: U256::from(updated_at),
+        answered_in_round: U256::from(42),
+    }
+}
+
+#[test]
+fn test_round_is_converted_with_the_feed_decimals() {
+    // 1834.56789012 USD with 8 decimals.
+    let ticker = ticker_from_round(
+        &eth_usd_aggregator(),
+        8,
+        &round(U256::from(183456789012u64), NOW - 600),
+        NOW,
+    )
+    .unwrap();
+
+    assert_eq!(ticker.exchange, Exchange::Chainlink);
+    assert_eq!(ticker.pair, Pair(CurrencyCode::WETH, CurrencyCode::USD));
+    assert_eq!(ticker.price, Price::new(U256::from(183456789012u64), 8));
+    // The price is as old as the round, not as recent as the check.
+    assert_eq!(ticker.timestamp, NOW - 600);
+}
+
+#[test]
+fn test_stale_rounds_are_rejected() {
+    let aggregator = eth_usd_aggregator();
+    let answer = U256::from(183456789012u64);
+
+    assert_eq!(
+        ticker_from_round(&aggregator, 8, &round(answer, NOW - 3601), NOW).unwrap_err(),
+        StaleRound::Expired {
+            age: 3601,
+            heartbeat: 3600
+        }
+    );
+    assert_eq!(
+        ticker_from_round(&aggregator, 8, &round(answer, 0), NOW).unwrap_err(),
+        StaleRound::Incomplete
+    );
+
+    let carried_over = Round {
+        answered_in_round: U256::from(41),
+        ..round(answer, NOW)
+    };
+    assert!(matches!(
+        ticker_from_round(&aggregator, 8, &carried_over, NOW),
+        Err(StaleRound::CarriedOver { .. })
+    ));
+
+    // -1 in two's complement.
+    let negative_answer = U256::MAX;
+    assert_eq!(
+        ticker_from_round(&aggregator, 8, &round(negative_answer, NOW), NOW).unwrap_err(),
+        StaleRound::InvalidAnswer(negative_answer)
+    );
+}
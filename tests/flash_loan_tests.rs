@@ -0,0 +1,92 @@
+use liquidation_bot::executor::flash_loan::{Configuration, FlashLoan, Lender};
+use liquidation_bot::types::RequiredFunds;
+
+use web3::ethabi::{self, ParamType, Token};
+use web3::signing;
+use web3::types::{Address, Bytes, U256};
+
+const LENDER_ADDRESS: &str = "0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2";
+const RECEIVER_ADDRESS: &str = "0x00000000000000000000000000000000000000f1";
+
+fn flash_loan(lender: Lender) -> FlashLoan {
+    FlashLoan::new(&Configuration {
+        lender,
+        lender_address: LENDER_ADDRESS.parse().unwrap(),
+        receiver_address: RECEIVER_ADDRESS.parse().unwrap(),
+        min_profit: U256::zero(),
+    })
+}
+
+fn selector(signature: &str) -> Vec<u8> {
+    signing::keccak256(signature.as_bytes())[..4].to_vec()
+}
+
+#[test]
+fn test_debt_is_borrowed_to_call_the_liquidator() {
+    let funds = RequiredFunds {
+        token: Address::from_low_u64_be(0xda1),
+        amount: U256::from(900),
+        flash_loan_fee: None,
+    };
+    let liquidator = Address::from_low_u64_be(0x11);
+    let liquidation = Bytes(vec![0xde, 0xad, 0xbe, 0xef]);
+    let callback_data = Token::Bytes(ethabi::encode(&[
+        Token::Address(liquidator),
+        Token::Bytes(liquidation.0.clone()),
+    ]));
+    let receiver = Token::Address(RECEIVER_ADDRESS.parse().unwrap());
+
+    let (to, data) = flash_loan(Lender::Aave).encode(&funds, liquidator, &liquidation);
+    assert_eq!(to, LENDER_ADDRESS.parse().unwrap());
+    assert_eq!(
+        data.0[..4],
+        selector("flashLoanSimple(address,address,uint256,bytes,uint16)")
+    );
+    let arguments = ethabi::decode(
+        &[
+            ParamType::Address,
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Bytes,
+            ParamType::Uint(16),
+        ],
+        &data.0[4..],
+    )
+    .unwrap();
+    assert_eq!(
+        arguments,
+        vec![
+            receiver.clone(),
+            Token::Address(funds.token),
+            Token::Uint(funds.amount),
+            callback_data.clone(),
+            Token::Uint(U256::zero()),
+        ]
+    );
+
+    let (to, data) = flash_loan(Lender::Balancer).encode(&funds, liquidator, &liquidation);
+    assert_eq!(to, LENDER_ADDRESS.parse().unwrap());
+    assert_eq!(
+        data.0[..4],
+        selector("flashLoan(address,address[],uint256[],bytes)")
+    );
+    let arguments = ethabi::decode(
+        &[
+            ParamType::Address,
+            ParamType::Array(Box::new(ParamType::Address)),
+            ParamType::Array(Box::new(ParamType::Uint(256))),
+            ParamType::Bytes,
+        ],
+        &data.0[4..],
+    )
+    .unwrap();
+    assert_eq!(
+        arguments,
+        vec![
+            receiver,
+            Token::Array(vec![Token::Address(funds.token)]),
+            Token::Array(vec![Token::Uint(funds.amount)]),
+            callback_data,
+        ]
+    );
+}
@@ -0,0 +1,107 @@
+use liquidation_bot::executor::gas::{self, Fees, GasError, GasPlan, Urgency};
+
+use web3::types::{BlockNumber, FeeHistory, U256};
+
+const GWEI: u64 = 1_000_000_000;
+
+fn gwei(value: u64) -> U256 {
+    U256::from(value * GWEI)
+}
+
+fn fee_history(base_fees: &[u64], priority_fees: &[u64]) -> FeeHistory {
+    FeeHistory {
+        oldest_block: BlockNumber::Number(100.into()),
+        base_fee_per_gas: base_fees.iter().map(|fee| gwei(*fee)).collect(),
+        gas_used_ratio: vec![0.5; priority_fees.len()],
+        reward: Some(priority_fees.iter().map(|fee| vec![gwei(*fee)]).collect()),
+    }
+}
+
+#[test]
+fn test_eip1559_fees_follow_recent_blocks() {
+    // Three blocks, the last base fee being the one of the next block. The empty block with no
+    // priority fee is ignored.
+    let history = fee_history(&[20, 22, 24, 30], &[1, 0, 3]);
+
+    assert_eq!(gas::next_base_fee(&history), gwei(30));
+    assert_eq!(
+        gas::eip1559_fees(&history, Urgency::Medium),
+        Fees::Eip1559 {
+            max_fee_per_gas: gwei(45 + 3),
+            max_priority_fee_per_gas: gwei(3),
+        }
+    );
+    assert_eq!(
+        gas::eip1559_fees(&history, Urgency::High),
+        Fees::Eip1559 {
+            max_fee_per_gas: gwei(60 + 3),
+            max_priority_fee_per_gas: gwei(3),
+        }
+    );
+
+    // Networks without priority fees still get the base fee.
+    let history = fee_history(&[10, 8], &[0]);
+    assert_eq!(
+        gas::eip1559_fees(&history, Urgency::Low),
+        Fees::Eip1559 {
+            max_fee_per_gas: gwei(9),
+            max_priority_fee_per_gas: U256::zero(),
+        }
+    );
+}
+
+#[test]
+fn test_legacy_fees_and_gas_limits_add_margins() {
+    assert_eq!(
+        gas::legacy_fees(gwei(40), Urgency::High),
+        Fees::Legacy {
+            gas_price: gwei(50)
+        }
+    );
+    assert_eq!(
+        gas::legacy_fees(gwei(40), Urgency::Low),
+        Fees::Legacy {
+            gas_price: gwei(40)
+        }
+    );
+    assert_eq!(gas::gas_limit(U256::from(250000), 2000), U256::from(300000));
+}
+
+#[test]
+fn test_fees_are_capped_by_the_reward() {
+    let plan = GasPlan {
+        gas_limit: U256::from(300000),
+        fees: Fees::Eip1559 {
+            max_fee_per_gas: gwei(48),
+            max_priority_fee_per_gas: gwei(3),
+        },
+    };
+    assert_eq!(plan.max_cost(), gwei(48 * 300000));
+
+    // Cheap enough for the budget, unchanged.
+    assert_eq!(
+        gas::cap_fees(plan.clone(), gwei(48 * 300000), gwei(30)).unwrap(),
+        plan
+    );
+
+    // Fees are lowered as long as the transaction can still be included.
+    let capped = gas::cap_fees(plan.clone(), gwei(32 * 300000), gwei(30)).unwrap();
+    assert_eq!(
+        capped.fees,
+        Fees::Eip1559 {
+            max_fee_per_gas: gwei(32),
+            max_priority_fee_per_gas: gwei(3),
+        }
+    );
+    assert_eq!(capped.max_cost(), gwei(32 * 300000));
+
+    match gas::cap_fees(plan, gwei(29 * 300000), gwei(30)) {
+        Err(GasError::Unprofitable {
+            gas_limit, budget, ..
+        }) => {
+            assert_eq!(gas_limit, U256::from(300000));
+            assert_eq!(budget, gwei(29 * 300000));
+        }
+        result => panic!("unexpected {:?}", result),
+    }
+}
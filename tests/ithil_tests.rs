@@ -0,0 +1,180 @@
+use web3::ethabi::{self, Contract, Token};
+use web3::types::{Address, Bytes, Log, H256, U256, U64};
+
+use liquidation_bot::events::{Event, LogMetadata};
+use liquidation_bot::feeds::ithil::{SeenLogs, StrategyEvents, SEEN_LOGS_DEPTH};
+
+/// Strategy ABI with the events of the margin trading strategy, `PositionWasClosed` having its id
+/// indexed or not.
+fn strategy_abi(indexed_id: bool) -> Contract {
+    let abi = format!(
+        r#"[
+        {{"type": "event", "name": "PositionWasOpened", "anonymous": false, "inputs": [
+            {{"name": "id", "type": "uint256", "indexed": true}},
+            {{"name": "owner", "type": "address", "indexed": true}},
+            {{"name": "owedToken", "type": "address", "indexed": false}},
+            {{"name": "heldToken", "type": "address", "indexed": false}},
+            {{"name": "collateralToken", "type": "address", "indexed": false}},
+            {{"name": "collateral", "type": "uint256", "indexed": false}},
+            {{"name": "principal", "type": "uint256", "indexed": false}},
+            {{"name": "allowance", "type": "uint256", "indexed": false}},
+            {{"name": "fees", "type": "uint256", "indexed": false}},
+            {{"name": "createdAt", "type": "uint256", "indexed": false}}
+        ]}},
+        {{"type": "event", "name": "PositionWasClosed", "anonymous": false, "inputs": [
+            {{"name": "id", "type": "uint256", "indexed": {}}}
+        ]}},
+        {{"type": "event", "name": "PositionWasLiquidated", "anonymous": false, "inputs": [
+            {{"name": "id", "type": "uint256", "indexed": true}}
+        ]}},
+        {{"type": "event", "name": "RiskFactorWasUpdated", "anonymous": false, "inputs": [
+            {{"name": "token", "type": "address", "indexed": true}},
+            {{"name": "newRiskFactor", "type": "uint256", "indexed": false}}
+        ]}}
+    ]"#,
+        indexed_id
+    );
+
+    Contract::load(abi.as_bytes()).unwrap()
+}
+
+fn log(topics: Vec<H256>, data: Vec<u8>) -> Log {
+    Log {
+        address: Address::from_low_u64_be(1),
+        topics,
+        data: Bytes(data),
+        block_hash: Some(H256::from_low_u64_be(100)),
+        block_number: Some(U64::from(100)),
+        transaction_hash: None,
+        transaction_index: None,
+        log_index: Some(U256::zero()),
+        transaction_log_index: None,
+        log_type: None,
+        removed: None,
+    }
+}
+
+#[test]
+fn test_logs_are_decoded_with_the_abi_of_their_strategy() {
+    let abi = strategy_abi(false);
+    let signature = abi.event("PositionWasClosed").unwrap().signature();
+    let closed = log(
+        vec![signature],
+        ethabi::encode(&[Token::Uint(U256::from(7))]),
+    );
+
+    // The id is in the data rather than in the topics, unlike in the margin trading strategy.
+    match StrategyEvents::from_abi(&abi).parse(&closed) {
+        Some(Event::PositionWasClosed(position_was_closed)) => {
+            assert_eq!(position_was_closed.id, U256::from(7));
+            assert_eq!(position_was_closed.strategy, Address::from_low_u64_be(1));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    // Logs which do not match the layout of the strategy are skipped.
+    let abi = strategy_abi(true);
+    assert!(StrategyEvents::from_abi(&abi).parse(&closed).is_none());
+    let risk_factor_was_updated = abi.event("RiskFactorWasUpdated").unwrap().signature();
+    let truncated = log(
+        vec![risk_factor_was_updated, H256::from_low_u64_be(2)],
+        vec![1, 2, 3],
+    );
+    assert!(StrategyEvents::from_abi(&abi).parse(&truncated).is_none());
+}
+
+fn log_at(block_number: u64, block_hash: u64, log_index: u64) -> Log {
+    Log {
+        block_number: Some(U64::from(block_number)),
+        block_hash: Some(H256::from_low_u64_be(block_hash)),
+        log_index: Some(U256::from(log_index)),
+        ..log(vec![], vec![])
+    }
+}
+
+#[test]
+fn test_logs_are_identified_by_block_hash_and_index() {
+    let mut seen_logs = SeenLogs::default();
+
+    assert!(seen_logs.insert(&log_at(100, 1, 0)));
+    assert!(!seen_logs.insert(&log_at(100, 1, 0)));
+    assert!(seen_logs.insert(&log_at(100, 1, 1)));
+    // Same index in a block which replaced the first one.
+    assert!(seen_logs.insert(&log_at(100, 2, 0)));
+
+    // Pending logs cannot be deduplicated.
+    let pending = Log {
+        block_hash: None,
+        ..log_at(101, 3, 0)
+    };
+    assert!(seen_logs.insert(&pending));
+    assert!(seen_logs.insert(&pending));
+}
+
+#[test]
+fn test_logs_overlapping_between_backfill_and_subscription_are_sent_once() {
+    let mut seen_logs = SeenLogs::default();
+
+    // Backfilled up to block 101 while the subscription buffered blocks 101 and 102.
+    let backfilled = [log_at(100, 1, 0), log_at(101, 2, 0), log_at(101, 2, 1)];
+    let live = [log_at(101, 2, 0), log_at(101, 2, 1), log_at(102, 3, 0)];
+    let backfilled_sent = backfilled
+        .iter()
+        .filter(|log| seen_logs.insert(log))
+        .count();
+    let live_sent = live.iter().filter(|log| seen_logs.insert(log)).count();
+    assert_eq!((backfilled_sent, live_sent), (3, 1));
+
+    // After a reconnection, the last block is fetched again.
+    assert!(!seen_logs.insert(&log_at(102, 3, 0)));
+
+    // A removed log is sent again if its block comes back.
+    seen_logs.remove(&LogMetadata {
+        block_number: 102,
+        block_hash: H256::from_low_u64_be(3),
+        log_index: U256::zero(),
+    });
+    assert!(seen_logs.insert(&log_at(102, 3, 0)));
+}
+
+#[test]
+fn test_seen_logs_are_forgotten_after_depth_blocks() {
+    let mut seen_logs = SeenLogs::default();
+    assert!(seen_logs.insert(&log_at(100, 1, 0)));
+
+    assert!(seen_logs.insert(&log_at(100 + SEEN_LOGS_DEPTH, 2, 0)));
+    assert!(!seen_logs.insert(&log_at(100, 1, 0)));
+
+    assert!(seen_logs.insert(&log_at(101 + SEEN_LOGS_DEPTH, 3, 0)));
+    assert!(seen_logs.insert(&log_at(100, 1, 0)));
+}
+
+#[test]
+fn test_strategies_need_not_emit_every_event() {
+    let abi = Contract::load(
+        r#"[
+        {"type": "event", "name": "PositionWasLiquidated", "anonymous": false, "inputs": [
+            {"name": "id", "type": "uint256", "indexed": true}
+        ]}
+    ]"#
+        .as_bytes(),
+    )
+    .unwrap();
+    let signature = abi.event("PositionWasLiquidated").unwrap().signature();
+    let strategy_events = StrategyEvents::from_abi(&abi);
+
+    // Only the events of the strategy are subscribed to.
+    assert_eq!(strategy_events.topics(), vec![signature]);
+
+    let liquidated = log(vec![signature, H256::from_low_u64_be(7)], vec![]);
+    assert!(matches!(
+        strategy_events.parse(&liquidated),
+        Some(Event::PositionWasLiquidated(_))
+    ));
+    let risk_factor_was_updated = strategy_abi(true)
+        .event("RiskFactorWasUpdated")
+        .unwrap()
+        .signature();
+    let updated = log(vec![risk_factor_was_updated], vec![]);
+    assert!(strategy_events.parse(&updated).is_none());
+}
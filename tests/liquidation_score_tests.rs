@@ -0,0 +1,303 @@
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+use proptest::prelude::*;
+use web3::types::{Address, H256, U256};
+
+use liquidation_bot::events::{
+    BlockHeader, Event, LogMetadata, PositionWasOpened, RiskFactorWasUpdated, Ticker,
+};
+use liquidation_bot::liquidator::{Configuration, Liquidator};
+use liquidation_bot::token_registry::TokenRegistry;
+use liquidation_bot::types::{CurrencyCode, Exchange, Pair, Price, Token};
+
+const MARGIN_TRADING_STRATEGY_ADDRESS: &str = "0x09A37C94DF2b68831F0e56b943A416a00E5FA154";
+const HELD_TOKEN_ADDRESS: &str = "0xc9EA4189848A3518B12808D98bFAD92eF48427A7";
+const OWED_TOKEN_ADDRESS: &str = "0x4315D935947bf9430152b5e90E0A5675e888Be90";
+
+#[derive(Clone, Debug)]
+struct ScoreInputs {
+    held_decimals: u32,
+    owed_decimals: u32,
+    held_price: Price,
+    owed_price: Price,
+    held_risk_factor: U256,
+    owed_risk_factor: U256,
+    collateral_in_owed_token: bool,
+    collateral: U256,
+    principal: U256,
+    allowance: U256,
+    fees: U256,
+    created_at: U256,
+    timestamp: U256,
+}
+
+// Reference implementation of the on-chain formula, following Solidity semantics: every unsigned
+// operation is checked and every signed value must fit an int256, otherwise the call reverts and
+// the reference returns None.
+
+fn to_int256(value: BigInt) -> Option<BigInt> {
+    let bound = BigInt::from(2).pow(255);
+    if value >= -bound.clone() && value < bound {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn u256_to_int256(value: U256) -> Option<BigInt> {
+    to_int256(BigInt::from_str(&value.to_string()).unwrap())
+}
+
+fn checked_pow10(exponent: u32) -> Option<U256> {
+    U256::from(10).checked_pow(U256::from(exponent))
+}
+
+fn reference_quote(
+    amount: U256,
+    src_price: &Price,
+    src_decimals: u32,
+    dst_price: &Price,
+    dst_decimals: u32,
+) -> Option<U256> {
+    let numerator = amount
+        .checked_mul(src_price.value)?
+        .checked_mul(checked_pow10(dst_decimals + dst_price.decimals)?)?;
+    let denominator = dst_price
+        .value
+        .checked_mul(checked_pow10(src_decimals + src_price.decimals)?)?;
+
+    numerator.checked_div(denominator)
+}
+
+fn reference_liquidation_score(inputs: &ScoreInputs) -> Option<BigInt> {
+    let pair_risk_factor = inputs
+        .held_risk_factor
+        .checked_add(inputs.owed_risk_factor)?
+        / 2;
+
+    let elapsed = inputs.timestamp.checked_sub(inputs.created_at)?;
+    let due_fees = U256::one().checked_add(
+        inputs
+            .fees
+            .checked_mul(elapsed)?
+            .checked_mul(inputs.principal)?
+            / U256::from(86400 * 10000),
+    )?;
+    let debt = inputs.principal.checked_add(due_fees)?;
+
+    let profit_and_loss = if inputs.collateral_in_owed_token {
+        let expected_tokens = reference_quote(
+            inputs.allowance,
+            &inputs.held_price,
+            inputs.held_decimals,
+            &inputs.owed_price,
+            inputs.owed_decimals,
+        )?;
+        to_int256(u256_to_int256(expected_tokens)? - u256_to_int256(debt)?)?
+    } else {
+        let expected_tokens = reference_quote(
+            debt,
+            &inputs.owed_price,
+            inputs.owed_decimals,
+            &inputs.held_price,
+            inputs.held_decimals,
+        )?;
+        to_int256(u256_to_int256(inputs.allowance)? - u256_to_int256(expected_tokens)?)?
+    };
+
+    let weighted_collateral = u256_to_int256(inputs.collateral.checked_mul(pair_risk_factor)?)?;
+    let weighted_profit_and_loss = to_int256(profit_and_loss * 10000)?;
+
+    to_int256(weighted_collateral - weighted_profit_and_loss)
+}
+
+fn make_token(address: &str, symbol: CurrencyCode, decimals: u32) -> Token {
+    Token {
+        name: format!("{:?}", symbol),
+        address: address.parse().unwrap(),
+        decimals: decimals as i32,
+        symbol,
+    }
+}
+
+fn liquidator_score(inputs: &ScoreInputs) -> Option<BigInt> {
+    let held_token = make_token(HELD_TOKEN_ADDRESS, CurrencyCode::WBTC, inputs.held_decimals);
+    let owed_token = make_token(OWED_TOKEN_ADDRESS, CurrencyCode::DAI, inputs.owed_decimals);
+
+    let tokens = TokenRegistry::new([held_token.clone(), owed_token.clone()]);
+
+    let strategy = Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            ..Default::default()
+        },
+        BlockHeader {
+            number: 1,
+            hash: H256::zero(),
+            parent_hash: H256::zero(),
+            timestamp: inputs.timestamp,
+            base_fee_per_gas: None,
+        },
+        tokens,
+    );
+
+    let events = vec![
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: held_token.address,
+            new_risk_factor: inputs.held_risk_factor,
+        }),
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: owed_token.address,
+            new_risk_factor: inputs.owed_risk_factor,
+        }),
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::WBTC, CurrencyCode::USD),
+            price: inputs.held_price.clone(),
+            timestamp: inputs.timestamp.low_u64(),
+        }),
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::DAI, CurrencyCode::USD),
+            price: inputs.owed_price.clone(),
+            timestamp: inputs.timestamp.low_u64(),
+        }),
+        Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy,
+            id: U256::from(1),
+            owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+                .parse()
+                .unwrap(),
+            owed_token: owed_token.address,
+            held_token: held_token.address,
+            collateral_token: if inputs.collateral_in_owed_token {
+                owed_token.address
+            } else {
+                held_token.address
+            },
+            collateral: inputs.collateral,
+            principal: inputs.principal,
+            allowance: inputs.allowance,
+            fees: inputs.fees,
+            created_at: inputs.created_at,
+        }),
+    ];
+
+    for event in events {
+        liquidator.run(&event);
+    }
+
+    liquidator.liquidation_score(&strategy, &U256::from(1))
+}
+
+/// Values spread over the whole 256-bit range. Bit lengths are drawn uniformly, with a bias
+/// towards short ones so that products of several values regularly stay within 256 bits and
+/// the on-chain computation does not revert.
+fn any_u256() -> impl Strategy<Value = U256> {
+    let bits = prop_oneof![3 => 0..=64u32, 1 => 0..=256u32];
+
+    (any::<[u8; 32]>(), bits).prop_map(|(bytes, bits)| match bits {
+        0 => U256::zero(),
+        bits => U256::from_big_endian(&bytes) >> (256 - bits),
+    })
+}
+
+fn any_price() -> impl Strategy<Value = Price> {
+    (any_u256(), 0..=18u32).prop_map(|(value, decimals)| Price::new(value, decimals))
+}
+
+fn any_score_inputs() -> impl Strategy<Value = ScoreInputs> {
+    (
+        (0..=24u32, 0..=24u32, any_price(), any_price()),
+        (any_u256(), any_u256(), any::<bool>()),
+        (any_u256(), any_u256(), any_u256(), any_u256()),
+        (any_u256(), any_u256()),
+    )
+        .prop_map(
+            |(
+                (held_decimals, owed_decimals, held_price, owed_price),
+                (held_risk_factor, owed_risk_factor, collateral_in_owed_token),
+                (collateral, principal, allowance, fees),
+                (created_at, timestamp),
+            )| ScoreInputs {
+                held_decimals,
+                owed_decimals,
+                held_price,
+                owed_price,
+                held_risk_factor,
+                owed_risk_factor,
+                collateral_in_owed_token,
+                collateral,
+                principal,
+                allowance,
+                fees,
+                created_at,
+                timestamp,
+            },
+        )
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2048))]
+
+    #[test]
+    fn test_liquidation_score_matches_vault_math(inputs in any_score_inputs()) {
+        // Whenever the on-chain computation does not revert, the bot must agree with it exactly.
+        if let Some(expected_score) = reference_liquidation_score(&inputs) {
+            prop_assert_eq!(liquidator_score(&inputs), Some(expected_score));
+        }
+    }
+
+    #[test]
+    fn test_liquidation_score_is_defined_on_the_whole_range(inputs in any_score_inputs()) {
+        prop_assume!(!inputs.held_price.value.is_zero() && !inputs.owed_price.value.is_zero());
+
+        prop_assert!(liquidator_score(&inputs).is_some());
+    }
+}
+
+#[test]
+fn test_large_position_is_quoted_exactly() {
+    // 1,000,000 WETH-sized amounts do not fit a u64 at 18 decimals.
+    let one_token = U256::exp10(18);
+    let inputs = ScoreInputs {
+        held_decimals: 18,
+        owed_decimals: 18,
+        held_price: Price::from_str("1234.56").unwrap(),
+        owed_price: Price::from_str("1.0001").unwrap(),
+        held_risk_factor: U256::from(3000),
+        owed_risk_factor: U256::from(1000),
+        collateral_in_owed_token: true,
+        collateral: U256::from(100_000) * one_token,
+        principal: U256::from(1_000_000_000u64) * one_token,
+        allowance: U256::from(1_000_000) * one_token,
+        fees: U256::zero(),
+        created_at: U256::from(1024),
+        timestamp: U256::from(1024),
+    };
+
+    let score = liquidator_score(&inputs).unwrap();
+
+    assert_eq!(Some(score.clone()), reference_liquidation_score(&inputs));
+    // The position is worth ~1.234 billion owed tokens against a 1 billion debt: not liquidable.
+    assert!(score < BigInt::from(0));
+}
+
+#[test]
+fn test_price_is_parsed_exactly() {
+    assert_eq!(
+        Price::from_str("20123.4500").unwrap(),
+        Price::new(U256::from(201234500), 4)
+    );
+    assert_eq!(Price::from_str("7").unwrap(), Price::new(U256::from(7), 0));
+    assert!(Price::from_str("-1.5").is_err());
+    assert!(Price::from_str("1e18").is_err());
+    assert!(Price::from_str("").is_err());
+}
@@ -1,16 +1,20 @@
-use std::collections::HashMap;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use num_bigint::BigInt;
+
 use liquidation_bot::events::{
-    BlockHeader, Event, PositionWasOpened, RiskFactorWasUpdated, Ticker,
+    BlockHeader, Event, LiquidationFailed, LiquidationSucceeded, LiquidationTimedOut, LogMetadata,
+    PositionWasClosed, PositionWasOpened, RiskFactorWasUpdated, Ticker,
 };
-use liquidation_bot::liquidator::Liquidator;
-use liquidation_bot::types::{CurrencyCode, Exchange, Pair, Token};
+use liquidation_bot::liquidator::{Configuration, Liquidator};
+use liquidation_bot::token_registry::TokenRegistry;
+use liquidation_bot::types::{CurrencyCode, Exchange, Pair, Price, RequiredFunds, Token};
 
-use web3::types::{Address, U256};
+use web3::types::{Address, H256, U256};
 
 const MARGIN_TRADING_STRATEGY_ADDRESS: &str = "0x09A37C94DF2b68831F0e56b943A416a00E5FA154";
+const YEARN_STRATEGY_ADDRESS: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
 
 #[test]
 fn test_position_is_liquidated_after_loss() {
@@ -39,63 +43,79 @@ fn test_position_is_liquidated_after_loss() {
         symbol: CurrencyCode::WBTC,
     };
 
-    let tokens: HashMap<Address, Token> = vec![
-        (dai_token.address, dai_token.clone()),
-        (weth_token.address, weth_token.clone()),
-        (wbtc_token.address, wbtc_token.clone()),
-    ]
-    .into_iter()
-    .collect();
+    let tokens = TokenRegistry::new([dai_token.clone(), weth_token.clone(), wbtc_token.clone()]);
 
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
     let latest_block = BlockHeader {
-        timestamp: U256::from(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        ),
+        number: 1,
+        hash: H256::zero(),
+        parent_hash: H256::zero(),
+        timestamp: U256::from(now),
+        base_fee_per_gas: None,
     };
 
     let margin_trading_strategy_address =
         Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
-    let mut liquidator = Liquidator::new(latest_block, margin_trading_strategy_address, tokens);
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            liquidation_reward: Some(500),
+            ..Default::default()
+        },
+        latest_block,
+        tokens,
+    );
 
     let events: Vec<Event> = vec![
         Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
-            token: weth_token.address.clone(),
+            log: LogMetadata::default(),
+            strategy: margin_trading_strategy_address,
+            token: weth_token.address,
             new_risk_factor: U256::from(3000),
         }),
         Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
-            token: wbtc_token.address.clone(),
+            log: LogMetadata::default(),
+            strategy: margin_trading_strategy_address,
+            token: wbtc_token.address,
             new_risk_factor: U256::from(2000),
         }),
         Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
-            token: dai_token.address.clone(),
+            log: LogMetadata::default(),
+            strategy: margin_trading_strategy_address,
+            token: dai_token.address,
             new_risk_factor: U256::from(1000),
         }),
         Event::Ticker(Ticker {
             exchange: Exchange::Coinbase,
             pair: Pair(CurrencyCode::WBTC, CurrencyCode::USD),
-            price: 20000.0,
+            price: Price::from_str("20000").unwrap(),
+            timestamp: now,
         }),
         Event::Ticker(Ticker {
             exchange: Exchange::Coinbase,
             pair: Pair(CurrencyCode::WETH, CurrencyCode::USD),
-            price: 1000.0,
+            price: Price::from_str("1000").unwrap(),
+            timestamp: now,
         }),
         Event::Ticker(Ticker {
             exchange: Exchange::Coinbase,
             pair: Pair(CurrencyCode::DAI, CurrencyCode::USD),
-            price: 1.0,
+            price: Price::from_str("1").unwrap(),
+            timestamp: now,
         }),
         Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy: margin_trading_strategy_address,
             id: U256::from(1),
             owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
                 .parse()
                 .unwrap(),
-            owed_token: dai_token.address.clone(),
-            held_token: wbtc_token.address.clone(),
-            collateral_token: dai_token.address.clone(),
+            owed_token: dai_token.address,
+            held_token: wbtc_token.address,
+            collateral_token: dai_token.address,
             collateral: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 DAI
             principal: U256::from(900).saturating_mul(U256::from(10).pow(U256::from(18))), // 900 DAI
             allowance: U256::from(5000000), // 0.05 WBTC
@@ -109,7 +129,8 @@ fn test_position_is_liquidated_after_loss() {
         Event::Ticker(Ticker {
             exchange: Exchange::Coinbase,
             pair: Pair(CurrencyCode::WBTC, CurrencyCode::USD),
-            price: 18300.0,
+            price: Price::from_str("18300").unwrap(),
+            timestamp: now,
         }),
     ];
 
@@ -120,4 +141,694 @@ fn test_position_is_liquidated_after_loss() {
     });
 
     assert_eq!(liquidations.len(), 1);
+    // 5% of the 100 DAI of collateral, at 1000 USD per ETH.
+    assert_eq!(
+        liquidations[0].expected_reward,
+        Some(U256::from(5).saturating_mul(U256::from(10).pow(U256::from(15))))
+    );
+}
+
+#[test]
+fn test_positions_are_tracked_per_strategy() {
+    let dai_token = Token {
+        name: "DAI Stablecoin".to_string(),
+        address: "0x4315D935947bf9430152b5e90E0A5675e888Be90"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::DAI,
+    };
+    let wbtc_token = Token {
+        name: "Wrapped Bitcoin".to_string(),
+        address: "0xc9EA4189848A3518B12808D98bFAD92eF48427A7"
+            .parse()
+            .unwrap(),
+        decimals: 8,
+        symbol: CurrencyCode::WBTC,
+    };
+
+    let tokens = TokenRegistry::new([dai_token.clone(), wbtc_token.clone()]);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let latest_block = BlockHeader {
+        number: 1,
+        hash: H256::zero(),
+        parent_hash: H256::zero(),
+        timestamp: U256::from(now),
+        base_fee_per_gas: None,
+    };
+
+    let margin_trading_strategy_address =
+        Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+    let yearn_strategy_address = Address::from_str(YEARN_STRATEGY_ADDRESS).unwrap();
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            ..Default::default()
+        },
+        latest_block,
+        tokens,
+    );
+
+    // Both strategies have a position with the same id, but the Yearn strategy has much lower
+    // risk factors so its position stays healthy after the price drop.
+    let risk_factors = vec![
+        (margin_trading_strategy_address, wbtc_token.address, 2000),
+        (margin_trading_strategy_address, dai_token.address, 1000),
+        (yearn_strategy_address, wbtc_token.address, 100),
+        (yearn_strategy_address, dai_token.address, 100),
+    ];
+    let mut events: Vec<Event> = risk_factors
+        .into_iter()
+        .map(|(strategy, token, risk_factor)| {
+            Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+                log: LogMetadata::default(),
+                strategy,
+                token,
+                new_risk_factor: U256::from(risk_factor),
+            })
+        })
+        .collect();
+
+    events.push(Event::Ticker(Ticker {
+        exchange: Exchange::Coinbase,
+        pair: Pair(CurrencyCode::DAI, CurrencyCode::USD),
+        price: Price::from_str("1").unwrap(),
+        timestamp: now,
+    }));
+
+    for strategy in [margin_trading_strategy_address, yearn_strategy_address] {
+        events.push(Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy,
+            id: U256::from(1),
+            owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+                .parse()
+                .unwrap(),
+            owed_token: dai_token.address,
+            held_token: wbtc_token.address,
+            collateral_token: dai_token.address,
+            collateral: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 DAI
+            principal: U256::from(900).saturating_mul(U256::from(10).pow(U256::from(18))), // 900 DAI
+            allowance: U256::from(5000000), // 0.05 WBTC
+            fees: U256::from(0),
+            created_at: U256::from(1024),
+        }));
+    }
+
+    events.push(Event::Ticker(Ticker {
+        exchange: Exchange::Coinbase,
+        pair: Pair(CurrencyCode::WBTC, CurrencyCode::USD),
+        price: Price::from_str("18300").unwrap(),
+        timestamp: now,
+    }));
+
+    let liquidations = events.into_iter().fold(vec![], |mut liquidations, event| {
+        let mut new_liquidations = liquidator.run(&event);
+        liquidations.append(&mut new_liquidations);
+        liquidations
+    });
+
+    assert_eq!(liquidations.len(), 1);
+    assert_eq!(liquidations[0].strategy, margin_trading_strategy_address);
+    assert_eq!(liquidations[0].position_id, U256::from(1));
+}
+
+#[test]
+fn test_stale_prices_do_not_trigger_liquidations() {
+    let dai_token = Token {
+        name: "DAI Stablecoin".to_string(),
+        address: "0x4315D935947bf9430152b5e90E0A5675e888Be90"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::DAI,
+    };
+    let wbtc_token = Token {
+        name: "Wrapped Bitcoin".to_string(),
+        address: "0xc9EA4189848A3518B12808D98bFAD92eF48427A7"
+            .parse()
+            .unwrap(),
+        decimals: 8,
+        symbol: CurrencyCode::WBTC,
+    };
+
+    let tokens = TokenRegistry::new([dai_token.clone(), wbtc_token.clone()]);
+
+    let now = 1_700_000_000;
+    let latest_block = BlockHeader {
+        number: 1,
+        hash: H256::zero(),
+        parent_hash: H256::zero(),
+        timestamp: U256::from(now),
+        base_fee_per_gas: None,
+    };
+
+    let strategy = Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+    // DAI prices move slowly, so they are allowed to be older than the default.
+    let max_price_ages = vec![(Pair(CurrencyCode::DAI, CurrencyCode::USD), 600)]
+        .into_iter()
+        .collect();
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            max_price_age: 60,
+            max_price_ages,
+            ..Default::default()
+        },
+        latest_block,
+        tokens,
+    );
+
+    let setup = [
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: wbtc_token.address,
+            new_risk_factor: U256::from(2000),
+        }),
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: dai_token.address,
+            new_risk_factor: U256::from(1000),
+        }),
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::DAI, CurrencyCode::USD),
+            price: Price::from_str("1").unwrap(),
+            timestamp: now - 300,
+        }),
+        // An outdated direct price, at which the position would not be liquidable.
+        Event::Ticker(Ticker {
+            exchange: Exchange::Binance,
+            pair: Pair(CurrencyCode::WBTC, CurrencyCode::DAI),
+            price: Price::from_str("30000").unwrap(),
+            timestamp: now - 300,
+        }),
+        Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy,
+            id: U256::from(1),
+            owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+                .parse()
+                .unwrap(),
+            owed_token: dai_token.address,
+            held_token: wbtc_token.address,
+            collateral_token: dai_token.address,
+            collateral: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 DAI
+            principal: U256::from(900).saturating_mul(U256::from(10).pow(U256::from(18))), // 900 DAI
+            allowance: U256::from(5000000), // 0.05 WBTC
+            fees: U256::from(0),
+            created_at: U256::from(1024),
+        }),
+    ];
+    for event in setup.iter() {
+        assert!(liquidator.run(event).is_empty());
+    }
+
+    let wbtc_ticker = |timestamp: u64| {
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::WBTC, CurrencyCode::USD),
+            price: Price::from_str("18300").unwrap(),
+            timestamp,
+        })
+    };
+
+    // A WBTC price published two minutes ago is too old to even score the position, although
+    // it would make it liquidable.
+    assert!(liquidator.run(&wbtc_ticker(now - 120)).is_empty());
+    assert!(liquidator
+        .liquidation_score(&strategy, &U256::from(1))
+        .is_none());
+
+    // A fresh WBTC price triggers the liquidation, the 5 minutes old DAI price being within its
+    // own limit, and the position is scored at the price of that path rather than the direct one.
+    let liquidations = liquidator.run(&wbtc_ticker(now));
+    assert_eq!(liquidations.len(), 1);
+    assert_eq!(liquidations[0].position_id, U256::from(1));
+}
+
+#[test]
+fn test_positions_in_whitelisted_tokens_are_scored_once_known() {
+    let dai_token = Token {
+        name: "DAI Stablecoin".to_string(),
+        address: "0x4315D935947bf9430152b5e90E0A5675e888Be90"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::DAI,
+    };
+    // A long-tail token missing from the token list, whitelisted after the deployment.
+    let link_token = Token {
+        name: "ChainLink Token".to_string(),
+        address: "0x514910771AF9Ca656af840dff83E8264EcF986CA"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::new("LINK"),
+    };
+
+    let tokens = TokenRegistry::new([dai_token.clone()]);
+    let latest_block = BlockHeader {
+        number: 1,
+        hash: H256::zero(),
+        parent_hash: H256::zero(),
+        timestamp: U256::from(1024),
+        base_fee_per_gas: None,
+    };
+    let strategy = Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            ..Default::default()
+        },
+        latest_block,
+        tokens.clone(),
+    );
+
+    let events = [
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: link_token.address,
+            new_risk_factor: U256::from(2000),
+        }),
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: dai_token.address,
+            new_risk_factor: U256::from(1000),
+        }),
+        Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy,
+            id: U256::from(1),
+            owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+                .parse()
+                .unwrap(),
+            owed_token: dai_token.address,
+            held_token: link_token.address,
+            collateral_token: dai_token.address,
+            collateral: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 DAI
+            principal: U256::from(900).saturating_mul(U256::from(10).pow(U256::from(18))), // 900 DAI
+            allowance: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 LINK
+            fees: U256::from(0),
+            created_at: U256::from(1024),
+        }),
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::DAI, CurrencyCode::USD),
+            price: Price::from_str("1").unwrap(),
+            timestamp: 1024,
+        }),
+        Event::Ticker(Ticker {
+            exchange: Exchange::Uniswap,
+            pair: Pair(CurrencyCode::new("LINK"), CurrencyCode::USD),
+            price: Price::from_str("5").unwrap(),
+            timestamp: 1024,
+        }),
+    ];
+    for event in events.iter() {
+        assert!(liquidator.run(event).is_empty());
+    }
+    assert_eq!(
+        liquidator.liquidation_score(&strategy, &U256::from(1)),
+        None
+    );
+
+    // Once the token is learned, the next price update scores the position: 100 LINK are worth
+    // 500 DAI against a 900 DAI debt.
+    tokens.insert(link_token);
+    let liquidations = liquidator.run(&events[4]);
+    assert_eq!(liquidations.len(), 1);
+    assert_eq!(liquidations[0].position_id, U256::from(1));
+}
+
+#[test]
+fn test_failed_liquidations_are_attempted_again() {
+    let dai_token = Token {
+        name: "DAI Stablecoin".to_string(),
+        address: "0x4315D935947bf9430152b5e90E0A5675e888Be90"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::DAI,
+    };
+    let weth_token = Token {
+        name: "Wrapped Ether".to_string(),
+        address: "0x26CB03b59858dCD2b12F9309de5d1e8269e16F61"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::WETH,
+    };
+
+    let latest_block = BlockHeader {
+        number: 1,
+        hash: H256::zero(),
+        parent_hash: H256::zero(),
+        timestamp: U256::from(1024),
+        base_fee_per_gas: None,
+    };
+    let strategy = Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            max_price_age: u64::MAX,
+            ..Default::default()
+        },
+        latest_block,
+        TokenRegistry::new([dai_token.clone(), weth_token.clone()]),
+    );
+
+    let events = [
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: weth_token.address,
+            new_risk_factor: U256::from(3000),
+        }),
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: dai_token.address,
+            new_risk_factor: U256::from(1000),
+        }),
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::DAI, CurrencyCode::USD),
+            price: Price::from_str("1").unwrap(),
+            timestamp: 1024,
+        }),
+        Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy,
+            id: U256::from(1),
+            owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+                .parse()
+                .unwrap(),
+            owed_token: dai_token.address,
+            held_token: weth_token.address,
+            collateral_token: dai_token.address,
+            collateral: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 DAI
+            principal: U256::from(900).saturating_mul(U256::from(10).pow(U256::from(18))), // 900 DAI
+            allowance: U256::from(1).saturating_mul(U256::from(10).pow(U256::from(18))),   // 1 WETH
+            fees: U256::from(0),
+            created_at: U256::from(1024),
+        }),
+    ];
+    for event in events.iter() {
+        assert!(liquidator.run(event).is_empty());
+    }
+
+    // 1 WETH is worth 500 DAI against a 900 DAI debt.
+    let ticker = Event::Ticker(Ticker {
+        exchange: Exchange::Coinbase,
+        pair: Pair(CurrencyCode::WETH, CurrencyCode::USD),
+        price: Price::from_str("500").unwrap(),
+        timestamp: 1024,
+    });
+    assert_eq!(liquidator.run(&ticker).len(), 1);
+    // The liquidation is requested only once...
+    assert!(liquidator.run(&ticker).is_empty());
+
+    // ...until the executor reports that it did not go through, then again after a delay doubled
+    // with every failure.
+    let ticker_at = |timestamp: u64| {
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::WETH, CurrencyCode::USD),
+            price: Price::from_str("500").unwrap(),
+            timestamp,
+        })
+    };
+    liquidator.run(&Event::LiquidationFailed(LiquidationFailed {
+        strategy,
+        position_id: U256::from(1),
+        reason: String::from("reverted: Liquidator__Position_Not_Liquidable(1, 0)"),
+    }));
+    assert!(liquidator.run(&ticker_at(1030)).is_empty());
+    assert_eq!(liquidator.run(&ticker_at(1084)).len(), 1);
+
+    liquidator.run(&Event::LiquidationTimedOut(LiquidationTimedOut {
+        strategy,
+        position_id: U256::from(1),
+    }));
+    assert!(liquidator.run(&ticker_at(1200)).is_empty());
+    let liquidations = liquidator.run(&ticker_at(1204));
+    assert_eq!(liquidations.len(), 1);
+    assert_eq!(liquidations[0].position_id, U256::from(1));
+
+    // A successful liquidation is not attempted again.
+    liquidator.run(&Event::LiquidationSucceeded(LiquidationSucceeded {
+        strategy,
+        position_id: U256::from(1),
+        transaction_hash: H256::zero(),
+    }));
+    assert!(liquidator.run(&ticker_at(2000)).is_empty());
+}
+
+#[test]
+fn test_liquidations_are_given_up_after_too_many_failures() {
+    let dai_token = Token {
+        name: "DAI Stablecoin".to_string(),
+        address: "0x4315D935947bf9430152b5e90E0A5675e888Be90"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::DAI,
+    };
+    let weth_token = Token {
+        name: "Wrapped Ether".to_string(),
+        address: "0x26CB03b59858dCD2b12F9309de5d1e8269e16F61"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::WETH,
+    };
+
+    let latest_block = BlockHeader {
+        number: 1,
+        hash: H256::zero(),
+        parent_hash: H256::zero(),
+        timestamp: U256::from(1024),
+        base_fee_per_gas: None,
+    };
+    let strategy = Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            max_liquidation_attempts: 2,
+            liquidation_retry_backoff: 0,
+            max_price_age: u64::MAX,
+            ..Default::default()
+        },
+        latest_block,
+        TokenRegistry::new([dai_token.clone(), weth_token.clone()]),
+    );
+
+    let events = [
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: weth_token.address,
+            new_risk_factor: U256::from(3000),
+        }),
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: dai_token.address,
+            new_risk_factor: U256::from(1000),
+        }),
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::DAI, CurrencyCode::USD),
+            price: Price::from_str("1").unwrap(),
+            timestamp: 1024,
+        }),
+        Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy,
+            id: U256::from(1),
+            owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+                .parse()
+                .unwrap(),
+            owed_token: dai_token.address,
+            held_token: weth_token.address,
+            collateral_token: dai_token.address,
+            collateral: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 DAI
+            principal: U256::from(900).saturating_mul(U256::from(10).pow(U256::from(18))), // 900 DAI
+            allowance: U256::from(1).saturating_mul(U256::from(10).pow(U256::from(18))),   // 1 WETH
+            fees: U256::from(0),
+            created_at: U256::from(1024),
+        }),
+    ];
+    for event in events.iter() {
+        assert!(liquidator.run(event).is_empty());
+    }
+
+    let ticker = Event::Ticker(Ticker {
+        exchange: Exchange::Coinbase,
+        pair: Pair(CurrencyCode::WETH, CurrencyCode::USD),
+        price: Price::from_str("500").unwrap(),
+        timestamp: 1024,
+    });
+    let timed_out = Event::LiquidationTimedOut(LiquidationTimedOut {
+        strategy,
+        position_id: U256::from(1),
+    });
+    assert_eq!(liquidator.run(&ticker).len(), 1);
+    liquidator.run(&timed_out);
+    assert_eq!(liquidator.run(&ticker).len(), 1);
+    liquidator.run(&timed_out);
+    assert!(liquidator.run(&ticker).is_empty());
+
+    // A close reverted by a reorganisation brings the position back along with its failures.
+    let closed_log = LogMetadata {
+        block_number: 2,
+        block_hash: H256::from_low_u64_be(2),
+        log_index: U256::zero(),
+    };
+    liquidator.run(&Event::PositionWasClosed(PositionWasClosed {
+        log: closed_log.clone(),
+        strategy,
+        id: U256::from(1),
+    }));
+    liquidator.run(&Event::LogWasRemoved(closed_log));
+    assert_eq!(liquidator.open_positions().len(), 1);
+    assert!(liquidator.run(&ticker).is_empty());
+}
+
+#[test]
+fn test_unprofitable_positions_are_not_liquidated() {
+    let dai_token = Token {
+        name: "DAI Stablecoin".to_string(),
+        address: "0x4315D935947bf9430152b5e90E0A5675e888Be90"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::DAI,
+    };
+    let weth_token = Token {
+        name: "Wrapped Ether".to_string(),
+        address: "0x26CB03b59858dCD2b12F9309de5d1e8269e16F61"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::WETH,
+    };
+    let wbtc_token = Token {
+        name: "Wrapped Bitcoin".to_string(),
+        address: "0xc9EA4189848A3518B12808D98bFAD92eF48427A7"
+            .parse()
+            .unwrap(),
+        decimals: 8,
+        symbol: CurrencyCode::WBTC,
+    };
+    let tokens = TokenRegistry::new([dai_token.clone(), weth_token.clone(), wbtc_token.clone()]);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    // 3 gwei per gas with the default tip, i.e. 0.0015 ETH per liquidation.
+    let latest_block = BlockHeader {
+        number: 1,
+        hash: H256::zero(),
+        parent_hash: H256::zero(),
+        timestamp: U256::from(now),
+        base_fee_per_gas: Some(U256::from(1_000_000_000u64)),
+    };
+
+    let strategy = Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            liquidation_reward: Some(500),
+            ..Default::default()
+        },
+        latest_block,
+        tokens,
+    );
+
+    let dai = |amount: u64| U256::from(amount).saturating_mul(U256::from(10).pow(U256::from(18)));
+    let position = |id: u64, size: u64| {
+        Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy,
+            id: U256::from(id),
+            owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+                .parse()
+                .unwrap(),
+            owed_token: dai_token.address,
+            held_token: wbtc_token.address,
+            collateral_token: dai_token.address,
+            collateral: dai(size),
+            principal: dai(9 * size),
+            allowance: U256::from(50000 * size),
+            fees: U256::from(0),
+            created_at: U256::from(1024),
+        })
+    };
+    let ticker = |pair: Pair, price: &str| {
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair,
+            price: Price::from_str(price).unwrap(),
+            timestamp: now,
+        })
+    };
+
+    let mut events: Vec<Event> = [
+        (weth_token.address, 3000),
+        (wbtc_token.address, 2000),
+        (dai_token.address, 1000),
+    ]
+    .into_iter()
+    .map(|(token, risk_factor)| {
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token,
+            new_risk_factor: U256::from(risk_factor),
+        })
+    })
+    .collect();
+    events.extend([
+        ticker(Pair(CurrencyCode::WBTC, CurrencyCode::USD), "20000"),
+        ticker(Pair(CurrencyCode::WETH, CurrencyCode::USD), "1000"),
+        ticker(Pair(CurrencyCode::DAI, CurrencyCode::USD), "1"),
+        // 100 DAI of collateral, rewarding 0.005 ETH.
+        position(1, 100),
+        // 1 DAI of collateral, rewarding 0.00005 ETH, less than the gas it costs.
+        position(2, 1),
+        ticker(Pair(CurrencyCode::WBTC, CurrencyCode::USD), "18300"),
+    ]);
+
+    let liquidations = events.into_iter().fold(vec![], |mut liquidations, event| {
+        liquidations.append(&mut liquidator.run(&event));
+        liquidations
+    });
+
+    assert_eq!(liquidations.len(), 1);
+    assert_eq!(liquidations[0].position_id, U256::from(1));
+    // 0.005 ETH of reward, less 0.0015 ETH of gas and 0.5% of slippage.
+    assert_eq!(
+        liquidations[0].expected_profit,
+        Some(BigInt::from(3_475_000_000_000_000u64))
+    );
+    // 900 DAI of principal and 1 wei of fees, borrowed for 0.05%, i.e. 0.45 DAI.
+    assert_eq!(
+        liquidations[0].required_funds,
+        RequiredFunds {
+            token: dai_token.address,
+            amount: dai(900) + 1,
+            flash_loan_fee: Some(U256::from(450_000_000_000_000u64)),
+        }
+    );
 }
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use web3::types::{Address, H256, U256};
+
+use liquidation_bot::events::{
+    BlockHeader, Event, LiquidationFailed, LiquidationSucceeded, Ticker,
+};
+use liquidation_bot::metrics::Metrics;
+use liquidation_bot::types::{CurrencyCode, Exchange, Pair, Price};
+
+const NOW: u64 = 1_700_000_000;
+
+fn ticker(exchange: Exchange, timestamp: u64) -> Event {
+    Event::Ticker(Ticker {
+        exchange,
+        pair: Pair(CurrencyCode::WETH, CurrencyCode::USD),
+        price: Price::from_str("1000").unwrap(),
+        timestamp,
+    })
+}
+
+#[test]
+fn test_metrics_are_rendered_in_the_prometheus_format() {
+    let metrics = Metrics::new();
+
+    metrics.observe(
+        &Event::BlockHeader(BlockHeader {
+            number: 100,
+            hash: H256::from_low_u64_be(100),
+            parent_hash: H256::from_low_u64_be(99),
+            timestamp: U256::from(NOW - 3),
+            base_fee_per_gas: None,
+        }),
+        NOW,
+    );
+    metrics.observe(&ticker(Exchange::Coinbase, NOW - 2), NOW);
+    metrics.observe(&ticker(Exchange::Binance, NOW - 5), NOW);
+    metrics.observe(
+        &Event::LiquidationSucceeded(LiquidationSucceeded {
+            strategy: Address::zero(),
+            position_id: U256::one(),
+            transaction_hash: H256::zero(),
+        }),
+        NOW,
+    );
+    metrics.observe(
+        &Event::LiquidationFailed(LiquidationFailed {
+            strategy: Address::zero(),
+            position_id: U256::from(2),
+            reason: String::from("reverted"),
+        }),
+        NOW,
+    );
+    metrics.liquidations_submitted(2);
+    metrics.set_positions(&HashMap::from([(Address::zero(), 3)]), 1);
+
+    // Prices age between scrapes.
+    let rendered = metrics.render(NOW + 10);
+    for line in [
+        r#"liquidation_bot_events_total{type="ticker"} 2"#,
+        r#"liquidation_bot_events_total{type="block_header"} 1"#,
+        r#"liquidation_bot_open_positions{strategy="0x0000000000000000000000000000000000000000"} 3"#,
+        "liquidation_bot_underwater_positions 1",
+        "liquidation_bot_liquidations_submitted_total 2",
+        "liquidation_bot_liquidations_succeeded_total 1",
+        "liquidation_bot_liquidations_failed_total 1",
+        r#"liquidation_bot_feed_lag_seconds{source="ethereum"} 3"#,
+        r#"liquidation_bot_feed_lag_seconds{source="coinbase"} 2"#,
+        r#"liquidation_bot_feed_lag_seconds{source="binance"} 5"#,
+        r#"liquidation_bot_price_age_seconds{pair="WETH-USD"} 12"#,
+        "liquidation_bot_latest_block_timestamp_seconds 1699999997",
+    ] {
+        assert!(
+            rendered.lines().any(|l| l == line),
+            "{} in {}",
+            line,
+            rendered
+        );
+    }
+
+    // Strategies whose positions are all closed are no longer reported.
+    metrics.set_positions(&HashMap::new(), 0);
+    assert!(!metrics
+        .render(NOW)
+        .contains("liquidation_bot_open_positions{"));
+}
@@ -0,0 +1,256 @@
+use liquidation_bot::events::Event;
+use liquidation_bot::executor;
+use liquidation_bot::executor::gas::{Fees, GasPlan};
+use liquidation_bot::executor::nonce::{
+    NonceManager, PendingTransaction, PrivateTransaction, Replacement,
+};
+
+use web3::types::{
+    Address, Bytes, Log, TransactionParameters, TransactionReceipt, H256, U256, U64,
+};
+
+const STUCK_BLOCKS: u64 = 3;
+const MAX_SPEED_UPS: u32 = 2;
+
+fn pending_liquidation(position_id: u64, sent_at: u64) -> PendingTransaction {
+    PendingTransaction {
+        positions: vec![(Address::zero(), U256::from(position_id))],
+        cancelled: false,
+        transaction: TransactionParameters::default(),
+        gas_plan: GasPlan {
+            gas_limit: U256::from(300000),
+            fees: Fees::Eip1559 {
+                max_fee_per_gas: U256::from(40),
+                max_priority_fee_per_gas: U256::from(2),
+            },
+        },
+        budget: None,
+        hashes: vec![H256::from_low_u64_be(position_id)],
+        cancellation_hashes: vec![],
+        sent_at,
+        private: None,
+        replacements: 0,
+    }
+}
+
+#[test]
+fn test_nonces_are_handed_out_without_waiting_for_transactions() {
+    let mut nonces = NonceManager::new(U256::from(7));
+
+    assert_eq!(nonces.reserve(), U256::from(7));
+    nonces.sent(U256::from(7), pending_liquidation(1, 100));
+    assert_eq!(nonces.reserve(), U256::from(8));
+    nonces.sent(U256::from(8), pending_liquidation(2, 100));
+
+    // A nonce whose transaction could not be sent is reused.
+    let nonce = nonces.reserve();
+    nonces.release(nonce);
+    assert_eq!(nonces.next_nonce(), U256::from(9));
+
+    // Only the transactions below the nonce of the account are mined.
+    let mined = nonces.confirm(U256::from(8));
+    assert_eq!(mined.len(), 1);
+    assert_eq!(mined[0].0, U256::from(7));
+    assert!(nonces.get(&U256::from(7)).is_none());
+    assert!(nonces.get(&U256::from(8)).is_some());
+
+    // Transactions sent from the same account elsewhere move the next nonce.
+    nonces.confirm(U256::from(12));
+    assert!(nonces.get(&U256::from(8)).is_none());
+    assert_eq!(nonces.reserve(), U256::from(12));
+}
+
+#[test]
+fn test_stuck_liquidations_are_sped_up_cancelled_then_reported() {
+    let mut nonces = NonceManager::new(U256::zero());
+    nonces.sent(nonces.next_nonce(), pending_liquidation(1, 100));
+    nonces.reserve();
+    nonces.sent(nonces.next_nonce(), pending_liquidation(2, 102));
+    nonces.reserve();
+
+    assert!(nonces.stuck(102, STUCK_BLOCKS, MAX_SPEED_UPS).is_empty());
+    assert_eq!(
+        nonces.stuck(103, STUCK_BLOCKS, MAX_SPEED_UPS),
+        vec![Replacement::SpeedUp(U256::zero())]
+    );
+
+    let replace = |nonces: &mut NonceManager, block: u64, cancelled: bool| {
+        let pending = nonces.get(&U256::zero()).unwrap().clone();
+        let gas_plan = GasPlan {
+            fees: pending.gas_plan.fees.bumped(1250),
+            ..pending.gas_plan
+        };
+        nonces.replaced(
+            U256::zero(),
+            pending.transaction,
+            gas_plan,
+            H256::from_low_u64_be(block),
+            block,
+            cancelled,
+        );
+    };
+
+    replace(&mut nonces, 103, false);
+    assert_eq!(
+        nonces.stuck(106, STUCK_BLOCKS, MAX_SPEED_UPS),
+        vec![
+            Replacement::SpeedUp(U256::zero()),
+            Replacement::SpeedUp(U256::one())
+        ]
+    );
+    replace(&mut nonces, 106, false);
+    assert_eq!(
+        nonces.stuck(109, STUCK_BLOCKS, MAX_SPEED_UPS)[0],
+        Replacement::Cancel(U256::zero())
+    );
+
+    // A stuck cancellation is sped up again.
+    replace(&mut nonces, 109, true);
+    let pending = nonces.get(&U256::zero()).unwrap();
+    assert!(pending.cancelled);
+    assert_eq!(pending.hashes.len(), 3);
+    assert_eq!(
+        pending.cancellation_hashes,
+        vec![H256::from_low_u64_be(109)]
+    );
+    assert_eq!(
+        pending.gas_plan.fees,
+        Fees::Eip1559 {
+            max_fee_per_gas: U256::from(56),
+            max_priority_fee_per_gas: U256::from(2),
+        }
+    );
+    assert_eq!(
+        nonces.stuck(112, STUCK_BLOCKS, MAX_SPEED_UPS)[0],
+        Replacement::SpeedUp(U256::zero())
+    );
+
+    // Cancellations are sped up as many times as liquidations, then reported every few blocks.
+    replace(&mut nonces, 112, true);
+    assert_eq!(
+        nonces.stuck(115, STUCK_BLOCKS, MAX_SPEED_UPS)[0],
+        Replacement::SpeedUp(U256::zero())
+    );
+    replace(&mut nonces, 115, true);
+    assert_eq!(
+        nonces.stuck(118, STUCK_BLOCKS, MAX_SPEED_UPS)[0],
+        Replacement::Stuck(U256::zero())
+    );
+    nonces.reported(U256::zero(), 118);
+    assert_eq!(
+        nonces.stuck(120, STUCK_BLOCKS, MAX_SPEED_UPS),
+        vec![Replacement::SpeedUp(U256::one())]
+    );
+    assert_eq!(
+        nonces.stuck(121, STUCK_BLOCKS, MAX_SPEED_UPS)[0],
+        Replacement::Stuck(U256::zero())
+    );
+    assert_eq!(
+        nonces.get(&U256::zero()).unwrap().cancellation_hashes.len(),
+        3
+    );
+}
+
+#[test]
+fn test_mined_nonces_give_the_outcome_of_liquidations() {
+    let position_was_liquidated = H256::from_low_u64_be(0xdead);
+    let mut pending = pending_liquidation(1, 100);
+    pending.hashes.push(H256::from_low_u64_be(2));
+    let receipt = |hash: u64, status: u64| TransactionReceipt {
+        transaction_hash: H256::from_low_u64_be(hash),
+        status: Some(U64::from(status)),
+        ..Default::default()
+    };
+
+    // Any version of the liquidation may be mined.
+    match &executor::outcomes(&pending, Some(&receipt(2, 1)), position_was_liquidated)[..] {
+        [Event::LiquidationSucceeded(succeeded)] => {
+            assert_eq!(succeeded.position_id, U256::from(1));
+            assert_eq!(succeeded.transaction_hash, H256::from_low_u64_be(2));
+        }
+        events => panic!("unexpected {:?}", events),
+    }
+    assert!(matches!(
+        executor::outcomes(&pending, Some(&receipt(1, 0)), position_was_liquidated)[..],
+        [Event::LiquidationFailed(_)]
+    ));
+
+    // A mined cancellation, or another transaction using the nonce, means the liquidation never
+    // happened.
+    pending.cancellation_hashes.push(H256::from_low_u64_be(3));
+    assert!(matches!(
+        executor::outcomes(&pending, Some(&receipt(3, 1)), position_was_liquidated)[..],
+        [Event::LiquidationTimedOut(_)]
+    ));
+    assert!(matches!(
+        executor::outcomes(&pending, None, position_was_liquidated)[..],
+        [Event::LiquidationTimedOut(_)]
+    ));
+}
+
+#[test]
+fn test_each_liquidation_of_a_batch_has_its_own_outcome() {
+    let position_was_liquidated = H256::from_low_u64_be(0xdead);
+    let strategy = Address::from_low_u64_be(0xbeef);
+    let mut pending = pending_liquidation(1, 100);
+    pending.positions = vec![(strategy, U256::from(1)), (strategy, U256::from(2))];
+
+    // Only the first position was liquidated, the liquidation of the second one failed within
+    // the batch.
+    let receipt = TransactionReceipt {
+        transaction_hash: H256::from_low_u64_be(1),
+        status: Some(U64::one()),
+        logs: vec![Log {
+            address: strategy,
+            topics: vec![position_was_liquidated, H256::from_low_u64_be(1)],
+            data: Bytes::default(),
+            block_hash: None,
+            block_number: None,
+            transaction_hash: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }],
+        ..Default::default()
+    };
+
+    match &executor::outcomes(&pending, Some(&receipt), position_was_liquidated)[..] {
+        [Event::LiquidationSucceeded(succeeded), Event::LiquidationFailed(failed)] => {
+            assert_eq!(succeeded.position_id, U256::from(1));
+            assert_eq!(failed.position_id, U256::from(2));
+        }
+        events => panic!("unexpected {:?}", events),
+    }
+}
+
+#[test]
+fn test_missed_bundles_are_broadcast_then_replaced() {
+    let mut nonces = NonceManager::new(U256::from(3));
+    let nonce = nonces.reserve();
+    nonces.sent(
+        nonce,
+        PendingTransaction {
+            private: Some(PrivateTransaction {
+                until: 103,
+                raw_transaction: Bytes(vec![0x02]),
+            }),
+            ..pending_liquidation(1, 100)
+        },
+    );
+
+    // Private transactions are left to the relay until their last target block.
+    assert!(nonces.missed(102).is_empty());
+    assert!(nonces.stuck(110, 3, 2).is_empty());
+    assert_eq!(nonces.missed(103), vec![nonce]);
+    // The transaction is broadcast as signed for the relay, with the hash already recorded.
+    let private = nonces.get(&nonce).unwrap().private.as_ref().unwrap();
+    assert_eq!(private.raw_transaction, Bytes(vec![0x02]));
+
+    nonces.published(nonce, 104);
+    assert!(nonces.get(&nonce).unwrap().private.is_none());
+    assert!(nonces.missed(110).is_empty());
+    assert!(nonces.stuck(106, 3, 2).is_empty());
+    assert_eq!(nonces.stuck(107, 3, 2), vec![Replacement::SpeedUp(nonce)]);
+}
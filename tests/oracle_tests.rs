@@ -0,0 +1,259 @@
+use std::str::FromStr;
+
+use liquidation_bot::oracle::{Configuration, Oracle, OracleError};
+use liquidation_bot::types::{CurrencyCode, Exchange, Pair, Price, PricePoint, Ratio};
+
+const NOW: u64 = 1_700_000_000;
+
+fn eth_usd() -> Pair {
+    Pair(CurrencyCode::WETH, CurrencyCode::USD)
+}
+
+fn price_point(exchange: Exchange, price: &str, timestamp: u64) -> PricePoint {
+    PricePoint {
+        price: Price::from_str(price).unwrap(),
+        exchange,
+        timestamp,
+    }
+}
+
+fn new_oracle(min_sources: usize) -> Oracle {
+    Oracle::new(Configuration {
+        min_sources,
+        max_deviation: 200, // 2%
+    })
+}
+
+#[test]
+fn test_price_is_the_median_of_all_sources() {
+    let mut oracle = new_oracle(1);
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "1000.5", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Binance, "1001", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Kraken, "999.25", NOW));
+
+    let aggregated_price = oracle.price(&eth_usd()).unwrap();
+    assert_eq!(aggregated_price.price, Price::from_str("1000.50").unwrap());
+    assert_eq!(aggregated_price.sources.len(), 3);
+
+    // The median of an even number of sources is the exact mean of the two middle prices.
+    let mut oracle = new_oracle(1);
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "1000", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Kraken, "1000.01", NOW));
+    assert_eq!(
+        oracle.price(&eth_usd()).unwrap().price,
+        Price::from_str("1000.005").unwrap()
+    );
+}
+
+#[test]
+fn test_outliers_are_rejected() {
+    let mut oracle = new_oracle(2);
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "1000", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Kraken, "1010", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Binance, "500", NOW));
+
+    let aggregated_price = oracle.trusted_price(&eth_usd(), NOW, |_| 60).unwrap();
+    assert_eq!(aggregated_price.price, Price::from_str("1005.0").unwrap());
+    assert_eq!(aggregated_price.outliers, vec![Exchange::Binance]);
+}
+
+#[test]
+fn test_trusted_price_requires_a_quorum_of_fresh_sources() {
+    let mut oracle = new_oracle(2);
+    assert_eq!(
+        oracle.trusted_price(&eth_usd(), NOW, |_| 60),
+        Err(OracleError::MissingPrice(eth_usd()))
+    );
+
+    oracle.update(
+        &eth_usd(),
+        price_point(Exchange::Coinbase, "1000", NOW - 120),
+    );
+    assert_eq!(
+        oracle.trusted_price(&eth_usd(), NOW, |_| 60),
+        Err(OracleError::StalePrice {
+            pair: eth_usd(),
+            age: 120,
+            max_age: 60
+        })
+    );
+
+    // A single fresh source is not enough.
+    oracle.update(&eth_usd(), price_point(Exchange::Kraken, "1000", NOW));
+    assert!(matches!(
+        oracle.trusted_price(&eth_usd(), NOW, |_| 60),
+        Err(OracleError::QuorumNotReached { .. })
+    ));
+
+    // Neither are two sources which disagree.
+    oracle.update(&eth_usd(), price_point(Exchange::Binance, "1100", NOW));
+    assert!(matches!(
+        oracle.trusted_price(&eth_usd(), NOW, |_| 60),
+        Err(OracleError::QuorumNotReached { .. })
+    ));
+
+    oracle.update(&eth_usd(), price_point(Exchange::Binance, "1001", NOW));
+    let aggregated_price = oracle.trusted_price(&eth_usd(), NOW, |_| 60).unwrap();
+    assert_eq!(aggregated_price.price, Price::from_str("1000.5").unwrap());
+}
+
+#[test]
+fn test_older_prices_do_not_replace_newer_ones() {
+    let mut oracle = new_oracle(1);
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "1000", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "900", NOW - 1));
+
+    assert_eq!(
+        oracle.price(&eth_usd()).unwrap().price,
+        Price::from_str("1000").unwrap()
+    );
+}
+
+#[test]
+fn test_conversions_chain_any_quoted_pairs() {
+    let wbtc_weth = Pair(CurrencyCode::WBTC, CurrencyCode::WETH);
+    let usdc_usd = Pair(CurrencyCode::USDC, CurrencyCode::USD);
+    let dai_usdc = Pair(CurrencyCode::DAI, CurrencyCode::USDC);
+
+    let mut oracle = new_oracle(1);
+    oracle.update(&wbtc_weth, price_point(Exchange::Binance, "16", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "2500", NOW));
+    oracle.update(&usdc_usd, price_point(Exchange::Chainlink, "1", NOW));
+    oracle.update(&dai_usdc, price_point(Exchange::Uniswap, "1.000", NOW));
+
+    // 1 WBTC = 16 WETH = 40000 USD = 40000 USDC = 40000 DAI
+    let conversion = oracle
+        .conversion(&CurrencyCode::WBTC, &CurrencyCode::DAI)
+        .unwrap();
+    assert_eq!(
+        conversion.path,
+        vec![wbtc_weth.clone(), eth_usd(), usdc_usd.clone(), dai_usdc]
+    );
+    assert_eq!(
+        conversion.rate.to_price(0),
+        Some(Price::from_str("40000").unwrap())
+    );
+
+    // Pairs are followed backwards with exact inverse prices.
+    let conversion = oracle
+        .conversion(&CurrencyCode::DAI, &CurrencyCode::WBTC)
+        .unwrap();
+    assert_eq!(
+        conversion.rate.to_price(8),
+        Some(Price::from_str("0.00002500").unwrap())
+    );
+    assert_eq!(
+        oracle
+            .conversion(&CurrencyCode::WETH, &CurrencyCode::WETH)
+            .unwrap()
+            .rate,
+        Ratio::one()
+    );
+    assert_eq!(
+        oracle.conversion(&CurrencyCode::WETH, &CurrencyCode::new("LINK")),
+        None
+    );
+}
+
+#[test]
+fn test_sources_of_a_pair_have_their_own_maximum_age() {
+    let mut oracle = new_oracle(2);
+    let max_age = |exchange| match exchange {
+        Exchange::Chainlink => 3600,
+        _ => 60,
+    };
+
+    oracle.update(
+        &eth_usd(),
+        price_point(Exchange::Chainlink, "1000", NOW - 1800),
+    );
+    oracle.update(
+        &eth_usd(),
+        price_point(Exchange::Coinbase, "1000", NOW - 120),
+    );
+
+    // The Chainlink round is current, the frozen Coinbase price does not count towards the quorum.
+    match oracle.trusted_price(&eth_usd(), NOW, max_age) {
+        Err(OracleError::QuorumNotReached { sources, .. }) => {
+            assert_eq!(sources, vec![Exchange::Chainlink])
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "1000", NOW));
+    assert!(oracle.trusted_price(&eth_usd(), NOW, max_age).is_ok());
+}
+
+#[test]
+fn test_trusted_conversions_avoid_untrusted_pairs() {
+    let wbtc_usd = Pair(CurrencyCode::WBTC, CurrencyCode::USD);
+    let wbtc_weth = Pair(CurrencyCode::WBTC, CurrencyCode::WETH);
+
+    let mut oracle = new_oracle(1);
+    oracle.update(
+        &wbtc_usd,
+        price_point(Exchange::Coinbase, "30000", NOW - 120),
+    );
+    oracle.update(&wbtc_weth, price_point(Exchange::Binance, "16", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "2500", NOW));
+
+    // The direct pair is too old, the rate comes from the fresh prices of the longer path.
+    let conversion = oracle
+        .trusted_conversion(&CurrencyCode::WBTC, &CurrencyCode::USD, NOW, |_, _| 60)
+        .unwrap();
+    assert_eq!(conversion.path, vec![wbtc_weth, eth_usd()]);
+    assert_eq!(
+        conversion.rate.to_price(0),
+        Some(Price::from_str("40000").unwrap())
+    );
+
+    // Without a trusted path, the reason the best path is not trusted is given.
+    oracle.update(
+        &eth_usd(),
+        price_point(Exchange::Coinbase, "2500", NOW - 300),
+    );
+    assert_eq!(
+        oracle.trusted_conversion(
+            &CurrencyCode::WBTC,
+            &CurrencyCode::USD,
+            NOW + 100,
+            |_, _| 60
+        ),
+        Err(OracleError::StalePrice {
+            pair: wbtc_usd,
+            age: 220,
+            max_age: 60
+        })
+    );
+}
+
+#[test]
+fn test_zero_prices_are_not_used_for_conversions() {
+    let wbtc_usd = Pair(CurrencyCode::WBTC, CurrencyCode::USD);
+    let wbtc_weth = Pair(CurrencyCode::WBTC, CurrencyCode::WETH);
+
+    let mut oracle = new_oracle(1);
+    oracle.update(&wbtc_usd, price_point(Exchange::Coinbase, "0", NOW));
+    oracle.update(&wbtc_weth, price_point(Exchange::Binance, "16", NOW));
+    oracle.update(&eth_usd(), price_point(Exchange::Coinbase, "2500", NOW));
+
+    // USD cannot be converted into WBTC at a zero price, the longer path is taken instead.
+    let conversion = oracle
+        .conversion(&CurrencyCode::USD, &CurrencyCode::WBTC)
+        .unwrap();
+    assert_eq!(conversion.path, vec![eth_usd(), wbtc_weth]);
+    assert_eq!(
+        conversion.rate.to_price(6),
+        Some(Price::from_str("0.000025").unwrap())
+    );
+
+    // Followed forwards, a zero price gives a zero rate.
+    let conversion = oracle
+        .conversion(&CurrencyCode::WBTC, &CurrencyCode::USD)
+        .unwrap();
+    assert_eq!(conversion.path, vec![wbtc_usd]);
+    assert_eq!(
+        conversion.rate.to_price(0),
+        Some(Price::from_str("0").unwrap())
+    );
+}
@@ -0,0 +1,57 @@
+use num_bigint::BigInt;
+use web3::types::U256;
+
+use liquidation_bot::profitability::{self, Configuration, Profitability};
+
+#[test]
+fn test_profit_is_reward_less_gas_and_slippage() {
+    let configuration = Configuration {
+        liquidation_gas: 400_000,
+        priority_fee_per_gas: U256::from(1_000_000_000u64),
+        slippage: 100,
+        min_profit: U256::zero(),
+        flash_loan_fee: 9,
+    };
+
+    // 0.01 ETH of reward at 24 gwei per gas.
+    let profitability = profitability::estimate(
+        U256::from(10_000_000_000_000_000u64),
+        U256::from(24_000_000_000u64),
+        &configuration,
+    );
+    assert_eq!(
+        profitability,
+        Profitability {
+            reward: U256::from(10_000_000_000_000_000u64),
+            gas_cost: U256::from(10_000_000_000_000_000u64),
+            slippage: U256::from(100_000_000_000_000u64),
+            net_profit: BigInt::from(-100_000_000_000_000i64),
+        }
+    );
+    assert!(!profitability.is_worth_it(U256::zero()));
+
+    let profitability = profitability::estimate(
+        U256::from(20_000_000_000_000_000u64),
+        U256::from(24_000_000_000u64),
+        &configuration,
+    );
+    assert_eq!(
+        profitability.net_profit,
+        BigInt::from(9_800_000_000_000_000u64)
+    );
+    assert!(profitability.is_worth_it(U256::from(9_800_000_000_000_000u64)));
+    assert!(!profitability.is_worth_it(U256::from(9_800_000_000_000_001u64)));
+
+    // Borrowing 10000 tokens costs 9 of them, here priced at 0.001 ETH each.
+    assert_eq!(
+        profitability::flash_loan_fee(U256::from(10_000), &configuration),
+        U256::from(9)
+    );
+    assert_eq!(
+        profitability::with_flash_loan(
+            &profitability.net_profit,
+            U256::from(9_000_000_000_000_000u64)
+        ),
+        BigInt::from(800_000_000_000_000u64)
+    );
+}
@@ -0,0 +1,112 @@
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::Sender;
+
+use liquidation_bot::events::{BlockHeader, Event};
+use liquidation_bot::feeds::supervisor::{
+    self, Configuration, Feed, FeedError, FeedHealth, HealthRegistry,
+};
+use liquidation_bot::readiness::{self, ComponentReadiness};
+
+use web3::types::{H256, U256};
+
+/// Connects, sends a block and keeps the connection open.
+struct QuietFeed;
+
+#[async_trait]
+impl Feed for QuietFeed {
+    fn name(&self) -> &str {
+        "ethereum_blocks"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        health.connected();
+        events_queue
+            .send(Event::BlockHeader(BlockHeader {
+                number: 1,
+                hash: H256::zero(),
+                parent_hash: H256::zero(),
+                timestamp: U256::zero(),
+                base_fee_per_gas: None,
+            }))
+            .await?;
+
+        futures::future::pending().await
+    }
+}
+
+#[tokio::test]
+async fn test_bot_is_ready_once_bootstrapped_with_fresh_feeds() {
+    let (tx, mut rx) = mpsc::channel(16);
+    let registry = HealthRegistry::new();
+    let configuration = readiness::Configuration {
+        max_silence: Duration::from_secs(60),
+        quiet_feeds: vec![],
+    };
+
+    let supervisor_registry = registry.clone();
+    tokio::spawn(async move {
+        supervisor::supervise(
+            QuietFeed,
+            tx,
+            supervisor_registry,
+            &Configuration::default(),
+        )
+        .await;
+    });
+    rx.recv().await.unwrap();
+    assert!(registry
+        .get("ethereum_blocks")
+        .unwrap()
+        .last_event
+        .is_some());
+
+    // Nothing is ready before the positions are rebuilt and the executor checked the chain.
+    let now = SystemTime::now();
+    let readiness = readiness::check(&registry, &configuration, now);
+    assert!(!readiness.ready);
+    assert_eq!(
+        readiness.components[readiness::BOOTSTRAP],
+        ComponentReadiness {
+            ready: false,
+            detail: String::from("not reported yet"),
+        }
+    );
+    assert!(readiness.components["feed:ethereum_blocks"].ready);
+
+    registry.set_component(readiness::BOOTSTRAP, true, String::from("3 open positions"));
+    registry.set_component(readiness::EXECUTOR, true, String::from("checked"));
+    registry.set_component(readiness::BALANCES, false, String::from("0x00 holds 0 wei"));
+    assert!(!readiness::check(&registry, &configuration, now).ready);
+    registry.set_component(readiness::BALANCES, true, String::from("1 account"));
+    assert!(readiness::check(&registry, &configuration, now).ready);
+
+    // Feeds without events and an executor which stopped checking the chain are not ready,
+    // unless the feed is allowed to stay silent.
+    let later = now + Duration::from_secs(120);
+    let readiness = readiness::check(&registry, &configuration, later);
+    assert!(!readiness.ready);
+    assert!(readiness.components[readiness::BOOTSTRAP].ready);
+    assert!(!readiness.components[readiness::EXECUTOR].ready);
+    assert!(!readiness.components["feed:ethereum_blocks"].ready);
+
+    let quiet_configuration = readiness::Configuration {
+        quiet_feeds: vec![String::from("ethereum_blocks")],
+        ..configuration
+    };
+    let readiness = readiness::check(&registry, &quiet_configuration, later);
+    assert!(readiness.components["feed:ethereum_blocks"].ready);
+
+    let json = serde_json::to_value(&readiness).unwrap();
+    assert_eq!(json["ready"], false);
+    assert_eq!(
+        json["components"]["feed:ethereum_blocks"]["detail"],
+        "connected"
+    );
+}
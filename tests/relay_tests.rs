@@ -0,0 +1,124 @@
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use secp256k1::SecretKey;
+use serde_json::{json, Value};
+
+use liquidation_bot::executor::relay::{self, Relay, RelayError};
+use web3::signing::{self, Key, SecretKeyRef};
+use web3::types::{Address, Bytes, H256};
+
+const SIGNING_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+/// Requests received by a mock relay: their signature header and body.
+type Requests = Arc<Mutex<Vec<(String, Value)>>>;
+
+/// Starts a relay on a local port answering every request with `response`.
+fn start_mock_relay(response: Value) -> (String, Requests) {
+    let requests: Requests = Arc::new(Mutex::new(vec![]));
+    let state = web::Data::new((requests.clone(), response));
+    let server = HttpServer::new(move || {
+        App::new().app_data(state.clone()).default_service(
+                web::post().to(
+                    |request: HttpRequest,
+                     body: web::Bytes,
+                     state: web::Data<(Requests, Value)>| async move {
+                        let signature = request
+                            .headers()
+                            .get("X-Flashbots-Signature")
+                            .map(|value| value.to_str().unwrap().to_string())
+                            .unwrap_or_default();
+                        let body = serde_json::from_slice(&body).unwrap();
+                        state.0.lock().unwrap().push((signature, body));
+                        HttpResponse::Ok().json(&state.1)
+                    },
+                ),
+            )
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let url = format!("http://{}", server.addrs()[0]);
+    actix_rt::spawn(server.run());
+
+    (url, requests)
+}
+
+fn relay(url: String) -> Relay {
+    Relay::new(&relay::Configuration {
+        url,
+        signing_key: SecretKey::from_str(SIGNING_KEY).unwrap(),
+        max_missed_blocks: 3,
+    })
+}
+
+/// Recovers the address which signed a request with `body` from its `signature` header.
+fn signer(signature: &str, body: &Value) -> Address {
+    let (address, signature) = signature.split_once(':').unwrap();
+    let signature = hex(signature);
+    let body_hash = format!(
+        "{:?}",
+        H256::from(signing::keccak256(body.to_string().as_bytes()))
+    );
+    let recovered = signing::recover(
+        signing::hash_message(body_hash.as_bytes()).as_bytes(),
+        &signature[..64],
+        signature[64] as i32 - 27,
+    )
+    .unwrap();
+    assert_eq!(Address::from_str(address).unwrap(), recovered);
+
+    recovered
+}
+
+fn hex(value: &str) -> Vec<u8> {
+    let value = value.strip_prefix("0x").unwrap();
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_bundles_are_signed_and_target_a_block() {
+    let bundle_hash = H256::from_low_u64_be(0xb0b);
+    let (url, requests) = start_mock_relay(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "result": { "bundleHash": bundle_hash },
+    }));
+
+    let transaction = Bytes(vec![0x02, 0xf8, 0x6c]);
+    let hash = relay(url).send_bundle(&[transaction], 1234).await.unwrap();
+    assert_eq!(hash, bundle_hash);
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    let (signature, body) = &requests[0];
+    assert_eq!(
+        body,
+        &json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_sendBundle",
+            "params": [{ "txs": ["0x02f86c"], "blockNumber": "0x4d2" }],
+        })
+    );
+    let key = SecretKey::from_str(SIGNING_KEY).unwrap();
+    assert_eq!(signer(signature, body), SecretKeyRef::new(&key).address());
+}
+
+#[actix_web::test]
+async fn test_rejected_bundles_are_errors() {
+    let (url, _) = start_mock_relay(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "error": { "code": -32000, "message": "block in the past" },
+    }));
+
+    match relay(url).send_bundle(&[Bytes(vec![0x01])], 1).await {
+        Err(RelayError::Rejected(message)) => assert_eq!(message, "block in the past"),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
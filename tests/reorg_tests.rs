@@ -0,0 +1,212 @@
+use std::str::FromStr;
+
+use liquidation_bot::events::{
+    BlockHeader, ChainReorganized, Event, LiquidationSucceeded, LogMetadata, PositionWasClosed,
+    PositionWasLiquidated, PositionWasOpened,
+};
+use liquidation_bot::liquidator::{Configuration, Liquidator, PositionStatus};
+use liquidation_bot::token_registry::TokenRegistry;
+use liquidation_bot::types::{CurrencyCode, Token};
+
+use web3::types::{Address, H256, U256};
+
+const MARGIN_TRADING_STRATEGY_ADDRESS: &str = "0x09A37C94DF2b68831F0e56b943A416a00E5FA154";
+const DAI_ADDRESS: &str = "0x4315D935947bf9430152b5e90E0A5675e888Be90";
+const WBTC_ADDRESS: &str = "0xc9EA4189848A3518B12808D98bFAD92eF48427A7";
+
+fn new_liquidator(confirmations: u64) -> Liquidator {
+    let dai_token = Token {
+        name: "DAI Stablecoin".to_string(),
+        address: DAI_ADDRESS.parse().unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::DAI,
+    };
+    let wbtc_token = Token {
+        name: "Wrapped Bitcoin".to_string(),
+        address: WBTC_ADDRESS.parse().unwrap(),
+        decimals: 8,
+        symbol: CurrencyCode::WBTC,
+    };
+    let tokens = TokenRegistry::new([dai_token, wbtc_token]);
+
+    Liquidator::new(
+        Configuration {
+            confirmations,
+            ..Default::default()
+        },
+        block_header(100),
+        tokens,
+    )
+}
+
+fn block_hash(number: u64) -> H256 {
+    H256::from_low_u64_be(number)
+}
+
+fn block_header(number: u64) -> BlockHeader {
+    BlockHeader {
+        number,
+        hash: block_hash(number),
+        parent_hash: block_hash(number - 1),
+        timestamp: U256::from(number * 12),
+        base_fee_per_gas: None,
+    }
+}
+
+fn log(block_number: u64, log_index: u64) -> LogMetadata {
+    LogMetadata {
+        block_number,
+        block_hash: block_hash(block_number),
+        log_index: U256::from(log_index),
+    }
+}
+
+fn position_was_opened(log: LogMetadata, id: u64) -> Event {
+    Event::PositionWasOpened(PositionWasOpened {
+        log,
+        strategy: Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap(),
+        id: U256::from(id),
+        owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+            .parse()
+            .unwrap(),
+        owed_token: DAI_ADDRESS.parse().unwrap(),
+        held_token: WBTC_ADDRESS.parse().unwrap(),
+        collateral_token: DAI_ADDRESS.parse().unwrap(),
+        collateral: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 DAI
+        principal: U256::from(900).saturating_mul(U256::from(10).pow(U256::from(18))),  // 900 DAI
+        allowance: U256::from(5000000),                                                 // 0.05 WBTC
+        fees: U256::from(0),
+        created_at: U256::from(1024),
+    })
+}
+
+fn position_ids(liquidator: &Liquidator) -> Vec<U256> {
+    let mut ids: Vec<U256> = liquidator
+        .open_positions()
+        .into_iter()
+        .map(|position| position.id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+fn snapshot_position_ids(liquidator: &Liquidator) -> Vec<U256> {
+    let mut ids: Vec<U256> = liquidator
+        .snapshot()
+        .positions
+        .into_iter()
+        .map(|position| position.id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn test_chain_reorganization_reverts_removed_blocks() {
+    let mut liquidator = new_liquidator(0);
+
+    liquidator.run(&position_was_opened(log(101, 0), 1));
+    liquidator.run(&position_was_opened(log(102, 0), 2));
+    liquidator.run(&Event::PositionWasClosed(PositionWasClosed {
+        log: log(102, 1),
+        strategy: Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap(),
+        id: U256::from(1),
+    }));
+    assert_eq!(position_ids(&liquidator), vec![U256::from(2)]);
+
+    // Block 102 is replaced: position 1 is open again and position 2 never existed.
+    liquidator.run(&Event::ChainReorganized(ChainReorganized {
+        removed_blocks: vec![block_hash(102)],
+    }));
+    assert_eq!(position_ids(&liquidator), vec![U256::from(1)]);
+}
+
+#[test]
+fn test_removed_log_is_reverted_and_later_events_replayed() {
+    let mut liquidator = new_liquidator(0);
+
+    liquidator.run(&position_was_opened(log(101, 0), 1));
+    liquidator.run(&position_was_opened(log(101, 1), 2));
+    liquidator.run(&position_was_opened(log(102, 0), 3));
+
+    liquidator.run(&Event::LogWasRemoved(log(101, 0)));
+    assert_eq!(
+        position_ids(&liquidator),
+        vec![U256::from(2), U256::from(3)]
+    );
+
+    // Removing an unknown log is a no-op.
+    liquidator.run(&Event::LogWasRemoved(log(103, 0)));
+    assert_eq!(
+        position_ids(&liquidator),
+        vec![U256::from(2), U256::from(3)]
+    );
+}
+
+#[test]
+fn test_snapshot_only_contains_finalized_events() {
+    let mut liquidator = new_liquidator(3);
+
+    liquidator.run(&position_was_opened(log(101, 0), 1));
+    liquidator.run(&Event::BlockHeader(block_header(101)));
+    assert_eq!(liquidator.finalized_block(), 98);
+    assert_eq!(position_ids(&liquidator), vec![U256::from(1)]);
+    assert!(snapshot_position_ids(&liquidator).is_empty());
+
+    liquidator.run(&Event::LogsSynced(104));
+    liquidator.run(&Event::BlockHeader(block_header(104)));
+    assert_eq!(liquidator.checkpoint_block(), 101);
+    assert_eq!(snapshot_position_ids(&liquidator), vec![U256::from(1)]);
+
+    // Events from finalized blocks are no longer reverted.
+    liquidator.run(&Event::ChainReorganized(ChainReorganized {
+        removed_blocks: vec![block_hash(101)],
+    }));
+    assert_eq!(position_ids(&liquidator), vec![U256::from(1)]);
+}
+
+#[test]
+fn test_snapshot_waits_for_the_logs_of_its_block() {
+    let mut liquidator = new_liquidator(3);
+
+    // The logs lag behind the blocks, e.g. while their feed reconnects.
+    liquidator.run(&Event::LogsSynced(100));
+    liquidator.run(&position_was_opened(log(101, 0), 1));
+    liquidator.run(&Event::BlockHeader(block_header(110)));
+    assert_eq!(liquidator.finalized_block(), 107);
+    assert_eq!(liquidator.checkpoint_block(), 100);
+    assert!(snapshot_position_ids(&liquidator).is_empty());
+
+    // Blocks are checkpointed once all their logs are received, earlier reports notwithstanding.
+    liquidator.run(&Event::LogsSynced(109));
+    liquidator.run(&Event::LogsSynced(105));
+    assert_eq!(liquidator.checkpoint_block(), 107);
+    assert_eq!(snapshot_position_ids(&liquidator), vec![U256::from(1)]);
+}
+
+#[test]
+fn test_reverted_liquidations_make_positions_liquidable_again() {
+    let mut liquidator = new_liquidator(3);
+    let strategy = Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+
+    liquidator.run(&position_was_opened(log(101, 0), 1));
+    liquidator.run(&Event::LiquidationSucceeded(LiquidationSucceeded {
+        strategy,
+        position_id: U256::from(1),
+        transaction_hash: H256::from_low_u64_be(0xbeef),
+    }));
+    liquidator.run(&Event::PositionWasLiquidated(PositionWasLiquidated {
+        log: log(102, 0),
+        strategy,
+        id: U256::from(1),
+    }));
+    assert!(position_ids(&liquidator).is_empty());
+
+    // The liquidation is reverted with its block, the position is open and liquidable again.
+    liquidator.run(&Event::ChainReorganized(ChainReorganized {
+        removed_blocks: vec![block_hash(102)],
+    }));
+    let positions = liquidator.open_positions();
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].status, PositionStatus::Opened);
+}
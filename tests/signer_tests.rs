@@ -0,0 +1,198 @@
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use openssl::pkcs5;
+use openssl::symm::{self, Cipher};
+use secp256k1::SecretKey;
+use serde_json::{json, Value};
+
+use liquidation_bot::executor::signer::{self, LocalSigner, RemoteSigner, Signer, SignerError};
+use web3::signing;
+use web3::types::{Address, Bytes, TransactionParameters, H256, U256, U64};
+
+// Test vector of the Web3 Secret Storage Definition.
+const PASSWORD: &str = "testpassword";
+const SECRET: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+const PBKDF2_KEYSTORE: &str = r#"{
+    "crypto" : {
+        "cipher" : "aes-128-ctr",
+        "cipherparams" : { "iv" : "6087dab2f9fdbbfaddc31a909735c1e6" },
+        "ciphertext" : "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+        "kdf" : "pbkdf2",
+        "kdfparams" : {
+            "c" : 262144,
+            "dklen" : 32,
+            "prf" : "hmac-sha256",
+            "salt" : "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+        },
+        "mac" : "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+    },
+    "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+    "version" : 3
+}"#;
+
+/// Encrypts `secret` into a keystore with the scrypt parameters of Geth's light keystores.
+fn scrypt_keystore(secret: &[u8], password: &str) -> Value {
+    let salt = [0x5a; 32];
+    let iv = [0x1f; 16];
+    let mut derived_key = [0u8; 32];
+    pkcs5::scrypt(
+        password.as_bytes(),
+        &salt,
+        4096,
+        8,
+        6,
+        64 * 1024 * 1024,
+        &mut derived_key,
+    )
+    .unwrap();
+    let ciphertext =
+        symm::encrypt(Cipher::aes_128_ctr(), &derived_key[..16], Some(&iv), secret).unwrap();
+    let mac = signing::keccak256(&[&derived_key[16..], &ciphertext[..]].concat());
+
+    json!({
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": hex(&iv) },
+            "ciphertext": hex(&ciphertext),
+            "kdf": "scrypt",
+            "kdfparams": { "dklen": 32, "n": 4096, "r": 8, "p": 6, "salt": hex(&salt) },
+            "mac": hex(&mac),
+        },
+        "version": 3,
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn transaction() -> TransactionParameters {
+    TransactionParameters {
+        nonce: Some(U256::from(7)),
+        to: Some(Address::from_low_u64_be(0x11)),
+        gas: U256::from(100_000),
+        data: Bytes(vec![0xde, 0xad]),
+        chain_id: Some(1),
+        transaction_type: Some(U64::from(2)),
+        max_fee_per_gas: Some(U256::from(30_000_000_000u64)),
+        max_priority_fee_per_gas: Some(U256::from(1_000_000_000u64)),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_keystores_are_decrypted_with_their_password() {
+    let secret = SecretKey::from_str(SECRET).unwrap();
+    let keystores = [
+        serde_json::from_str(PBKDF2_KEYSTORE).unwrap(),
+        scrypt_keystore(&secret[..], PASSWORD),
+    ];
+    for keystore in keystores {
+        assert_eq!(
+            signer::decrypt_keystore(&keystore, PASSWORD).unwrap(),
+            secret
+        );
+        assert!(matches!(
+            signer::decrypt_keystore(&keystore, "wrong password"),
+            Err(SignerError::WrongPassword)
+        ));
+    }
+
+    // The trailing newline of the password file is not part of the password.
+    let dir = env::temp_dir().join(format!("liquidation-bot-keystore-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let keystore_path = dir.join("keystore.json");
+    let password_path = dir.join("password");
+    fs::write(&keystore_path, PBKDF2_KEYSTORE).unwrap();
+    fs::write(&password_path, format!("{}\n", PASSWORD)).unwrap();
+    let read = signer::read_keystore(
+        keystore_path.to_str().unwrap(),
+        password_path.to_str().unwrap(),
+    );
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(read.unwrap(), secret);
+}
+
+#[tokio::test]
+async fn test_local_signer_signs_complete_transactions() {
+    let signer = LocalSigner::new(SecretKey::from_str(SECRET).unwrap());
+    assert_eq!(
+        signer.address(),
+        Address::from_str("0x008AeEda4D805471dF9b2A5B0f38A0C3bCBA786b").unwrap()
+    );
+
+    let signed = signer.sign_transaction(transaction()).await.unwrap();
+    // An EIP-1559 transaction, hashed as sent.
+    assert_eq!(signed.raw_transaction.0[0], 2);
+    assert_eq!(
+        signed.transaction_hash,
+        H256::from(signing::keccak256(&signed.raw_transaction.0))
+    );
+
+    let incomplete = TransactionParameters {
+        nonce: None,
+        ..transaction()
+    };
+    assert!(matches!(
+        signer.sign_transaction(incomplete).await,
+        Err(SignerError::IncompleteTransaction)
+    ));
+}
+
+#[actix_web::test]
+async fn test_remote_signer_signs_with_eth_sign_transaction() {
+    let requests: Arc<Mutex<Vec<Value>>> = Arc::new(Mutex::new(vec![]));
+    let state = web::Data::new(requests.clone());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .default_service(web::post().to(
+                |body: web::Json<Value>, requests: web::Data<Arc<Mutex<Vec<Value>>>>| async move {
+                    requests.lock().unwrap().push(body.into_inner());
+                    HttpResponse::Ok()
+                        .json(json!({ "jsonrpc": "2.0", "id": 1, "result": "0x02f00d" }))
+                },
+            ))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let url = format!("http://{}", server.addrs()[0]);
+    actix_rt::spawn(server.run());
+
+    let address = Address::from_low_u64_be(0xacc);
+    let signer = RemoteSigner::new(&url, address);
+    assert_eq!(signer.address(), address);
+
+    let signed = signer.sign_transaction(transaction()).await.unwrap();
+    assert_eq!(signed.raw_transaction, Bytes(vec![0x02, 0xf0, 0x0d]));
+    assert_eq!(
+        signed.transaction_hash,
+        H256::from(signing::keccak256(&[0x02, 0xf0, 0x0d]))
+    );
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(
+        requests[0],
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_signTransaction",
+            "params": [{
+                "from": address,
+                "to": Address::from_low_u64_be(0x11),
+                "gas": "0x186a0",
+                "value": "0x0",
+                "data": "0xdead",
+                "nonce": "0x7",
+                "type": "0x2",
+                "maxFeePerGas": "0x6fc23ac00",
+                "maxPriorityFeePerGas": "0x3b9aca00",
+            }],
+        })
+    );
+}
@@ -0,0 +1,69 @@
+use liquidation_bot::executor::simulation;
+
+use serde_json::json;
+use web3::ethabi::{self, Token};
+use web3::types::U256;
+
+const LIQUIDATOR_ABI: &str = r#"[
+    {"type": "function", "name": "liquidateSingle", "inputs": [{"name": "strategy", "type": "address"}, {"name": "positionId", "type": "uint256"}], "outputs": [], "stateMutability": "nonpayable"},
+    {"type": "error", "name": "Liquidator__Position_Not_Liquidable", "inputs": [{"name": "positionId", "type": "uint256"}, {"name": "score", "type": "int256"}]}
+]"#;
+
+fn liquidator_abi() -> ethabi::Contract {
+    ethabi::Contract::load(LIQUIDATOR_ABI.as_bytes()).unwrap()
+}
+
+fn with_selector(selector: &[u8], tokens: &[Token]) -> Vec<u8> {
+    [selector, &ethabi::encode(tokens)].concat()
+}
+
+#[test]
+fn test_revert_reasons_are_decoded() {
+    let abi = liquidator_abi();
+
+    let data = with_selector(
+        &[0x08, 0xc3, 0x79, 0xa0],
+        &[Token::String(String::from(
+            "ERC20: transfer amount exceeds balance",
+        ))],
+    );
+    assert_eq!(
+        simulation::decode_revert_reason(&abi, &data),
+        "ERC20: transfer amount exceeds balance"
+    );
+
+    let data = with_selector(&[0x4e, 0x48, 0x7b, 0x71], &[Token::Uint(U256::from(0x11))]);
+    assert_eq!(simulation::decode_revert_reason(&abi, &data), "panic 0x11");
+
+    let error = abi.error("Liquidator__Position_Not_Liquidable").unwrap();
+    let data = error
+        .encode(&[Token::Uint(U256::from(3)), Token::Int(U256::from(0))])
+        .unwrap();
+    assert_eq!(
+        simulation::decode_revert_reason(&abi, &data),
+        "Liquidator__Position_Not_Liquidable(3, 0)"
+    );
+
+    assert_eq!(
+        simulation::decode_revert_reason(&abi, &[0xde, 0xad, 0xbe, 0xef]),
+        "unknown error 0xdeadbeef"
+    );
+    assert_eq!(
+        simulation::decode_revert_reason(&abi, &[]),
+        "reverted without reason"
+    );
+}
+
+#[test]
+fn test_revert_data_is_read_from_node_errors() {
+    assert_eq!(
+        simulation::revert_data(&json!("0x4e487b71")),
+        Some(vec![0x4e, 0x48, 0x7b, 0x71])
+    );
+    assert_eq!(
+        simulation::revert_data(&json!({"message": "reverted", "data": "0xdeadbeef"})),
+        Some(vec![0xde, 0xad, 0xbe, 0xef])
+    );
+    assert_eq!(simulation::revert_data(&json!("0xzz")), None);
+    assert_eq!(simulation::revert_data(&json!(3)), None);
+}
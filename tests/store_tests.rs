@@ -0,0 +1,135 @@
+use std::env;
+use std::str::FromStr;
+
+use liquidation_bot::events::{
+    BlockHeader, Event, LogMetadata, PositionWasOpened, RiskFactorWasUpdated, Ticker,
+};
+use liquidation_bot::liquidator::{Configuration, Liquidator};
+use liquidation_bot::store::{Checkpoint, Store};
+use liquidation_bot::token_registry::TokenRegistry;
+use liquidation_bot::types::{CurrencyCode, Exchange, Pair, Price, Token};
+
+use web3::types::{Address, H256, U256};
+
+const MARGIN_TRADING_STRATEGY_ADDRESS: &str = "0x09A37C94DF2b68831F0e56b943A416a00E5FA154";
+
+#[test]
+fn test_checkpoint_restores_positions_state() {
+    let dai_token = Token {
+        name: "DAI Stablecoin".to_string(),
+        address: "0x4315D935947bf9430152b5e90E0A5675e888Be90"
+            .parse()
+            .unwrap(),
+        decimals: 18,
+        symbol: CurrencyCode::DAI,
+    };
+    let wbtc_token = Token {
+        name: "Wrapped Bitcoin".to_string(),
+        address: "0xc9EA4189848A3518B12808D98bFAD92eF48427A7"
+            .parse()
+            .unwrap(),
+        decimals: 8,
+        symbol: CurrencyCode::WBTC,
+    };
+    let tokens = TokenRegistry::new([dai_token.clone(), wbtc_token.clone()]);
+    let latest_block = BlockHeader {
+        number: 1,
+        hash: H256::zero(),
+        parent_hash: H256::zero(),
+        timestamp: U256::from(1024),
+        base_fee_per_gas: None,
+    };
+    let strategy = Address::from_str(MARGIN_TRADING_STRATEGY_ADDRESS).unwrap();
+
+    let mut liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            ..Default::default()
+        },
+        latest_block.clone(),
+        tokens,
+    );
+    let events = [
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: wbtc_token.address,
+            new_risk_factor: U256::from(2000),
+        }),
+        Event::RiskFactorWasUpdated(RiskFactorWasUpdated {
+            log: LogMetadata::default(),
+            strategy,
+            token: dai_token.address,
+            new_risk_factor: U256::from(1000),
+        }),
+        Event::PositionWasOpened(PositionWasOpened {
+            log: LogMetadata::default(),
+            strategy,
+            id: U256::from(1),
+            owner: "0x643969a6ad1638e646Eda63961E1b54c198d15E3"
+                .parse()
+                .unwrap(),
+            owed_token: dai_token.address,
+            held_token: wbtc_token.address,
+            collateral_token: dai_token.address,
+            collateral: U256::from(100).saturating_mul(U256::from(10).pow(U256::from(18))), // 100 DAI
+            principal: U256::from(900).saturating_mul(U256::from(10).pow(U256::from(18))), // 900 DAI
+            allowance: U256::from(5000000), // 0.05 WBTC
+            fees: U256::from(0),
+            created_at: U256::from(1024),
+        }),
+    ];
+    for event in events.iter() {
+        liquidator.run(event);
+    }
+
+    let path = env::temp_dir().join(format!("liquidation-bot-store-{}", std::process::id()));
+    let store = Store::open(path.to_str().unwrap()).unwrap();
+    assert!(store.load_checkpoint().unwrap().is_none());
+    store
+        .save_checkpoint(&Checkpoint {
+            block_number: 7200738,
+            state: liquidator.snapshot(),
+        })
+        .unwrap();
+
+    let checkpoint = store.load_checkpoint().unwrap().unwrap();
+    assert_eq!(checkpoint.block_number, 7200738);
+
+    // Tokens are restored along with the positions, whether or not they were in the token list.
+    let mut restored_liquidator = Liquidator::new(
+        Configuration {
+            confirmations: 0,
+            ..Default::default()
+        },
+        latest_block,
+        TokenRegistry::default(),
+    );
+    restored_liquidator.restore(checkpoint.state);
+
+    // The restored liquidator liquidates the position as soon as prices make it underwater.
+    let price_events = [
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::DAI, CurrencyCode::USD),
+            price: Price::from_str("1").unwrap(),
+            timestamp: 1024,
+        }),
+        Event::Ticker(Ticker {
+            exchange: Exchange::Coinbase,
+            pair: Pair(CurrencyCode::WBTC, CurrencyCode::USD),
+            price: Price::from_str("18300").unwrap(),
+            timestamp: 1024,
+        }),
+    ];
+    let liquidations: Vec<_> = price_events
+        .iter()
+        .flat_map(|event| restored_liquidator.run(event))
+        .collect();
+
+    assert_eq!(liquidations.len(), 1);
+    assert_eq!(liquidations[0].strategy, strategy);
+
+    drop(store);
+    std::fs::remove_dir_all(path).unwrap();
+}
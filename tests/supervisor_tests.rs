@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::Sender;
+
+use liquidation_bot::events::{BlockHeader, Event};
+use liquidation_bot::feeds::supervisor::{
+    self, Backoff, Configuration, Feed, FeedError, FeedHealth, FeedStatus, HealthRegistry,
+};
+
+use web3::types::{H256, U256};
+
+/// Fails to connect `failures` times, then sends one block per run and drops the connection.
+struct FlakyFeed {
+    failures: u32,
+    next_block: u64,
+}
+
+#[async_trait]
+impl Feed for FlakyFeed {
+    fn name(&self) -> &str {
+        "flaky"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        if self.failures > 0 {
+            self.failures -= 1;
+            return Err("connection refused".into());
+        }
+
+        health.connected();
+        events_queue
+            .send(Event::BlockHeader(BlockHeader {
+                number: self.next_block,
+                hash: H256::zero(),
+                parent_hash: H256::zero(),
+                timestamp: U256::zero(),
+                base_fee_per_gas: None,
+            }))
+            .await?;
+        self.next_block += 1;
+
+        Ok(())
+    }
+}
+
+/// Panics on its first run, then sends one block per run like a healthy feed.
+struct PanickingFeed {
+    runs: u32,
+}
+
+#[async_trait]
+impl Feed for PanickingFeed {
+    fn name(&self) -> &str {
+        "panicking"
+    }
+
+    async fn run(
+        &mut self,
+        events_queue: Sender<Event>,
+        health: &FeedHealth,
+    ) -> Result<(), FeedError> {
+        self.runs += 1;
+        health.connected();
+        if self.runs == 1 {
+            panic!("unexpected message");
+        }
+
+        events_queue
+            .send(Event::BlockHeader(BlockHeader {
+                number: self.runs as u64,
+                hash: H256::zero(),
+                parent_hash: H256::zero(),
+                timestamp: U256::zero(),
+                base_fee_per_gas: None,
+            }))
+            .await?;
+        std::future::pending().await
+    }
+}
+
+#[test]
+fn test_backoff_doubles_up_to_the_maximum() {
+    let mut backoff = Backoff::new(&Configuration {
+        initial_backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(5),
+    });
+
+    let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+    assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+
+    backoff.reset();
+    assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_feed_is_reconnected_and_resumes() {
+    let (tx, mut rx) = mpsc::channel(16);
+    let registry = HealthRegistry::new();
+    let feed = FlakyFeed {
+        failures: 3,
+        next_block: 1,
+    };
+
+    let supervisor_registry = registry.clone();
+    let supervisor = tokio::spawn(async move {
+        supervisor::supervise(feed, tx, supervisor_registry, &Configuration::default()).await;
+    });
+
+    // The feed picks up where it stopped after every reconnection.
+    for expected_number in 1..=3 {
+        match rx.recv().await {
+            Some(Event::BlockHeader(block_header)) => {
+                assert_eq!(block_header.number, expected_number)
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    let state = registry.get("flaky").unwrap();
+    assert!(state.disconnections >= 5);
+    assert_ne!(state.status, FeedStatus::Connecting);
+
+    // The supervisor stops once the events are no longer consumed.
+    drop(rx);
+    supervisor.await.unwrap();
+    assert!(!registry.all_connected());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_panicking_feed_is_disconnected_and_restarted() {
+    let (tx, mut rx) = mpsc::channel(16);
+    let registry = HealthRegistry::new();
+
+    let supervisor_registry = registry.clone();
+    tokio::spawn(async move {
+        supervisor::supervise(
+            PanickingFeed { runs: 0 },
+            tx,
+            supervisor_registry,
+            &Configuration::default(),
+        )
+        .await;
+    });
+
+    // The panic is reported as a disconnection, then the feed runs again.
+    match rx.recv().await {
+        Some(Event::BlockHeader(block_header)) => assert_eq!(block_header.number, 2),
+        event => panic!("unexpected event {:?}", event),
+    }
+    let state = registry.get("panicking").unwrap();
+    assert_eq!(state.disconnections, 1);
+    assert_eq!(state.status, FeedStatus::Connected);
+}
@@ -0,0 +1,133 @@
+use std::str::FromStr;
+
+use liquidation_bot::feeds::uniswap::{find_route, is_connection_error, Hop, Pool};
+use liquidation_bot::types::{Price, Ratio};
+use liquidation_bot::uniswap_math::{self, MAX_TICK, MIN_TICK};
+use web3::types::{Address, U256};
+
+#[test]
+fn test_sqrt_ratio_at_tick_matches_tick_math() {
+    assert_eq!(uniswap_math::sqrt_ratio_at_tick(0), Some(U256::one() << 96));
+    assert_eq!(
+        uniswap_math::sqrt_ratio_at_tick(MIN_TICK),
+        Some(U256::from(4295128739u64))
+    );
+    assert_eq!(
+        uniswap_math::sqrt_ratio_at_tick(MAX_TICK),
+        Some(U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap())
+    );
+    assert_eq!(
+        uniswap_math::sqrt_ratio_at_tick(1),
+        Some(U256::from_dec_str("79232123823359799118286999568").unwrap())
+    );
+    assert_eq!(
+        uniswap_math::sqrt_ratio_at_tick(-1),
+        Some(U256::from_dec_str("79224201403219477170569942574").unwrap())
+    );
+    assert_eq!(uniswap_math::sqrt_ratio_at_tick(MAX_TICK + 1), None);
+}
+
+#[test]
+fn test_average_tick_rounds_towards_negative_infinity() {
+    assert_eq!(uniswap_math::average_tick(0, 6000, 600), 10);
+    assert_eq!(uniswap_math::average_tick(0, -6000, 600), -10);
+    assert_eq!(uniswap_math::average_tick(0, -6001, 600), -11);
+    assert_eq!(uniswap_math::average_tick(100, 6099, 600), 9);
+}
+
+fn sqrt_price_x96(sqrt_raw_price: u64) -> U256 {
+    U256::from(sqrt_raw_price) << 96
+}
+
+#[test]
+fn test_pool_price_accounts_for_token_decimals() {
+    // A USDC (6 decimals) / WETH (18 decimals) pool trading 4 * 10^8 WETH units per USDC unit,
+    // i.e. 1 USDC for 0.0004 WETH, or 2500 USDC per WETH.
+    let sqrt_price = sqrt_price_x96(20000);
+
+    let weth_per_usdc = uniswap_math::pool_price(sqrt_price, 6, 18, true);
+    assert_eq!(
+        weth_per_usdc.to_price(18),
+        Some(Price::from_str("0.000400000000000000").unwrap())
+    );
+    let usdc_per_weth = uniswap_math::pool_price(sqrt_price, 6, 18, false);
+    assert_eq!(
+        usdc_per_weth.to_price(6),
+        Some(Price::from_str("2500.000000").unwrap())
+    );
+}
+
+#[test]
+fn test_multi_hop_prices_are_exact_products() {
+    // 1 WBTC (8 decimals) for 16 WETH, and 2500 USDC per WETH: WBTC is worth 40000 USDC.
+    let wbtc_in_weth = uniswap_math::pool_price(sqrt_price_x96(400000), 8, 18, true);
+    let weth_in_usdc = uniswap_math::pool_price(sqrt_price_x96(20000), 6, 18, false);
+
+    let wbtc_in_usdc = wbtc_in_weth.mul(&weth_in_usdc);
+    assert_eq!(
+        wbtc_in_usdc.to_price(2),
+        Some(Price::from_str("40000.00").unwrap())
+    );
+    assert_eq!(Ratio::one().mul(&wbtc_in_usdc), wbtc_in_usdc);
+}
+
+#[test]
+fn test_routes_go_through_intermediate_tokens() {
+    let [usdc, weth, wbtc, dai, link] = [1u64, 2, 3, 4, 5].map(Address::from_low_u64_be);
+    let pool = |address: u64, token0: Address, token1: Address| Pool {
+        address: Address::from_low_u64_be(address),
+        token0,
+        token1,
+    };
+    let pools = [
+        pool(10, usdc, weth),
+        pool(11, wbtc, weth),
+        pool(12, dai, usdc),
+    ];
+
+    assert_eq!(
+        find_route(&pools, dai, usdc),
+        Some(vec![Hop {
+            pool: Address::from_low_u64_be(12),
+            zero_for_one: true
+        }])
+    );
+    assert_eq!(
+        find_route(&pools, wbtc, usdc),
+        Some(vec![
+            Hop {
+                pool: Address::from_low_u64_be(11),
+                zero_for_one: true
+            },
+            Hop {
+                pool: Address::from_low_u64_be(10),
+                zero_for_one: false
+            },
+        ])
+    );
+    assert_eq!(find_route(&pools, link, usdc), None);
+}
+
+#[test]
+fn test_only_connection_errors_reconnect_the_feed() {
+    use web3::contract::Error;
+    use web3::error::TransportError;
+
+    assert!(is_connection_error(&Error::Api(web3::Error::Unreachable)));
+    assert!(is_connection_error(&Error::Api(web3::Error::Transport(
+        TransportError::Message(String::from("connection closed"))
+    ))));
+
+    // Calls failing on a pool, e.g. reverting, only skip the routes through it.
+    let reverted = jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(3),
+        message: String::from("execution reverted: OLD"),
+        data: None,
+    };
+    assert!(!is_connection_error(&Error::Api(web3::Error::Rpc(
+        reverted
+    ))));
+    assert!(!is_connection_error(&Error::InvalidOutputType(
+        String::from("invalid tick")
+    )));
+}
@@ -0,0 +1,272 @@
+use std::env;
+use std::str::FromStr;
+
+use liquidation_bot::liquidation_bot::Configuration;
+use liquidation_bot::types::Pair;
+use liquidation_bot::utils::{self, ConfigurationError};
+use web3::types::Address;
+
+#[test]
+fn test_every_network_profile_is_valid() {
+    for network in ["mainnet", "goerli", "arbitrum", "local"] {
+        let profile = utils::load_network_profile("config.toml", network).unwrap();
+
+        assert_eq!(profile.deployment_dir, format!("deployed/{}", network));
+        assert_eq!(profile.private_key_env, "PRIVATE_KEY");
+
+        for (pair, feed) in profile.chainlink_feeds.iter() {
+            assert!(Pair::from_str(pair).is_ok(), "unknown pair {}", pair);
+            assert!(Address::from_str(&feed.address).is_ok());
+        }
+        for pool in profile.uniswap_pools.iter() {
+            assert!(Address::from_str(pool).is_ok());
+        }
+    }
+
+    assert!(utils::load_network_profile("config.toml", "ropsten").is_err());
+}
+
+#[test]
+fn test_env_vars_are_expanded_in_urls() {
+    env::set_var("LIQUIDATION_BOT_TEST_API_KEY", "secret");
+
+    assert_eq!(
+        utils::expand_env_vars("wss://goerli.infura.io/ws/v3/${LIQUIDATION_BOT_TEST_API_KEY}")
+            .unwrap(),
+        "wss://goerli.infura.io/ws/v3/secret"
+    );
+    assert_eq!(
+        utils::expand_env_vars("ws://127.0.0.1:8545").unwrap(),
+        "ws://127.0.0.1:8545"
+    );
+    assert!(utils::expand_env_vars("https://rpc/${LIQUIDATION_BOT_TEST_UNSET_KEY}").is_err());
+}
+
+#[test]
+fn test_zero_block_ranges_are_rejected() {
+    let path = env::temp_dir().join("liquidation_bot_test_zero_block_ranges.toml");
+    let profile = |setting: &str| {
+        format!(
+            "[networks.test]\n\
+             ethereum_provider_https_url = \"http://127.0.0.1:8545\"\n\
+             ethereum_provider_wss_url = \"ws://127.0.0.1:8545\"\n\
+             deployment_dir = \"deployed/local\"\n\
+             {}\n",
+            setting
+        )
+    };
+
+    for setting in ["log_page_size = 0", "checkpoint_interval = 0"] {
+        std::fs::write(&path, profile(setting)).unwrap();
+        let error = utils::load_network_profile(path.to_str().unwrap(), "test").unwrap_err();
+        assert!(error.to_string().contains("must be positive"), "{}", error);
+    }
+
+    std::fs::write(&path, profile("log_page_size = 1")).unwrap();
+    assert!(utils::load_network_profile(path.to_str().unwrap(), "test").is_ok());
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Loads a profile with `settings` in a deployment directory of its own named after `name`.
+fn load_test_config(name: &str, settings: &str) -> Result<Configuration, ConfigurationError> {
+    load_test_deployment(
+        name,
+        r#"{"Liquidator": "0x0000000000000000000000000000000000001111"}"#,
+        settings,
+    )
+}
+
+/// Loads a profile with `settings` in a deployment directory of its own named after `name`, whose
+/// contracts are at `addresses`.
+fn load_test_deployment(
+    name: &str,
+    addresses: &str,
+    settings: &str,
+) -> Result<Configuration, ConfigurationError> {
+    let dir = env::temp_dir().join(format!("liquidation_bot_test_{}", name));
+    std::fs::create_dir_all(dir.join("deployments")).unwrap();
+    std::fs::create_dir_all(dir.join("abi")).unwrap();
+    std::fs::write(
+        dir.join("deployments/addresses.json"),
+        format!(r#"{{"addresses": {}}}"#, addresses),
+    )
+    .unwrap();
+    std::fs::write(dir.join("deployments/tokenlist.json"), r#"{"tokens": []}"#).unwrap();
+    env::set_var(
+        "LIQUIDATION_BOT_TEST_PRIVATE_KEY",
+        "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318",
+    );
+
+    let config_path = dir.join("config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "[networks.test]\n\
+             ethereum_provider_https_url = \"http://127.0.0.1:8545\"\n\
+             ethereum_provider_wss_url = \"ws://127.0.0.1:8545\"\n\
+             deployment_dir = \"{}\"\n\
+             private_key_env = \"LIQUIDATION_BOT_TEST_PRIVATE_KEY\"\n\
+             {}\n",
+            dir.to_str().unwrap(),
+            settings
+        ),
+    )
+    .unwrap();
+
+    utils::load_config(config_path.to_str().unwrap(), "test")
+}
+
+#[test]
+fn test_relays_require_their_own_signing_key() {
+    let relay_url = "relay_url = \"https://relay.flashbots.net\"";
+    match load_test_config("relay_key", relay_url) {
+        Err(error) => assert!(error.to_string().contains("relay_signing_key_env")),
+        Ok(_) => panic!("bundles signed with the liquidation key"),
+    }
+
+    env::set_var(
+        "LIQUIDATION_BOT_TEST_RELAY_KEY",
+        "0x0000000000000000000000000000000000000000000000000000000000000001",
+    );
+    let settings = format!(
+        "{}\nrelay_signing_key_env = \"LIQUIDATION_BOT_TEST_RELAY_KEY\"",
+        relay_url
+    );
+    let configuration = load_test_config("relay_key", &settings).unwrap();
+    assert!(configuration.executor_configuration.relay.is_some());
+}
+
+#[test]
+fn test_min_profit_requires_the_liquidation_reward() {
+    match load_test_config("min_profit", "min_liquidation_profit = 1000") {
+        Err(error) => assert!(error.to_string().contains("liquidation_reward")),
+        Ok(_) => panic!("minimum profit without a reward to estimate it"),
+    }
+
+    let configuration = load_test_config(
+        "min_profit",
+        "min_liquidation_profit = 1000\nliquidation_reward = 500",
+    )
+    .unwrap();
+    assert_eq!(
+        configuration.liquidator_configuration.liquidation_reward,
+        Some(500)
+    );
+}
+
+#[test]
+fn test_wallets_are_declared_once() {
+    let wallet =
+        "[[networks.test.wallets]]\nprivate_key_env = \"LIQUIDATION_BOT_TEST_PRIVATE_KEY\"";
+    match load_test_config("duplicate_wallets", &format!("{}\n{}", wallet, wallet)) {
+        Err(error) => assert!(error.to_string().contains("more than once")),
+        Ok(_) => panic!("two wallets sharing the nonces of one account"),
+    }
+
+    assert!(load_test_config("duplicate_wallets", wallet).is_ok());
+}
+
+#[test]
+fn test_chainlink_prices_are_trusted_up_to_the_heartbeat() {
+    let settings = "max_price_ages = { \"DAI-USD\" = 300 }\n\
+                    chainlink_feeds = { \
+                    \"ETH-USD\" = { address = \"0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419\", heartbeat = 3600 }, \
+                    \"DAI-USD\" = { address = \"0xAed0c38402a5d19df6E4c03F4E2DceD6e29c1ee9\", heartbeat = 3600 } }";
+    let configuration = load_test_config("chainlink_ages", settings).unwrap();
+    let liquidator_configuration = configuration.liquidator_configuration;
+    let eth_usd = Pair::from_str("ETH-USD").unwrap();
+    let dai_usd = Pair::from_str("DAI-USD").unwrap();
+
+    assert_eq!(
+        liquidator_configuration.chainlink_max_price_ages[&eth_usd],
+        3600
+    );
+    assert_eq!(
+        liquidator_configuration.chainlink_max_price_ages[&dai_usd],
+        3600
+    );
+    // The other sources of the pairs keep their own maximum age.
+    assert!(!liquidator_configuration
+        .max_price_ages
+        .contains_key(&eth_usd));
+    assert_eq!(liquidator_configuration.max_price_ages[&dai_usd], 300);
+}
+
+#[test]
+fn test_strategies_are_checked_when_loading() {
+    let dir = env::temp_dir().join("liquidation_bot_test_strategies");
+    std::fs::create_dir_all(dir.join("abi")).unwrap();
+    let _ = std::fs::remove_file(dir.join("abi/YearnStrategy.json"));
+    let addresses = r#"{
+        "Liquidator": "0x0000000000000000000000000000000000001111",
+        "YearnStrategy": "0x0000000000000000000000000000000000002222"
+    }"#;
+
+    // The ABI of every strategy is needed to decode its logs.
+    match load_test_deployment("strategies", addresses, "") {
+        Err(ConfigurationError::Io(path, _)) => assert!(path.ends_with("YearnStrategy.json")),
+        result => panic!("unexpected result {:?}", result.err()),
+    }
+
+    // Strategies need not emit every event.
+    std::fs::write(
+        dir.join("abi/YearnStrategy.json"),
+        r#"[{"type": "event", "name": "PositionWasClosed", "anonymous": false, "inputs": [
+            {"name": "id", "type": "uint256", "indexed": true}
+        ]}]"#,
+    )
+    .unwrap();
+    let configuration = load_test_deployment("strategies", addresses, "").unwrap();
+    let strategies = configuration.ithil_feed_configuration.strategies;
+    assert_eq!(strategies.len(), 1);
+    assert_eq!(strategies[0].address, Address::from_low_u64_be(0x2222));
+
+    let addresses = r#"{
+        "Liquidator": "0x0000000000000000000000000000000000001111",
+        "YearnStrategy": "0x2222"
+    }"#;
+    match load_test_deployment("strategies", addresses, "") {
+        Err(error) => assert!(error.to_string().contains("invalid YearnStrategy address")),
+        Ok(_) => panic!("strategy with an invalid address"),
+    }
+}
+
+#[test]
+fn test_chainlink_feeds_need_a_valid_address() {
+    let settings = "chainlink_feeds = { \"ETH-USD\" = { address = \"0x5f4e\", heartbeat = 3600 } }";
+    match load_test_config("chainlink_address", settings) {
+        Err(error) => assert!(error
+            .to_string()
+            .contains("invalid ETH-USD Chainlink feed address")),
+        Ok(_) => panic!("Chainlink feed with an invalid address"),
+    }
+}
+
+#[test]
+fn test_flash_loans_need_every_setting() {
+    let lender = "flash_loan_lender = \"aave\"\n\
+                  flash_loan_lender_address = \"0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2\"";
+    match load_test_config("flash_loan", lender) {
+        Err(error) => assert!(error.to_string().contains("flash_loan_receiver_address")),
+        Ok(_) => panic!("flash loans silently left out"),
+    }
+
+    let settings = format!("{}\nflash_loan_receiver_address = \"0x1234\"", lender);
+    match load_test_config("flash_loan", &settings) {
+        Err(error) => assert!(error
+            .to_string()
+            .contains("invalid flash loan receiver address")),
+        Ok(_) => panic!("flash loan receiver with an invalid address"),
+    }
+
+    let settings = format!(
+        "{}\nflash_loan_receiver_address = \"0x0000000000000000000000000000000000003333\"",
+        lender
+    );
+    let configuration = load_test_config("flash_loan", &settings).unwrap();
+    let flash_loan = configuration.executor_configuration.flash_loan.unwrap();
+    assert_eq!(
+        flash_loan.receiver_address,
+        Address::from_low_u64_be(0x3333)
+    );
+}
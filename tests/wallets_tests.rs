@@ -0,0 +1,112 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use secp256k1::SecretKey;
+use web3::types::{Address, TransactionParameters, H256, U256};
+
+use liquidation_bot::executor::gas::{Fees, GasPlan};
+use liquidation_bot::executor::nonce::PendingTransaction;
+use liquidation_bot::executor::signer::LocalSigner;
+use liquidation_bot::executor::wallets::{Wallet, WalletPool};
+
+const MIN_BALANCE: u64 = 1_000_000;
+
+fn wallet(secret: &str, balance: u64) -> Wallet {
+    let signer = LocalSigner::new(SecretKey::from_str(secret).unwrap());
+    Wallet::new(Arc::new(signer), U256::zero(), U256::from(balance))
+}
+
+// Costs at most 300000 * 10 = 3000000 wei.
+fn pending_liquidation(position_id: u64) -> PendingTransaction {
+    PendingTransaction {
+        positions: vec![(Address::zero(), U256::from(position_id))],
+        cancelled: false,
+        transaction: TransactionParameters::default(),
+        gas_plan: GasPlan {
+            gas_limit: U256::from(300000),
+            fees: Fees::Eip1559 {
+                max_fee_per_gas: U256::from(10),
+                max_priority_fee_per_gas: U256::from(1),
+            },
+        },
+        budget: None,
+        hashes: vec![H256::from_low_u64_be(position_id)],
+        cancellation_hashes: vec![],
+        sent_at: 100,
+        private: None,
+        replacements: 0,
+    }
+}
+
+fn send(pool: &mut WalletPool, wallet: usize, position_id: u64) {
+    let nonces = &mut pool.get_mut(wallet).nonces;
+    let nonce = nonces.reserve();
+    nonces.sent(nonce, pending_liquidation(position_id));
+}
+
+#[test]
+fn test_liquidations_go_to_the_least_busy_account_able_to_pay() {
+    let mut pool = WalletPool::new(
+        vec![
+            wallet(
+                "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318",
+                10_000_000,
+            ),
+            wallet(
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                20_000_000,
+            ),
+            wallet(
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                1_000,
+            ),
+        ],
+        U256::from(MIN_BALANCE),
+    );
+    let cost = U256::from(3_000_000);
+
+    // On a tie, the account with the most ETH is picked.
+    assert_eq!(pool.pick(cost), Some(1));
+    send(&mut pool, 1, 1);
+    assert_eq!(pool.get(1).spendable(), U256::from(17_000_000));
+
+    assert_eq!(pool.pick(cost), Some(0));
+    send(&mut pool, 0, 2);
+    assert_eq!(pool.pick(cost), Some(1));
+    send(&mut pool, 1, 3);
+
+    // The first account cannot pay for a second liquidation, the third for any.
+    send(&mut pool, 1, 4);
+    assert_eq!(pool.get(0).spendable(), U256::from(7_000_000));
+    assert_eq!(pool.pick(U256::from(8_000_000)), Some(1));
+    assert_eq!(pool.pick(U256::from(20_000_000)), None);
+
+    // Mined transactions no longer count.
+    pool.get_mut(1).nonces.confirm(U256::from(3));
+    assert_eq!(pool.pick(cost), Some(1));
+
+    // Accounts able to pay come in order of preference, to fall back on the next one.
+    assert_eq!(pool.eligible(cost), vec![1, 0]);
+    assert_eq!(pool.eligible(U256::zero()), vec![1, 2, 0]);
+    assert!(pool.eligible(U256::from(30_000_000)).is_empty());
+}
+
+#[test]
+fn test_accounts_below_the_minimum_balance_are_reported_once() {
+    let mut pool = WalletPool::new(
+        vec![wallet(
+            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318",
+            10_000_000,
+        )],
+        U256::from(MIN_BALANCE),
+    );
+
+    assert!(!pool.update_balance(0, U256::from(2_000_000)));
+    assert!(pool.update_balance(0, U256::from(900_000)));
+    assert_eq!(pool.get(0).balance, U256::from(900_000));
+    assert!(!pool.update_balance(0, U256::from(800_000)));
+
+    // Once topped up, the account is reported again when it runs low.
+    assert!(!pool.update_balance(0, U256::from(5_000_000)));
+    assert!(pool.update_balance(0, U256::from(500_000)));
+}